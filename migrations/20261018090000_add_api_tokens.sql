@@ -0,0 +1,12 @@
+-- Create a table for admin API tokens. Only the SHA-256 hash of each token
+-- is stored; the plaintext is shown once when the token is created.
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT UNIQUE NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP NULL
+);
+
+-- Create index on token hash for authentication lookups
+CREATE INDEX IF NOT EXISTS idx_api_tokens_token_hash ON api_tokens(token_hash);
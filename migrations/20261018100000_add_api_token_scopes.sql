@@ -0,0 +1,4 @@
+-- Add scopes and an optional app allowlist to API tokens. Existing tokens
+-- keep full access.
+ALTER TABLE api_tokens ADD COLUMN scopes TEXT NOT NULL DEFAULT '["admin"]';
+ALTER TABLE api_tokens ADD COLUMN apps TEXT NULL;
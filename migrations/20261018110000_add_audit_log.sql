@@ -0,0 +1,17 @@
+-- Create a table recording every mutating action, whether it came through
+-- the admin API or was initiated by the supervisor.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    app_name TEXT NULL,
+    parameters TEXT NULL,
+    source_ip TEXT NULL,
+    result TEXT NOT NULL,
+    message TEXT NULL
+);
+
+-- Create indexes for filtering by app and time
+CREATE INDEX IF NOT EXISTS idx_audit_log_app_name ON audit_log(app_name);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
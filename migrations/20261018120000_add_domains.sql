@@ -0,0 +1,12 @@
+-- Create a table mapping hostnames to apps. A hostname is either a full
+-- domain like `www.example.com` or a wildcard like `*.example.com`.
+CREATE TABLE IF NOT EXISTS domains (
+    id TEXT PRIMARY KEY NOT NULL,
+    hostname TEXT UNIQUE NOT NULL,
+    app_id TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (app_id) REFERENCES apps(id)
+);
+
+-- Create index for listing an app's domains
+CREATE INDEX IF NOT EXISTS idx_domains_app_id ON domains(app_id);
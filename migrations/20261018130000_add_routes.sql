@@ -0,0 +1,16 @@
+-- Create a table of path-based routes. Requests to `hostname` whose path
+-- starts with `path_prefix` go to the app, the longest matching prefix
+-- winning.
+CREATE TABLE IF NOT EXISTS routes (
+    id TEXT PRIMARY KEY NOT NULL,
+    hostname TEXT NOT NULL,
+    path_prefix TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    strip_prefix BOOLEAN NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE (hostname, path_prefix),
+    FOREIGN KEY (app_id) REFERENCES apps(id)
+);
+
+-- Create index for listing an app's routes
+CREATE INDEX IF NOT EXISTS idx_routes_app_id ON routes(app_id);
@@ -0,0 +1,2 @@
+-- Seconds without proxied requests before an app is put to sleep, NULL to never sleep
+ALTER TABLE apps ADD COLUMN idle_timeout INTEGER NULL;
@@ -0,0 +1,11 @@
+-- Number of processes to run for each app
+ALTER TABLE apps ADD COLUMN instances INTEGER NOT NULL DEFAULT 1;
+
+-- Ports of the instances beyond the first, which uses the app's own port
+CREATE TABLE IF NOT EXISTS instances (
+    app_id TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    port INTEGER UNIQUE NOT NULL,
+    PRIMARY KEY (app_id, idx),
+    FOREIGN KEY (app_id) REFERENCES apps(id)
+);
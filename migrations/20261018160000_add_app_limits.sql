@@ -0,0 +1,2 @@
+-- Limits the proxy enforces on requests to an app as JSON, NULL for none
+ALTER TABLE apps ADD COLUMN limits TEXT NULL;
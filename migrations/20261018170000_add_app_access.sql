@@ -0,0 +1,2 @@
+-- Basic auth users and IP allowlist the proxy checks before forwarding to an app
+ALTER TABLE apps ADD COLUMN access TEXT NULL;
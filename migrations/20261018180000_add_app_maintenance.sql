@@ -0,0 +1,2 @@
+-- Maintenance mode, during which the proxy answers for the app
+ALTER TABLE apps ADD COLUMN maintenance TEXT NULL;
@@ -0,0 +1,2 @@
+-- Whether the proxy compresses the app's responses
+ALTER TABLE apps ADD COLUMN compression BOOLEAN NOT NULL DEFAULT FALSE;
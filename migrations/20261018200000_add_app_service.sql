@@ -0,0 +1,2 @@
+-- How clients reach the app, HTTP through the proxy when NULL
+ALTER TABLE apps ADD COLUMN service TEXT NULL;
@@ -1,33 +1,349 @@
+use crate::api_types::{
+    AccessInfo, AddDomainRequest, AddRouteRequest, AppInfo, AuditLogQuery, CreateAppRequest,
+    DeployRequest, EnableMaintenanceRequest, ErrorResponse, LogsQuery, LogsResponse,
+    MaintenanceInfo, MessageResponse, RemoveRouteQuery, RoutesQuery, ScaleRequest,
+    SetAllowlistRequest, SetCompressionRequest, SetEnvRequest, SetIdleTimeoutRequest,
+    SetServiceRequest, SetUserRequest, API_PREFIX,
+};
+use crate::commands::app_command::access;
 use crate::commands::app_command::app_env;
+use crate::commands::app_command::compression;
 use crate::commands::app_command::create;
 use crate::commands::app_command::delete;
 use crate::commands::app_command::deploy;
+use crate::commands::app_command::domains;
+use crate::commands::app_command::idle_timeout;
+use crate::commands::app_command::limits;
+use crate::commands::app_command::maintenance;
 use crate::commands::app_command::restart;
+use crate::commands::app_command::routes;
+use crate::commands::app_command::scale;
+use crate::commands::app_command::service;
 use crate::commands::app_command::{start, stop};
+use crate::commands::server_command::audit;
 use crate::commands::server_command::serve::ProxyState;
+use crate::commands::server_command::token;
 use crate::db;
+use crate::models::{
+    ApiToken, App, AppError as AppModelError, AppLimits, AuditEntry, Domain, Route, TokenScope,
+};
+use crate::supervisor::SUPERVISOR;
 use axum::extract::DefaultBodyLimit;
-use axum::http::StatusCode;
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
 use axum::response::sse::{Event, Sse};
 use axum::{
-    extract::{Multipart, Path, Query, State},
-    response::IntoResponse,
-    routing::{delete, get, post},
+    extract::{ConnectInfo, Extension, Multipart, Path, Query, State},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use bytes::Bytes;
 use futures_util::stream::unfold;
-use serde::Deserialize;
-use std::collections::HashMap;
+use sqlx::{Pool, Sqlite};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader, SeekFrom};
 use tokio::sync::RwLock;
 use tracing::instrument;
+use utoipa::OpenApi;
+
+/// An error returned by an API handler, rendered as an [`ErrorResponse`]
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    fn app_not_found(name: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "app_not_found",
+            format!("App not found: {}", name),
+        )
+        .with_details(serde_json::json!({ "app": name }))
+    }
+
+    fn internal(code: &'static str, error: impl std::fmt::Display) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, error.to_string())
+    }
+
+    /// Attach the app the error is about, unless the error already names it
+    fn for_app(self, name: &str) -> Self {
+        match self.details {
+            Some(_) => self,
+            None => self.with_details(serde_json::json!({ "app": name })),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::error!("{} ({}): {}", self.status, self.code, self.message);
+        }
+
+        let body = ErrorResponse {
+            code: self.code.to_string(),
+            message: self.message,
+            details: self.details,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<db::DatabaseError> for ApiError {
+    fn from(e: db::DatabaseError) -> Self {
+        Self::internal("database_error", e)
+    }
+}
+
+impl From<start::StartError> for ApiError {
+    fn from(e: start::StartError) -> Self {
+        use start::StartError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            AppAlreadyRunning(ref name) => {
+                Self::new(StatusCode::CONFLICT, "app_already_running", e.to_string()).for_app(name)
+            }
+            AppNotDeployed(ref name) => {
+                Self::new(StatusCode::CONFLICT, "app_not_deployed", e.to_string()).for_app(name)
+            }
+            AppStartFailed(_) => Self::internal("start_failed", e),
+            AppLogBroken(_) => Self::internal("log_error", e),
+            InvalidBinaryPath(_) => Self::internal("invalid_binary_path", e),
+            DatabaseError(_) => Self::internal("database_error", e),
+            ConfigError(_) => Self::internal("config_error", e),
+        }
+    }
+}
+
+impl From<delete::DeleteError> for ApiError {
+    fn from(e: delete::DeleteError) -> Self {
+        use delete::DeleteError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            AppRunning(ref name) => {
+                Self::new(StatusCode::CONFLICT, "app_running", e.to_string()).for_app(name)
+            }
+            DirectoryError(_) => Self::internal("io_error", e),
+            DatabaseError(e) => e.into(),
+            CmdProviderError(_) => Self::internal("provider_error", e),
+        }
+    }
+}
+
+impl From<deploy::DeployError> for ApiError {
+    fn from(e: deploy::DeployError) -> Self {
+        use deploy::DeployError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            CopyError(_) | PermissionError(_) => Self::internal("io_error", e),
+            ConfigError(_) => Self::internal("config_error", e),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<create::AppCreateError> for ApiError {
+    fn from(e: create::AppCreateError) -> Self {
+        use create::AppCreateError::*;
+        match e {
+            AppAlreadyExists(ref name) => {
+                Self::new(StatusCode::CONFLICT, "app_already_exists", e.to_string()).for_app(name)
+            }
+            AppError(AppModelError::InvalidName(ref name)) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_app_name", e.to_string()).for_app(name)
+            }
+            AppError(AppModelError::InvalidPort(_)) => Self::internal("invalid_port", e),
+            DatabaseError(e) => e.into(),
+            InternalError => Self::internal("internal_error", e),
+            CmdProviderError(_) => Self::internal("provider_error", e),
+        }
+    }
+}
+
+impl From<domains::DomainError> for ApiError {
+    fn from(e: domains::DomainError) -> Self {
+        use domains::DomainError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            InvalidHostname(ref hostname) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_hostname", e.to_string())
+                    .with_details(serde_json::json!({ "hostname": hostname }))
+            }
+            DomainTaken {
+                ref hostname,
+                ref app,
+            } => Self::new(StatusCode::CONFLICT, "domain_taken", e.to_string())
+                .with_details(serde_json::json!({ "hostname": hostname, "app": app })),
+            DomainNotFound {
+                ref hostname,
+                ref app,
+            } => Self::new(StatusCode::NOT_FOUND, "domain_not_found", e.to_string())
+                .with_details(serde_json::json!({ "hostname": hostname, "app": app })),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<access::AccessError> for ApiError {
+    fn from(e: access::AccessError) -> Self {
+        use access::AccessError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            UserNotFound {
+                ref username,
+                ref app,
+            } => Self::new(StatusCode::NOT_FOUND, "user_not_found", e.to_string())
+                .with_details(serde_json::json!({ "username": username, "app": app })),
+            InvalidUsername(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_username", e.to_string())
+            }
+            EmptyPassword => Self::new(StatusCode::BAD_REQUEST, "invalid_password", e.to_string()),
+            InvalidNetwork(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_network", e.to_string())
+            }
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<maintenance::MaintenanceError> for ApiError {
+    fn from(e: maintenance::MaintenanceError) -> Self {
+        use maintenance::MaintenanceError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            PageTooLarge => Self::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "page_too_large",
+                e.to_string(),
+            ),
+            InvalidNetwork(_) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_network", e.to_string())
+            }
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<compression::CompressionError> for ApiError {
+    fn from(e: compression::CompressionError) -> Self {
+        use compression::CompressionError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<service::ServiceError> for ApiError {
+    fn from(e: service::ServiceError) -> Self {
+        use service::ServiceError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            NoFreePort(..) => Self::new(StatusCode::CONFLICT, "no_free_port", e.to_string()),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<limits::LimitsError> for ApiError {
+    fn from(e: limits::LimitsError) -> Self {
+        use limits::LimitsError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            InvalidLimit(_) => Self::new(StatusCode::BAD_REQUEST, "invalid_limit", e.to_string()),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<idle_timeout::IdleTimeoutError> for ApiError {
+    fn from(e: idle_timeout::IdleTimeoutError) -> Self {
+        use idle_timeout::IdleTimeoutError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            InvalidTimeout => Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_idle_timeout",
+                e.to_string(),
+            ),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
+
+impl From<scale::ScaleError> for ApiError {
+    fn from(e: scale::ScaleError) -> Self {
+        use scale::ScaleError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            InvalidInstances(instances) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_instances", e.to_string())
+                    .with_details(serde_json::json!({ "instances": instances }))
+            }
+            SupervisorError(_) => Self::internal("scale_failed", e),
+            DatabaseError(e) => e.into(),
+            ConfigError(_) => Self::internal("scale_failed", e),
+        }
+    }
+}
+
+impl From<routes::RouteError> for ApiError {
+    fn from(e: routes::RouteError) -> Self {
+        use routes::RouteError::*;
+        match e {
+            AppNotFound(ref name) => Self::app_not_found(name),
+            InvalidHostname(ref hostname) => {
+                Self::new(StatusCode::BAD_REQUEST, "invalid_hostname", e.to_string())
+                    .with_details(serde_json::json!({ "hostname": hostname }))
+            }
+            InvalidPathPrefix(ref path_prefix) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_path_prefix",
+                e.to_string(),
+            )
+            .with_details(serde_json::json!({ "path_prefix": path_prefix })),
+            RouteExists {
+                ref hostname,
+                ref path_prefix,
+                ref app,
+            } => Self::new(StatusCode::CONFLICT, "route_exists", e.to_string()).with_details(
+                serde_json::json!({ "hostname": hostname, "path_prefix": path_prefix, "app": app }),
+            ),
+            RouteNotFound {
+                ref hostname,
+                ref path_prefix,
+            } => Self::new(StatusCode::NOT_FOUND, "route_not_found", e.to_string()).with_details(
+                serde_json::json!({ "hostname": hostname, "path_prefix": path_prefix }),
+            ),
+            DatabaseError(e) => e.into(),
+        }
+    }
+}
 
 pub fn create_api_router(state: Arc<RwLock<ProxyState>>) -> Router {
-    Router::new()
+    let v1 = Router::new()
         .route("/apps", get(list_apps))
         .route("/apps", post(create_app))
         .route("/apps/:name", get(get_app))
@@ -38,169 +354,446 @@ pub fn create_api_router(state: Arc<RwLock<ProxyState>>) -> Router {
         .route("/apps/:name/logs", get(get_logs))
         .route("/apps/:name/deploy", post(deploy_app))
         .route("/apps/:name/env", post(set_env))
+        .route("/apps/:name/idle-timeout", put(set_idle_timeout))
+        .route("/apps/:name/limits", put(set_limits))
+        .route("/apps/:name/compression", put(set_compression))
+        .route("/apps/:name/service", put(set_service))
+        .route("/apps/:name/scale", put(scale_app))
+        .route("/apps/:name/access/users/:username", put(set_access_user))
+        .route(
+            "/apps/:name/access/users/:username",
+            delete(remove_access_user),
+        )
+        .route("/apps/:name/access/allow", put(set_allowlist))
+        .route("/apps/:name/maintenance", put(enable_maintenance))
+        .route("/apps/:name/maintenance", delete(disable_maintenance))
+        .route("/apps/:name/domains", get(list_domains))
+        .route("/apps/:name/domains", post(add_domain))
+        .route("/apps/:name/domains/:hostname", delete(remove_domain))
+        .route("/routes", get(list_routes))
+        .route("/routes", post(add_route))
+        .route("/routes", delete(remove_route))
+        .route("/audit", get(list_audit_log))
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024)) // 100MB limit
-        .with_state(state)
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        // The API description is public so clients can be built against it
+        .route("/openapi.json", get(openapi));
+
+    Router::new().nest(API_PREFIX, v1).with_state(state)
 }
 
-#[instrument(skip(state))]
-async fn list_apps(State(state): State<Arc<RwLock<ProxyState>>>) -> impl IntoResponse {
-    let pool = state.read().await.db_pool.clone();
-    match db::apps::get_all(&pool).await {
-        Ok(apps) => {
-            let app_infos = apps
-                .into_iter()
-                .map(|app| AppInfo {
-                    id: app.id.to_string(),
-                    name: app.name,
-                    state: app.state.to_string(),
-                    host: app.host,
-                    port: app.port,
-                    process_id: app.process_id,
-                })
-                .collect::<Vec<AppInfo>>();
-            Json(app_infos).into_response()
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "BinaryDrop admin API"),
+    servers((url = "/v1")),
+    paths(
+        list_apps,
+        create_app,
+        get_app,
+        delete_app,
+        start_app,
+        stop_app,
+        restart_app,
+        get_logs,
+        deploy_app,
+        set_env,
+        set_idle_timeout,
+        set_limits,
+        set_compression,
+        set_service,
+        scale_app,
+        set_access_user,
+        remove_access_user,
+        set_allowlist,
+        enable_maintenance,
+        disable_maintenance,
+        list_domains,
+        add_domain,
+        remove_domain,
+        list_routes,
+        add_route,
+        remove_route,
+        list_audit_log,
+    ),
+    components(schemas(
+        AppInfo,
+        MessageResponse,
+        LogsResponse,
+        ErrorResponse,
+        CreateAppRequest,
+        SetEnvRequest,
+        SetIdleTimeoutRequest,
+        AppLimits,
+        SetCompressionRequest,
+        SetServiceRequest,
+        crate::models::Service,
+        crate::models::ServiceType,
+        ScaleRequest,
+        AccessInfo,
+        SetUserRequest,
+        SetAllowlistRequest,
+        MaintenanceInfo,
+        EnableMaintenanceRequest,
+        DeployRequest,
+        AddDomainRequest,
+        Domain,
+        AddRouteRequest,
+        Route,
+        AuditEntry,
+        crate::models::AuditResult,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = []))
+)]
+pub struct ApiDoc;
+
+/// Registers the bearer token security scheme used by every route
+struct BearerAuth;
+
+impl utoipa::Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Marks requests that arrived over the local control socket. They are
+/// handled as [`ApiToken::local`] without needing a bearer token.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalConnection;
+
+/// Address of the client that made the request, when known
+#[derive(Debug, Clone)]
+pub struct SourceIp(pub Option<String>);
+
+/// Reject any request without a valid `Authorization: Bearer <token>` header.
+/// The authenticated token and the client address are stored in the request
+/// extensions.
+pub(crate) async fn require_token<B>(
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    if req.extensions().get::<LocalConnection>().is_some() {
+        req.extensions_mut().insert(ApiToken::local());
+        req.extensions_mut().insert(SourceIp(None));
+        return Ok(next.run(req).await);
+    }
+
+    let secret = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+
+    let secret = match secret {
+        Some(secret) if !secret.is_empty() => secret,
+        _ => {
+            return Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Missing bearer token",
+            ));
         }
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to list apps: {}", e),
-        )
-            .into_response(),
+    };
+
+    let pool = state.read().await.db_pool.clone();
+    let api_token = token::authenticate(&pool, &secret)
+        .await
+        .map_err(|e| ApiError::internal("authentication_failed", e))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Invalid bearer token",
+            )
+        })?;
+
+    tracing::debug!("Authenticated request with token '{}'", api_token.name);
+    let source_ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    req.extensions_mut().insert(api_token);
+    req.extensions_mut().insert(SourceIp(source_ip));
+    Ok(next.run(req).await)
+}
+
+/// Check that the request's token grants `scope` for `app_name`, returning a
+/// 403 describing the missing permission otherwise.
+pub(crate) fn authorize(
+    api_token: &ApiToken,
+    scope: TokenScope,
+    app_name: Option<&str>,
+) -> Result<(), ApiError> {
+    if api_token.allows(scope, app_name) {
+        return Ok(());
     }
+
+    let message = match app_name {
+        Some(name) => format!(
+            "Token '{}' does not have the '{}' scope for app '{}' (token allows: {})",
+            api_token.name,
+            scope,
+            name,
+            api_token.permissions()
+        ),
+        None => format!(
+            "Token '{}' does not have the '{}' scope for all apps (token allows: {})",
+            api_token.name,
+            scope,
+            api_token.permissions()
+        ),
+    };
+
+    Err(
+        ApiError::new(StatusCode::FORBIDDEN, "forbidden", message).with_details(
+            serde_json::json!({
+                "token": api_token.name,
+                "scope": scope,
+                "app": app_name,
+            }),
+        ),
+    )
 }
 
-#[instrument(skip(state))]
+/// Start an audit log entry for an action taken with `api_token`
+fn audit_entry(api_token: &ApiToken, source_ip: &SourceIp, action: &str) -> AuditEntry {
+    AuditEntry::new(&api_token.name, action).with_source_ip(source_ip.0.clone())
+}
+
+/// Look up an app, failing with a 404 when it does not exist
+async fn find_app(pool: &Pool<Sqlite>, name: &str) -> Result<App, ApiError> {
+    db::apps::get_by_name(pool, name)
+        .await?
+        .ok_or_else(|| ApiError::app_not_found(name))
+}
+
+#[utoipa::path(
+    get,
+    path = "/apps",
+    responses(
+        (status = 200, description = "Apps the token can read", body = [AppInfo]),
+        (status = 403, description = "Missing scope", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, state))]
+async fn list_apps(
+    Extension(api_token): Extension<ApiToken>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+) -> Result<Json<Vec<AppInfo>>, ApiError> {
+    // App-scoped tokens only see the apps they are allowed to read
+    if !api_token.is_app_scoped() {
+        authorize(&api_token, TokenScope::Read, None)?;
+    }
+
+    let pool = state.read().await.db_pool.clone();
+    let apps = db::apps::get_all(&pool).await?;
+    let app_infos = apps
+        .into_iter()
+        .filter(|app| api_token.allows(TokenScope::Read, Some(&app.name)))
+        .map(AppInfo::from)
+        .collect::<Vec<AppInfo>>();
+
+    Ok(Json(app_infos))
+}
+
+#[utoipa::path(
+    get,
+    path = "/apps/{name}",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, state))]
 async fn get_app(
+    Extension(api_token): Extension<ApiToken>,
     State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Read, Some(&name))?;
+
     let pool = state.read().await.db_pool.clone();
-    match db::apps::get_by_name(&pool, &name).await {
-        Ok(Some(app)) => {
-            let app_info = AppInfo {
-                id: app.id.to_string(),
-                name: app.name,
-                state: app.state.to_string(),
-                host: app.host,
-                port: app.port,
-                process_id: app.process_id,
-            };
-            Json(app_info).into_response()
-        }
-        Ok(None) => (
-            axum::http::StatusCode::NOT_FOUND,
-            format!("App '{}' not found", name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to get app: {}", e),
-        )
-            .into_response(),
-    }
+    Ok(Json(AppInfo::from(find_app(&pool, &name).await?)))
 }
 
-#[instrument(skip(state))]
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/start",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "App started", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "App already running or not deployed", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
 async fn start_app(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
     State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
     use crate::providers::cmd::CmdProvider;
     let pool = state.read().await.db_pool.clone();
 
-    match start::execute(&pool, &name, CmdProvider {}).await {
-        Ok(_) => (
-            axum::http::StatusCode::OK,
-            format!("App '{}' started", name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to start app: {}", e),
-        )
-            .into_response(),
+    let supervisor = SUPERVISOR.get();
+    if supervisor.is_some_and(|supervisor| supervisor.is_app_running(&name)) {
+        return Err(start::StartError::AppAlreadyRunning(name).into());
+    }
+
+    let result = start::execute(&pool, &name, CmdProvider {}).await;
+    let entry = audit_entry(&api_token, &source_ip, "start")
+        .with_app(&name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    let handle = result?;
+
+    if let Some(supervisor) = supervisor {
+        let app = find_app(&pool, &name).await?;
+        supervisor
+            .adopt_process(&app, handle)
+            .await
+            .map_err(|e| ApiError::internal("start_failed", e).for_app(&name))?;
     }
+
+    app_response(&pool, &name, StatusCode::OK).await
 }
 
-#[instrument(skip(_state))]
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/stop",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "App stopped", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
 async fn stop_app(
-    State(_state): State<Arc<RwLock<ProxyState>>>,
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
-    match stop::execute(&name).await {
-        Ok(_) => (
-            axum::http::StatusCode::OK,
-            format!("App '{}' stopped", name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to stop app: {}", e),
-        )
-            .into_response(),
-    }
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    find_app(&pool, &name).await?;
+
+    let result = stop::execute(&name).await;
+    let entry = audit_entry(&api_token, &source_ip, "stop")
+        .with_app(&name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result.map_err(|e| ApiError::internal("stop_failed", e).for_app(&name))?;
+
+    app_response(&pool, &name, StatusCode::OK).await
 }
 
-#[instrument(skip(_state))]
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/restart",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "App restarted", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "App not deployed", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
 async fn restart_app(
-    State(_state): State<Arc<RwLock<ProxyState>>>,
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
-    match restart::execute(&name).await {
-        Ok(_) => (
-            axum::http::StatusCode::OK,
-            format!("App '{}' restarted", name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to restart app: {}", e),
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    if !find_app(&pool, &name).await?.is_deployed() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "app_not_deployed",
+            format!("App not deployed: {}", name),
         )
-            .into_response(),
+        .for_app(&name));
     }
+
+    let result = restart::execute(&name).await;
+    let entry = audit_entry(&api_token, &source_ip, "restart")
+        .with_app(&name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result.map_err(|e| ApiError::internal("restart_failed", e).for_app(&name))?;
+
+    app_response(&pool, &name, StatusCode::OK).await
 }
 
-#[instrument(skip(name, _state))]
+#[utoipa::path(
+    get,
+    path = "/apps/{name}/logs",
+    params(("name" = String, Path, description = "Name of the app"), LogsQuery),
+    responses(
+        (status = 200, description = "Log lines, or a `text/event-stream` of new lines with `follow=true`", body = LogsResponse),
+        (status = 404, description = "App or log file not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, name, state))]
 async fn get_logs(
+    Extension(api_token): Extension<ApiToken>,
     Path(name): Path<String>,
-    Query(params): Query<HashMap<String, String>>,
-    State(_state): State<Arc<RwLock<ProxyState>>>,
-) -> impl IntoResponse {
-    let lines = params
-        .get("lines")
-        .and_then(|l| l.parse::<usize>().ok())
-        .unwrap_or(50);
-    let follow = params
-        .get("follow")
-        .and_then(|f| f.parse::<bool>().ok())
-        .unwrap_or(false);
-
-    let log_path = match crate::config::get_app_log_path(&name) {
-        Ok(path) => path,
-        Err(e) => {
-            return (StatusCode::NOT_FOUND, format!("Log file not found: {}", e)).into_response();
-        }
-    };
+    Query(params): Query<LogsQuery>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Read, Some(&name))?;
 
-    if !log_path.exists() {
-        return (
+    let pool = state.read().await.db_pool.clone();
+    find_app(&pool, &name).await?;
+
+    let lines = params.lines.unwrap_or(50);
+    let follow = params.follow.unwrap_or(false);
+
+    let access = params.access.unwrap_or(false);
+
+    let log_not_found = || {
+        let kind = if access { "Access log" } else { "Log file" };
+        ApiError::new(
             StatusCode::NOT_FOUND,
-            format!("Log file not found for app '{}'", name),
+            "log_not_found",
+            format!("{} not found for app '{}'", kind, name),
         )
-            .into_response();
+        .for_app(&name)
+    };
+    let log_path = if access {
+        crate::config::get_app_access_log_path(&name)
+    } else {
+        crate::config::get_app_log_path(&name)
+    }
+    .map_err(|_| log_not_found())?;
+    if !log_path.exists() {
+        return Err(log_not_found());
     }
 
     if follow {
         // Open the file and seek to the end
-        let file = match File::open(&log_path).await {
-            Ok(f) => f,
-            Err(e) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to open log file: {}", e),
-                )
-                    .into_response();
-            }
-        };
+        let file = File::open(&log_path)
+            .await
+            .map_err(|e| ApiError::internal("log_error", e).for_app(&name))?;
         let mut reader = BufReader::new(file);
         let _ = reader.seek(SeekFrom::End(0)).await;
 
@@ -225,36 +818,48 @@ async fn get_logs(
                 }
             }
         });
-        Sse::new(stream).into_response()
+        Ok(Sse::new(stream).into_response())
     } else {
         // Read last N lines from the log file
-        match tokio::fs::read(&log_path).await {
-            Ok(data) => {
-                let content = String::from_utf8_lossy(&data);
-                let lines_vec: Vec<&str> = content.lines().collect();
-                let start = if lines_vec.len() > lines {
-                    lines_vec.len() - lines
-                } else {
-                    0
-                };
-                let last_lines = lines_vec[start..].join("\n");
-                (StatusCode::OK, last_lines).into_response()
-            }
-            Err(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to read log file: {}", e),
-            )
-                .into_response(),
-        }
+        let data = tokio::fs::read(&log_path)
+            .await
+            .map_err(|e| ApiError::internal("log_error", e).for_app(&name))?;
+        let content = String::from_utf8_lossy(&data);
+        let lines_vec: Vec<&str> = content.lines().collect();
+        let start = if lines_vec.len() > lines {
+            lines_vec.len() - lines
+        } else {
+            0
+        };
+        Ok(Json(LogsResponse {
+            app: name,
+            lines: lines_vec[start..].iter().map(|l| l.to_string()).collect(),
+        })
+        .into_response())
     }
 }
 
-#[instrument(skip(state, multipart))]
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/deploy",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body(content = DeployRequest, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Binary deployed", body = AppInfo),
+        (status = 400, description = "No binary in the upload", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state, multipart))]
 async fn deploy_app(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
     State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
     mut multipart: Multipart,
-) -> impl IntoResponse {
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Deploy, Some(&name))?;
+
     let pool = state.read().await.db_pool.clone();
     // Get the binary file from the multipart form
     let mut binary_data: Option<Bytes> = None;
@@ -271,129 +876,825 @@ async fn deploy_app(
                 }
                 Err(e) => {
                     tracing::error!("Error reading binary field: {}", e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Error reading binary file",
-                    )
-                        .into_response();
+                    return Err(ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_upload",
+                        format!("Error reading binary file: {}", e),
+                    ));
                 }
             }
         }
     }
 
-    let binary_data = match binary_data {
-        Some(data) => data,
-        None => {
-            return (StatusCode::BAD_REQUEST, "No binary file provided").into_response();
-        }
-    };
+    let binary_data = binary_data.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "missing_binary",
+            "No binary file provided",
+        )
+    })?;
 
     tracing::info!("Passing binary data to deploy command");
 
     // Pass the binary data to the deploy command
-    match deploy::execute(&pool, &name, &binary_data).await {
-        Ok(_) => (
-            StatusCode::OK,
-            format!("App '{}' deployed successfully", name),
-        )
-            .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to deploy app: {}", e),
-        )
-            .into_response(),
-    }
+    let result = deploy::execute(&pool, &name, &binary_data).await;
+    let entry = audit_entry(&api_token, &source_ip, "deploy")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "size": binary_data.len() }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result?;
+
+    app_response(&pool, &name, StatusCode::OK).await
 }
 
-#[instrument(skip(state))]
+#[utoipa::path(
+    delete,
+    path = "/apps/{name}",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "App deleted", body = MessageResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "App is running", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
 async fn delete_app(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
     State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-) -> impl IntoResponse {
+) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
     let pool = state.read().await.db_pool.clone();
     use crate::providers::cmd::CmdProvider;
-    match delete::execute(&pool, &name, CmdProvider {}).await {
-        Ok(_) => (
-            axum::http::StatusCode::OK,
-            format!("App '{}' deleted", name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to delete app: {}", e),
-        )
-            .into_response(),
-    }
-}
+    let result = delete::execute(&pool, &name, CmdProvider {}).await;
+    let entry = audit_entry(&api_token, &source_ip, "delete")
+        .with_app(&name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result?;
+
+    // Free the port of a TCP service
+    let tcp_proxy = Arc::clone(&state.read().await.tcp_proxy);
+    tcp_proxy.sync().await?;
 
-#[derive(Debug, serde::Deserialize)]
-struct CreateAppRequest {
-    name: String,
+    Ok(Json(MessageResponse {
+        message: format!("App '{}' deleted", name),
+    }))
 }
 
-#[instrument(skip(state))]
+#[utoipa::path(
+    post,
+    path = "/apps",
+    request_body = CreateAppRequest,
+    responses(
+        (status = 201, description = "App created", body = AppInfo),
+        (status = 400, description = "Invalid app name", body = ErrorResponse),
+        (status = 409, description = "App already exists", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
 async fn create_app(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
     State(state): State<Arc<RwLock<ProxyState>>>,
     Json(payload): Json<CreateAppRequest>,
-) -> impl IntoResponse {
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&payload.name))?;
+
     let pool = state.read().await.db_pool.clone();
     use crate::providers::cmd::CmdProvider;
-    match create::execute(&pool, &payload.name, CmdProvider {}).await {
-        Ok(_) => (
-            axum::http::StatusCode::CREATED,
-            format!("App '{}' created", payload.name),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to create app: {}", e),
+    let result = create::execute(&pool, &payload.name, CmdProvider {}).await;
+    let entry = audit_entry(&api_token, &source_ip, "create")
+        .with_app(&payload.name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result?;
+
+    app_response(&pool, &payload.name, StatusCode::CREATED).await
+}
+
+/// Respond with the current state of an app after an action on it
+async fn app_response(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    status: StatusCode,
+) -> Result<Response, ApiError> {
+    let app = find_app(pool, name).await?;
+    Ok((status, Json(AppInfo::from(app))).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/env",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = SetEnvRequest,
+    responses(
+        (status = 200, description = "Environment updated", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+async fn set_env(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetEnvRequest>,
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Deploy, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    find_app(&pool, &name).await?;
+
+    let delete = payload.delete.unwrap_or(false);
+    let result = app_env::set_env(&name, &payload.key, &payload.value, delete).await;
+    let action = if delete { "delete_env" } else { "set_env" };
+    let entry = audit_entry(&api_token, &source_ip, action)
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "key": payload.key, "value": payload.value }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result.map_err(|e| ApiError::internal("env_update_failed", e).for_app(&name))?;
+
+    app_response(&pool, &name, StatusCode::OK).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/limits",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = AppLimits,
+    responses(
+        (status = 200, description = "Limits replaced", body = AppInfo),
+        (status = 400, description = "Invalid limit", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn set_limits(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<AppLimits>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let parameters = serde_json::json!(payload);
+    let result = limits::set(&pool, &name, payload).await;
+    let entry = audit_entry(&api_token, &source_ip, "set_limits")
+        .with_app(&name)
+        .with_parameters(parameters)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/compression",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = SetCompressionRequest,
+    responses(
+        (status = 200, description = "Compression turned on or off", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn set_compression(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetCompressionRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = compression::set(&pool, &name, payload.enabled).await;
+    let entry = audit_entry(&api_token, &source_ip, "set_compression")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "enabled": payload.enabled }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/service",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = SetServiceRequest,
+    responses(
+        (status = 200, description = "Service declared", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "No free port for TCP services", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn set_service(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetServiceRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let (pool, tcp_proxy) = {
+        let state = state.read().await;
+        (state.db_pool.clone(), Arc::clone(&state.tcp_proxy))
+    };
+    let result = service::set(
+        &pool,
+        &name,
+        payload.service,
+        tcp_proxy.host(),
+        tcp_proxy.ports(),
+    )
+    .await;
+    let entry = audit_entry(&api_token, &source_ip, "set_service")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "service": payload.service }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    let app = result?;
+
+    // Open or close the app's listener straight away
+    let failed = tcp_proxy.sync().await?;
+    if let Some((_, e)) = failed.iter().find(|(app_name, _)| *app_name == name) {
+        let port = app.service.listen_port().unwrap_or_default();
+        return Err(ApiError::internal(
+            "listen_failed",
+            format!("Failed to listen on port {}: {}", port, e),
         )
-            .into_response(),
+        .for_app(&name));
     }
+
+    Ok(Json(AppInfo::from(app)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/access/users/{username}",
+    params(
+        ("name" = String, Path, description = "Name of the app"),
+        ("username" = String, Path, description = "Basic auth username"),
+    ),
+    request_body = SetUserRequest,
+    responses(
+        (status = 200, description = "User added or password changed", body = AppInfo),
+        (status = 400, description = "Invalid username or password", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state, payload))]
+async fn set_access_user(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path((name, username)): Path<(String, String)>,
+    Json(payload): Json<SetUserRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = access::set_user(&pool, &name, &username, &payload.password).await;
+    let entry = audit_entry(&api_token, &source_ip, "set_access_user")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "username": username }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
-struct AppInfo {
-    id: String,
-    name: String,
-    state: String,
-    host: String,
-    port: Option<u16>,
-    process_id: Option<u32>,
+#[utoipa::path(
+    delete,
+    path = "/apps/{name}/access/users/{username}",
+    params(
+        ("name" = String, Path, description = "Name of the app"),
+        ("username" = String, Path, description = "Basic auth username"),
+    ),
+    responses(
+        (status = 200, description = "User removed", body = AppInfo),
+        (status = 404, description = "App or user not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn remove_access_user(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path((name, username)): Path<(String, String)>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = access::remove_user(&pool, &name, &username).await;
+    let entry = audit_entry(&api_token, &source_ip, "remove_access_user")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "username": username }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
 }
 
-#[derive(Debug, Deserialize)]
-struct SetEnvRequest {
-    key: String,
-    value: String,
-    delete: Option<bool>,
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/access/allow",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = SetAllowlistRequest,
+    responses(
+        (status = 200, description = "Allowlist replaced", body = AppInfo),
+        (status = 400, description = "Invalid address or network", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn set_allowlist(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetAllowlistRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = access::set_allowlist(&pool, &name, &payload.networks).await;
+    let entry = audit_entry(&api_token, &source_ip, "set_allowlist")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "networks": payload.networks }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
 }
 
-async fn set_env(
-    State(_state): State<Arc<RwLock<ProxyState>>>,
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/maintenance",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = EnableMaintenanceRequest,
+    responses(
+        (status = 200, description = "App in maintenance mode", body = AppInfo),
+        (status = 400, description = "Invalid address or network", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 413, description = "Maintenance page too large", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state, payload))]
+async fn enable_maintenance(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
     Path(name): Path<String>,
-    Json(payload): Json<SetEnvRequest>,
-) -> impl IntoResponse {
-    match app_env::set_env(
+    Json(payload): Json<EnableMaintenanceRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let parameters = serde_json::json!({
+        "custom_page": payload.page.is_some(),
+        "allow": payload.allow,
+        "retry_after": payload.retry_after,
+    });
+    let result = maintenance::enable(
+        &pool,
         &name,
-        &payload.key,
-        &payload.value,
-        payload.delete.unwrap_or(false),
+        payload.page,
+        &payload.allow,
+        payload.retry_after,
+    )
+    .await;
+    let entry = audit_entry(&api_token, &source_ip, "enable_maintenance")
+        .with_app(&name)
+        .with_parameters(parameters)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/apps/{name}/maintenance",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "App out of maintenance mode", body = AppInfo),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn disable_maintenance(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = maintenance::disable(&pool, &name).await;
+    let entry = audit_entry(&api_token, &source_ip, "disable_maintenance")
+        .with_app(&name)
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/idle-timeout",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = SetIdleTimeoutRequest,
+    responses(
+        (status = 200, description = "Idle timeout updated", body = AppInfo),
+        (status = 400, description = "Invalid idle timeout", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn set_idle_timeout(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<SetIdleTimeoutRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = idle_timeout::set(&pool, &name, payload.minutes).await;
+    let entry = audit_entry(&api_token, &source_ip, "set_idle_timeout")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "minutes": payload.minutes }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apps/{name}/scale",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = ScaleRequest,
+    responses(
+        (status = 200, description = "Instance count updated", body = AppInfo),
+        (status = 400, description = "Invalid instance count", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn scale_app(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<ScaleRequest>,
+) -> Result<Json<AppInfo>, ApiError> {
+    authorize(&api_token, TokenScope::Control, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = scale::execute(&pool, &name, payload.instances).await;
+    let entry = audit_entry(&api_token, &source_ip, "scale")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "instances": payload.instances }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok(Json(AppInfo::from(result?)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/apps/{name}/domains",
+    params(("name" = String, Path, description = "Name of the app")),
+    responses(
+        (status = 200, description = "Hostnames routed to the app", body = [Domain]),
+        (status = 404, description = "App not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, state))]
+async fn list_domains(
+    Extension(api_token): Extension<ApiToken>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<Domain>>, ApiError> {
+    authorize(&api_token, TokenScope::Read, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    Ok(Json(domains::list(&pool, &name).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/apps/{name}/domains",
+    params(("name" = String, Path, description = "Name of the app")),
+    request_body = AddDomainRequest,
+    responses(
+        (status = 201, description = "Hostname routed to the app", body = Domain),
+        (status = 400, description = "Invalid hostname", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "Hostname already routed to an app", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn add_domain(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path(name): Path<String>,
+    Json(payload): Json<AddDomainRequest>,
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = domains::add(&pool, &name, &payload.hostname).await;
+    let entry = audit_entry(&api_token, &source_ip, "add_domain")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "hostname": payload.hostname }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok((StatusCode::CREATED, Json(result?)).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/apps/{name}/domains/{hostname}",
+    params(
+        ("name" = String, Path, description = "Name of the app"),
+        ("hostname" = String, Path, description = "Hostname to stop routing"),
+    ),
+    responses(
+        (status = 200, description = "Hostname removed", body = MessageResponse),
+        (status = 404, description = "App or domain not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn remove_domain(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Path((name, hostname)): Path<(String, String)>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&name))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = domains::remove(&pool, &name, &hostname).await;
+    let entry = audit_entry(&api_token, &source_ip, "remove_domain")
+        .with_app(&name)
+        .with_parameters(serde_json::json!({ "hostname": hostname }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+    result?;
+
+    Ok(Json(MessageResponse {
+        message: format!("Removed {} from app '{}'", hostname, name),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/routes",
+    params(RoutesQuery),
+    responses(
+        (status = 200, description = "Routes the token can read, longest prefix first per hostname", body = [Route]),
+    )
+)]
+#[instrument(skip(api_token, state))]
+async fn list_routes(
+    Extension(api_token): Extension<ApiToken>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Query(query): Query<RoutesQuery>,
+) -> Result<Json<Vec<Route>>, ApiError> {
+    // App-scoped tokens only see routes to the apps they are allowed to read
+    if !api_token.is_app_scoped() {
+        authorize(&api_token, TokenScope::Read, None)?;
+    }
+
+    let pool = state.read().await.db_pool.clone();
+    let routes = routes::list(&pool, query.hostname.as_deref())
+        .await?
+        .into_iter()
+        .filter(|route| api_token.allows(TokenScope::Read, Some(&route.app_name)))
+        .collect();
+
+    Ok(Json(routes))
+}
+
+#[utoipa::path(
+    post,
+    path = "/routes",
+    request_body = AddRouteRequest,
+    responses(
+        (status = 201, description = "Route added", body = Route),
+        (status = 400, description = "Invalid hostname or path prefix", body = ErrorResponse),
+        (status = 404, description = "App not found", body = ErrorResponse),
+        (status = 409, description = "The hostname already has a route for the prefix", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn add_route(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Json(payload): Json<AddRouteRequest>,
+) -> Result<Response, ApiError> {
+    authorize(&api_token, TokenScope::Admin, Some(&payload.app))?;
+
+    let pool = state.read().await.db_pool.clone();
+    let result = routes::add(
+        &pool,
+        &payload.hostname,
+        &payload.path_prefix,
+        &payload.app,
+        payload.strip_prefix,
+    )
+    .await;
+    let entry = audit_entry(&api_token, &source_ip, "add_route")
+        .with_app(&payload.app)
+        .with_parameters(serde_json::json!({
+            "hostname": payload.hostname,
+            "path_prefix": payload.path_prefix,
+            "strip_prefix": payload.strip_prefix,
+        }))
+        .with_result(&result);
+    audit::record(&pool, entry).await;
+
+    Ok((StatusCode::CREATED, Json(result?)).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/routes",
+    params(RemoveRouteQuery),
+    responses(
+        (status = 200, description = "Route removed", body = MessageResponse),
+        (status = 404, description = "Route not found", body = ErrorResponse),
+    )
+)]
+#[instrument(skip(api_token, source_ip, state))]
+async fn remove_route(
+    Extension(api_token): Extension<ApiToken>,
+    Extension(source_ip): Extension<SourceIp>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Query(query): Query<RemoveRouteQuery>,
+) -> Result<Json<MessageResponse>, ApiError> {
+    let pool = state.read().await.db_pool.clone();
+
+    // Removing a route needs admin on the app it goes to
+    let path_prefix = routes::normalize_path_prefix(&query.path_prefix)?;
+    let route = routes::list(&pool, Some(&query.hostname))
+        .await?
+        .into_iter()
+        .find(|route| route.path_prefix == path_prefix);
+    authorize(
+        &api_token,
+        TokenScope::Admin,
+        route.as_ref().map(|r| r.app_name.as_str()),
+    )?;
+
+    let result = routes::remove(&pool, &query.hostname, &query.path_prefix).await;
+    let mut entry = audit_entry(&api_token, &source_ip, "remove_route")
+        .with_parameters(serde_json::json!({
+            "hostname": query.hostname,
+            "path_prefix": query.path_prefix,
+        }))
+        .with_result(&result);
+    if let Ok(route) = &result {
+        entry = entry.with_app(&route.app_name);
+    }
+    audit::record(&pool, entry).await;
+    let route = result?;
+
+    Ok(Json(MessageResponse {
+        message: format!(
+            "Removed route {}{} to app '{}'",
+            route.hostname, route.path_prefix, route.app_name
+        ),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "Audit log entries, newest first", body = [AuditEntry]),
+        (status = 403, description = "Missing admin scope", body = ErrorResponse),
     )
-    .await
-    {
-        Ok(_) => (
-            axum::http::StatusCode::OK,
-            format!(
-                "Environment variable {} set for app '{}'",
-                payload.key, name
+)]
+#[instrument(skip(api_token, state))]
+async fn list_audit_log(
+    Extension(api_token): Extension<ApiToken>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditEntry>>, ApiError> {
+    authorize(&api_token, TokenScope::Admin, query.app.as_deref())?;
+
+    let pool = state.read().await.db_pool.clone();
+    let entries = audit::list(&pool, query.app.as_deref(), query.since).await?;
+
+    Ok(Json(entries))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_status_codes() {
+        let cases: Vec<(ApiError, StatusCode, &str)> = vec![
+            (
+                start::StartError::AppNotFound("web".to_string()).into(),
+                StatusCode::NOT_FOUND,
+                "app_not_found",
             ),
-        )
-            .into_response(),
-        Err(e) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to set environment variable: {}", e),
-        )
-            .into_response(),
+            (
+                start::StartError::AppAlreadyRunning("web".to_string()).into(),
+                StatusCode::CONFLICT,
+                "app_already_running",
+            ),
+            (
+                delete::DeleteError::AppRunning("web".to_string()).into(),
+                StatusCode::CONFLICT,
+                "app_running",
+            ),
+            (
+                deploy::DeployError::CopyError("disk full".to_string()).into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "io_error",
+            ),
+            (
+                create::AppCreateError::AppError(AppModelError::InvalidName("Web".to_string()))
+                    .into(),
+                StatusCode::BAD_REQUEST,
+                "invalid_app_name",
+            ),
+            (
+                domains::DomainError::DomainTaken {
+                    hostname: "www.example.com".to_string(),
+                    app: "web".to_string(),
+                }
+                .into(),
+                StatusCode::CONFLICT,
+                "domain_taken",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!(error.status, status, "{}", error.message);
+            assert_eq!(error.code, code);
+        }
+    }
+
+    #[test]
+    fn test_error_details_name_app() {
+        let error: ApiError = start::StartError::AppNotDeployed("web".to_string()).into();
+        assert_eq!(error.details, Some(serde_json::json!({ "app": "web" })));
+        assert_eq!(error.message, "App not deployed: web");
+    }
+
+    #[test]
+    fn test_openapi_describes_routes() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/apps",
+            "/apps/{name}",
+            "/apps/{name}/start",
+            "/apps/{name}/stop",
+            "/apps/{name}/restart",
+            "/apps/{name}/logs",
+            "/apps/{name}/deploy",
+            "/apps/{name}/env",
+            "/apps/{name}/idle-timeout",
+            "/apps/{name}/limits",
+            "/apps/{name}/compression",
+            "/apps/{name}/service",
+            "/apps/{name}/scale",
+            "/apps/{name}/access/users/{username}",
+            "/apps/{name}/access/allow",
+            "/apps/{name}/maintenance",
+            "/apps/{name}/domains",
+            "/apps/{name}/domains/{hostname}",
+            "/routes",
+            "/audit",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(doc["components"]["schemas"]["ErrorResponse"].is_object());
+        assert!(doc["components"]["securitySchemes"]["bearer"].is_object());
     }
 }
@@ -1,126 +1,242 @@
-use crate::config::ClientConfig;
+use crate::api_types::{
+    AddDomainRequest, AddRouteRequest, AppInfo, AuditLogQuery, CreateAppRequest,
+    EnableMaintenanceRequest, ErrorResponse, LogsQuery, LogsResponse, MessageResponse,
+    RemoveRouteQuery, RoutesQuery, ScaleRequest, SetAllowlistRequest, SetCompressionRequest,
+    SetEnvRequest, SetIdleTimeoutRequest, SetServiceRequest, SetUserRequest, API_PREFIX,
+};
+use crate::config::{self, ClientContext};
+use crate::models::{AppLimits, AuditEntry, Domain, Route, ServiceType};
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use futures_util::stream::BoxStream;
 use futures_util::StreamExt;
-use reqwest::Client;
-use serde::Deserialize;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
 
 pub enum LogStream {
     Lines(BoxStream<'static, anyhow::Result<String>>),
-    Full(String),
+    Full(LogsResponse),
 }
 
-#[derive(Debug, Deserialize)]
-pub struct AppInfo {
-    pub id: String,
-    pub name: String,
-    pub state: String,
-    pub host: Option<String>,
-    pub port: u16,
-    pub process_id: Option<u32>,
-    pub binary_path: Option<String>,
-    pub binary_hash: Option<String>,
+/// An error response from the API
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("{}{}", .0.message, hint(.0))]
+    Unauthorized(ErrorResponse),
+    #[error("{}{}", .0.message, hint(.0))]
+    Forbidden(ErrorResponse),
+    #[error("{}{}", .0.message, hint(.0))]
+    NotFound(ErrorResponse),
+    #[error("{}{}", .0.message, hint(.0))]
+    Conflict(ErrorResponse),
+    #[error("{}{}", .0.message, hint(.0))]
+    BadRequest(ErrorResponse),
+    #[error("Server error: {}", .0.message)]
+    Server(ErrorResponse),
+    #[error("Unexpected response from server ({status}): {body}")]
+    Unexpected { status: StatusCode, body: String },
+}
+
+impl ClientError {
+    /// Build the error for a failed response from its status and body
+    fn from_response(status: StatusCode, body: &str) -> Self {
+        let error: ErrorResponse = match serde_json::from_str(body) {
+            Ok(error) => error,
+            Err(_) => {
+                return ClientError::Unexpected {
+                    status,
+                    body: body.to_string(),
+                }
+            }
+        };
+
+        match status {
+            StatusCode::UNAUTHORIZED => ClientError::Unauthorized(error),
+            StatusCode::FORBIDDEN => ClientError::Forbidden(error),
+            StatusCode::NOT_FOUND => ClientError::NotFound(error),
+            StatusCode::CONFLICT => ClientError::Conflict(error),
+            s if s.is_client_error() => ClientError::BadRequest(error),
+            _ => ClientError::Server(error),
+        }
+    }
+
+    /// The stable error code sent by the server, if any
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Unauthorized(e)
+            | ClientError::Forbidden(e)
+            | ClientError::NotFound(e)
+            | ClientError::Conflict(e)
+            | ClientError::BadRequest(e)
+            | ClientError::Server(e) => Some(&e.code),
+            ClientError::Unexpected { .. } => None,
+        }
+    }
+}
+
+/// A suggestion for what to do next, shown below the error message
+fn hint(error: &ErrorResponse) -> String {
+    let app = error
+        .details
+        .as_ref()
+        .and_then(|d| d.get("app"))
+        .and_then(|a| a.as_str())
+        .unwrap_or("<app>");
+
+    let hint = match error.code.as_str() {
+        "unauthorized" => {
+            "check the token of the current context with `bindrop context list`".to_string()
+        }
+        "forbidden" => "ask for a token with the required scope".to_string(),
+        "app_not_found" => "run `bindrop status` to list apps".to_string(),
+        "app_already_exists" => format!("run `bindrop status {}` to inspect it", app),
+        "app_already_running" => format!("use `bindrop restart {}` to restart it", app),
+        "app_not_deployed" => format!(
+            "deploy a binary first with `bindrop deploy {} <binary>`",
+            app
+        ),
+        "app_running" => format!("stop it first with `bindrop stop {}`", app),
+        "domain_taken" => format!(
+            "remove it from that app first with `bindrop domains remove {} <hostname>`",
+            app
+        ),
+        "route_exists" => {
+            "remove it first with `bindrop routes remove <hostname> <path>`".to_string()
+        }
+        "route_not_found" => "run `bindrop routes list` to list routes".to_string(),
+        "domain_not_found" => format!("run `bindrop domains list {}` to list its domains", app),
+        "invalid_idle_timeout" => {
+            "use `bindrop idle-timeout <app> --off` to keep an app running".to_string()
+        }
+        "user_not_found" => format!("run `bindrop access show {}` to list its users", app),
+        "invalid_username" => "use letters, digits, `.`, `_`, `@` and `-` in usernames".to_string(),
+        "invalid_limit" => format!(
+            "use 0 to remove a limit, e.g. `bindrop limits {} --rps 0`",
+            app
+        ),
+        "no_free_port" => "widen the `[tcp]` port range in the server config".to_string(),
+        _ => return String::new(),
+    };
+
+    format!("\nhint: {}", hint)
 }
 
 pub struct ApiClient {
-    config: ClientConfig,
+    base_url: String,
+    token: Option<String>,
     client: Client,
 }
 
 impl ApiClient {
-    pub fn new(config: ClientConfig) -> Self {
+    /// Create a client for `context`. When the context points at this machine
+    /// and the server's control socket is reachable, the socket is used
+    /// instead of HTTP so no token or DNS is needed.
+    pub fn new(context: ClientContext) -> Self {
+        if context.is_local() {
+            if let Some(socket) = config::find_control_socket() {
+                match Client::builder().unix_socket(socket.clone()).build() {
+                    Ok(client) => {
+                        tracing::debug!("Using control socket {}", socket.display());
+                        return Self {
+                            // The host is ignored when connecting over the socket
+                            base_url: "http://localhost".to_string(),
+                            token: None,
+                            client,
+                        };
+                    }
+                    Err(e) => tracing::warn!("Failed to use control socket: {}", e),
+                }
+            }
+        }
+
         Self {
+            base_url: context.url,
+            token: context.token,
             client: Client::new(),
-            config,
         }
     }
 
-    pub async fn create_app(&self, app_name: &str) -> Result<()> {
-        let response = self
+    /// Build a request against the admin API, attaching the configured token
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let builder = self
             .client
-            .post(&format!("{}/apps", self.config.base_url))
-            .json(&serde_json::json!({ "name": app_name }))
+            .request(method, format!("{}{}{}", self.base_url, API_PREFIX, path));
+
+        match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    /// Deserialize a successful JSON response, or turn an error response into
+    /// a [`ClientError`]
+    async fn parse<T: DeserializeOwned>(response: Response) -> Result<T> {
+        let response = Self::check(response).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Pass through a successful response, or turn an error response into a
+    /// [`ClientError`]
+    async fn check(response: Response) -> Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await?;
+        Err(ClientError::from_response(status, &body).into())
+    }
+
+    pub async fn create_app(&self, app_name: &str) -> Result<AppInfo> {
+        let response = self
+            .request(Method::POST, "/apps")
+            .json(&CreateAppRequest {
+                name: app_name.to_string(),
+            })
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' created successfully", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to create app: {}", error))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn start_app(&self, app_name: &str) -> Result<()> {
+    pub async fn start_app(&self, app_name: &str) -> Result<AppInfo> {
         let response = self
-            .client
-            .post(&format!("{}/apps/{}/start", self.config.base_url, app_name))
+            .request(Method::POST, &format!("/apps/{}/start", app_name))
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' started successfully", app_name);
-            Ok(())
-        } else {
-            let err = response.text().await?;
-            Err(anyhow!("Failed to start app: {}", err))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn stop_app(&self, app_name: &str) -> Result<()> {
+    pub async fn stop_app(&self, app_name: &str) -> Result<AppInfo> {
         let response = self
-            .client
-            .post(&format!("{}/apps/{}/stop", self.config.base_url, app_name))
+            .request(Method::POST, &format!("/apps/{}/stop", app_name))
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' stopped successfully", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!(error))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn restart_app(&self, app_name: &str) -> Result<()> {
+    pub async fn restart_app(&self, app_name: &str) -> Result<AppInfo> {
         let response = self
-            .client
-            .post(&format!(
-                "{}/apps/{}/restart",
-                self.config.base_url, app_name
-            ))
+            .request(Method::POST, &format!("/apps/{}/restart", app_name))
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' restarted successfully", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to restart app: {}", error))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn delete_app(&self, app_name: &str) -> Result<()> {
+    pub async fn delete_app(&self, app_name: &str) -> Result<MessageResponse> {
         let response = self
-            .client
-            .delete(&format!("{}/apps/{}", self.config.base_url, app_name))
+            .request(Method::DELETE, &format!("/apps/{}", app_name))
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' deleted successfully", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to delete app: {}", error))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn deploy_app(&self, app_name: &str, binary_path: &str) -> Result<()> {
+    pub async fn deploy_app(&self, app_name: &str, binary_path: &str) -> Result<AppInfo> {
         // Create multipart form
         let form = reqwest::multipart::Form::new()
             .file("binary", binary_path)
@@ -128,22 +244,12 @@ impl ApiClient {
             .map_err(|e| anyhow!("Failed to create multipart form: {}", e))?;
 
         let response = self
-            .client
-            .post(&format!(
-                "{}/apps/{}/deploy",
-                self.config.base_url, app_name
-            ))
+            .request(Method::POST, &format!("/apps/{}/deploy", app_name))
             .multipart(form)
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("App '{}' deployed successfully", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to deploy app: {}", error))
-        }
+        Self::parse(response).await
     }
 
     pub async fn set_env(
@@ -152,80 +258,312 @@ impl ApiClient {
         key: &str,
         value: &str,
         delete: bool,
-    ) -> Result<()> {
+    ) -> Result<AppInfo> {
         let response = self
-            .client
-            .post(&format!("{}/apps/{}/env", self.config.base_url, app_name))
-            .json(&serde_json::json!({ "key": key, "value": value, "delete": delete }))
+            .request(Method::POST, &format!("/apps/{}/env", app_name))
+            .json(&SetEnvRequest {
+                key: key.to_string(),
+                value: value.to_string(),
+                delete: Some(delete),
+            })
             .send()
             .await?;
 
-        if response.status().is_success() {
-            println!("Environment variable set for app '{}'", app_name);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to set environment variable: {}", error))
-        }
+        Self::parse(response).await
     }
 
-    pub async fn get_status(&self, app_name: Option<&str>) -> Result<()> {
-        let url = match app_name {
-            Some(name) => format!("{}/apps/{}", self.config.base_url, name),
-            None => format!("{}/apps", self.config.base_url),
-        };
+    pub async fn set_idle_timeout(&self, app_name: &str, minutes: Option<u32>) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/idle-timeout", app_name))
+            .json(&SetIdleTimeoutRequest { minutes })
+            .send()
+            .await?;
 
-        let response = self.client.get(&url).send().await?;
+        Self::parse(response).await
+    }
 
-        if response.status().is_success() {
-            let status = response.text().await?;
-            println!("Status: {}", status);
-            Ok(())
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to get status: {}", error))
-        }
+    pub async fn set_limits(&self, app_name: &str, limits: &AppLimits) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/limits", app_name))
+            .json(limits)
+            .send()
+            .await?;
+
+        Self::parse(response).await
     }
 
-    pub async fn get_logs(&self, app_name: &str, lines: usize, follow: bool) -> Result<LogStream> {
-        let url = format!(
-            "{}/apps/{}/logs?lines={}&follow={}",
-            self.config.base_url, app_name, lines, follow
-        );
-        let response = self.client.get(&url).send().await?;
-        if response.status().is_success() {
-            if follow {
-                let stream = response
-                    .bytes_stream()
-                    .map(|chunk: Result<Bytes, reqwest::Error>| {
-                        chunk
-                            .map_err(|e| anyhow::anyhow!(e))
-                            .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
-                    })
-                    .boxed();
-                Ok(LogStream::Lines(stream))
-            } else {
-                Ok(LogStream::Full(response.text().await?))
-            }
-        } else {
-            Err(anyhow::anyhow!(
-                "Failed to fetch logs: {}",
-                response.status()
-            ))
+    pub async fn set_access_user(
+        &self,
+        app_name: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<AppInfo> {
+        let response = self
+            .request(
+                Method::PUT,
+                &format!("/apps/{}/access/users/{}", app_name, username),
+            )
+            .json(&SetUserRequest {
+                password: password.to_string(),
+            })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn remove_access_user(&self, app_name: &str, username: &str) -> Result<AppInfo> {
+        let response = self
+            .request(
+                Method::DELETE,
+                &format!("/apps/{}/access/users/{}", app_name, username),
+            )
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn set_allowlist(&self, app_name: &str, networks: Vec<String>) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/access/allow", app_name))
+            .json(&SetAllowlistRequest { networks })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn enable_maintenance(
+        &self,
+        app_name: &str,
+        request: &EnableMaintenanceRequest,
+    ) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/maintenance", app_name))
+            .json(request)
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn disable_maintenance(&self, app_name: &str) -> Result<AppInfo> {
+        let response = self
+            .request(Method::DELETE, &format!("/apps/{}/maintenance", app_name))
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn set_compression(&self, app_name: &str, enabled: bool) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/compression", app_name))
+            .json(&SetCompressionRequest { enabled })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn set_service(&self, app_name: &str, service: ServiceType) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/service", app_name))
+            .json(&SetServiceRequest { service })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn scale_app(&self, app_name: &str, instances: u32) -> Result<AppInfo> {
+        let response = self
+            .request(Method::PUT, &format!("/apps/{}/scale", app_name))
+            .json(&ScaleRequest { instances })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn list_apps(&self) -> Result<Vec<AppInfo>> {
+        let response = self.request(Method::GET, "/apps").send().await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn get_logs(
+        &self,
+        app_name: &str,
+        lines: usize,
+        follow: bool,
+        access: bool,
+    ) -> Result<LogStream> {
+        let query = LogsQuery {
+            lines: Some(lines),
+            follow: Some(follow),
+            access: Some(access),
+        };
+        let response = self
+            .request(Method::GET, &format!("/apps/{}/logs", app_name))
+            .query(&query)
+            .send()
+            .await?;
+        if !follow {
+            return Ok(LogStream::Full(Self::parse(response).await?));
         }
+
+        let response = Self::check(response).await?;
+
+        // Reassemble server-sent events into log lines
+        let stream = response
+            .bytes_stream()
+            .scan(
+                String::new(),
+                |buffer, chunk: Result<Bytes, reqwest::Error>| {
+                    let lines = match chunk {
+                        Ok(bytes) => {
+                            buffer.push_str(&String::from_utf8_lossy(&bytes));
+                            let mut lines = Vec::new();
+                            while let Some(end) = buffer.find("\n\n") {
+                                let event: String = buffer.drain(..end + 2).collect();
+                                let data = event
+                                    .lines()
+                                    .filter_map(|l| l.strip_prefix("data:"))
+                                    .map(|l| l.strip_prefix(' ').unwrap_or(l))
+                                    .collect::<Vec<_>>()
+                                    .join("\n");
+                                lines.push(Ok(data));
+                            }
+                            lines
+                        }
+                        Err(e) => vec![Err(anyhow::anyhow!(e))],
+                    };
+                    futures_util::future::ready(Some(futures_util::stream::iter(lines)))
+                },
+            )
+            .flatten()
+            .boxed();
+
+        Ok(LogStream::Lines(stream))
     }
 
     pub async fn get_app_info(&self, app_name: &str) -> Result<AppInfo> {
-        let url = format!("{}/apps/{}", self.config.base_url, app_name);
-        let response = self.client.get(&url).send().await?;
-        if response.status().is_success() {
-            let app_info: AppInfo = response.json().await?;
-            Ok(app_info)
-        } else {
-            let error = response.text().await?;
-            Err(anyhow!("Failed to get app info: {}", error))
-        }
+        let path = format!("/apps/{}", app_name);
+        let response = self.request(Method::GET, &path).send().await?;
+
+        Self::parse(response).await
     }
 
-    // Add other methods (start_app, stop_app, etc.) similarly
+    pub async fn list_domains(&self, app_name: &str) -> Result<Vec<Domain>> {
+        let response = self
+            .request(Method::GET, &format!("/apps/{}/domains", app_name))
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn add_domain(&self, app_name: &str, hostname: &str) -> Result<Domain> {
+        let response = self
+            .request(Method::POST, &format!("/apps/{}/domains", app_name))
+            .json(&AddDomainRequest {
+                hostname: hostname.to_string(),
+            })
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn remove_domain(&self, app_name: &str, hostname: &str) -> Result<MessageResponse> {
+        let response = self
+            .request(
+                Method::DELETE,
+                &format!("/apps/{}/domains/{}", app_name, hostname),
+            )
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn list_routes(&self, hostname: Option<&str>) -> Result<Vec<Route>> {
+        let query = RoutesQuery {
+            hostname: hostname.map(str::to_string),
+        };
+        let response = self
+            .request(Method::GET, "/routes")
+            .query(&query)
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn add_route(&self, request: &AddRouteRequest) -> Result<Route> {
+        let response = self
+            .request(Method::POST, "/routes")
+            .json(request)
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn remove_route(&self, hostname: &str, path_prefix: &str) -> Result<MessageResponse> {
+        let query = RemoveRouteQuery {
+            hostname: hostname.to_string(),
+            path_prefix: path_prefix.to_string(),
+        };
+        let response = self
+            .request(Method::DELETE, "/routes")
+            .query(&query)
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+
+    pub async fn get_audit_log(
+        &self,
+        app_name: Option<&str>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<AuditEntry>> {
+        let query = AuditLogQuery {
+            app: app_name.map(str::to_string),
+            since,
+        };
+
+        let response = self
+            .request(Method::GET, "/audit")
+            .query(&query)
+            .send()
+            .await?;
+
+        Self::parse(response).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_error_from_response() {
+        let body =
+            r#"{"code":"app_running","message":"App is running: web","details":{"app":"web"}}"#;
+        let error = ClientError::from_response(StatusCode::CONFLICT, body);
+        assert!(matches!(error, ClientError::Conflict(_)));
+        assert_eq!(error.code(), Some("app_running"));
+        assert_eq!(
+            error.to_string(),
+            "App is running: web\nhint: stop it first with `bindrop stop web`"
+        );
+
+        let error = ClientError::from_response(StatusCode::BAD_GATEWAY, "upstream down");
+        assert!(matches!(error, ClientError::Unexpected { .. }));
+        assert_eq!(error.code(), None);
+    }
 }
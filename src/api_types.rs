@@ -0,0 +1,261 @@
+//! Request and response types of the admin API, shared by the server
+//! (`api.rs`) and the client (`api_client.rs`)
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::models::{App, AppAccess, AppLimits, Maintenance, Service, ServiceType};
+
+/// Prefix of every route in the current API version
+pub const API_PREFIX: &str = "/v1";
+
+/// An app and its current state
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AppInfo {
+    pub id: String,
+    pub name: String,
+    /// Lifecycle state, e.g. `created`, `deployed`, `running` or `crashed`
+    pub state: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub process_id: Option<u32>,
+    pub binary_path: Option<String>,
+    /// SHA-256 of the deployed binary
+    pub binary_hash: Option<String>,
+    pub restart_count: u32,
+    /// Minutes without requests before the app is put to sleep
+    pub idle_timeout_minutes: Option<u32>,
+    /// Number of processes run for the app
+    pub instances: u32,
+    /// Limits the proxy enforces on requests to the app
+    pub limits: AppLimits,
+    /// Who the proxy lets through to the app
+    pub access: AccessInfo,
+    /// Set while the proxy serves a maintenance page for the app
+    pub maintenance: Option<MaintenanceInfo>,
+    /// Whether the proxy compresses the app's responses
+    pub compression: bool,
+    /// HTTP through the proxy, or raw TCP on a port of its own
+    pub service: Service,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<App> for AppInfo {
+    fn from(app: App) -> Self {
+        Self {
+            id: app.id,
+            name: app.name,
+            state: app.state.to_string(),
+            host: app.host,
+            port: app.port,
+            process_id: app.process_id,
+            binary_path: app.binary_path,
+            binary_hash: app.binary_hash,
+            restart_count: app.restart_count,
+            idle_timeout_minutes: app.idle_timeout.map(|secs| secs / 60),
+            instances: app.instances,
+            limits: app.limits,
+            access: AccessInfo::from(&app.access),
+            maintenance: app.maintenance.as_ref().map(MaintenanceInfo::from),
+            compression: app.compression,
+            service: app.service,
+            created_at: app.created_at,
+            updated_at: app.updated_at,
+        }
+    }
+}
+
+/// Access rules of an app, without the password hashes
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct AccessInfo {
+    /// Users who must sign in with HTTP basic auth
+    pub users: Vec<String>,
+    /// Addresses and CIDR networks requests must come from
+    pub allow: Vec<String>,
+}
+
+impl From<&AppAccess> for AccessInfo {
+    fn from(access: &AppAccess) -> Self {
+        Self {
+            users: access.users.iter().map(|u| u.username.clone()).collect(),
+            allow: access.allow.clone(),
+        }
+    }
+}
+
+/// Maintenance mode of an app, without the page itself
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceInfo {
+    pub since: DateTime<Utc>,
+    /// Whether the app has a page of its own instead of the server's
+    pub custom_page: bool,
+    /// Addresses and CIDR networks still let through to the app
+    pub allow: Vec<String>,
+    /// Seconds clients are told to wait before trying again
+    pub retry_after: u32,
+}
+
+impl From<&Maintenance> for MaintenanceInfo {
+    fn from(maintenance: &Maintenance) -> Self {
+        Self {
+            since: maintenance.since,
+            custom_page: maintenance.page.is_some(),
+            allow: maintenance.allow.clone(),
+            retry_after: maintenance.retry_after,
+        }
+    }
+}
+
+/// Result of an action that has no other data to return
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+/// The last lines of an app's log
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LogsResponse {
+    pub app: String,
+    pub lines: Vec<String>,
+}
+
+/// Body of every error response from the API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Stable, machine-readable error code such as `app_not_found`
+    pub code: String,
+    /// Human-readable description of the error
+    pub message: String,
+    /// Extra context, such as the app the error is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateAppRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddDomainRequest {
+    /// Full hostname like `www.example.com`, or a wildcard like `*.example.com`
+    pub hostname: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AddRouteRequest {
+    pub hostname: String,
+    /// Path prefix such as `/api`, matched on whole path segments
+    pub path_prefix: String,
+    /// App requests under the prefix go to
+    pub app: String,
+    /// Remove the prefix from the path before forwarding to the app
+    #[serde(default)]
+    pub strip_prefix: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetEnvRequest {
+    pub key: String,
+    pub value: String,
+    /// Remove the variable instead of setting it
+    #[serde(default)]
+    pub delete: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetIdleTimeoutRequest {
+    /// Minutes without proxied requests before the app is stopped until the
+    /// next request wakes it. `null` keeps it running.
+    pub minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetUserRequest {
+    /// Password, hashed by the server before it is stored
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetAllowlistRequest {
+    /// Addresses and CIDR networks like `10.0.0.0/8`. Empty allows everyone.
+    pub networks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct EnableMaintenanceRequest {
+    /// HTML page to serve instead of the server's 503 error page. It may
+    /// use the placeholders `{{status}}`, `{{reason}}` and `{{message}}`.
+    #[serde(default)]
+    pub page: Option<String>,
+    /// Addresses and CIDR networks still let through to the app
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Seconds clients are told to wait before trying again, 300 by default
+    #[serde(default)]
+    pub retry_after: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetCompressionRequest {
+    /// Compress responses with gzip, brotli or zstd for clients that accept
+    /// them
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetServiceRequest {
+    /// `tcp` to give the app a public port of its own, `http` to serve it
+    /// through the proxy again
+    pub service: ServiceType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScaleRequest {
+    /// Number of processes to run, each on a port of its own
+    pub instances: u32,
+}
+
+/// Upload of a new binary, sent as `multipart/form-data`
+#[derive(Debug, ToSchema)]
+pub struct DeployRequest {
+    #[schema(value_type = String, format = Binary)]
+    pub binary: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LogsQuery {
+    /// Number of lines to return (default 50)
+    pub lines: Option<usize>,
+    /// Stream new lines as server-sent events instead of returning them
+    pub follow: Option<bool>,
+    /// Read the proxy's access log of the app instead of its output
+    pub access: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    /// Only entries for this app
+    pub app: Option<String>,
+    /// Only entries at or after this time (RFC 3339)
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RoutesQuery {
+    /// Only routes of this hostname
+    pub hostname: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RemoveRouteQuery {
+    pub hostname: String,
+    pub path_prefix: String,
+}
@@ -1,13 +1,27 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
 use clap::{Parser, Subcommand};
 
-use crate::api_client::ApiClient;
-use crate::commands::server_command::serve;
-use crate::config::{ClientConfig, ServerConfig};
+use crate::api_client::{ApiClient, LogStream};
+use crate::api_types::{AddRouteRequest, EnableMaintenanceRequest};
+use crate::commands::server_command::{audit, forwarding, serve, token};
+use crate::config::{ClientConfig, ClientContext, ServerConfig};
+use crate::models::{
+    AppLimits, AuditEntry, BasicAuthUser, Service, ServiceType, TokenScope, LOCAL_ACTOR,
+};
+use crate::output::{print_list, print_message, print_one, OutputFormat, Render};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Client context (server) to use instead of the current one
+    #[arg(long, global = true, env = "BINDROP_CONTEXT")]
+    context: Option<String>,
+
+    /// Output format
+    #[arg(long, short, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -28,11 +42,11 @@ enum Commands {
 
     /// Deploy a binary to an app
     Deploy {
-        /// Name of the app
+        /// Name of the app (may be omitted when the context has a default app)
         app_name: String,
 
         /// Path to the binary file
-        binary_path: String,
+        binary_path: Option<String>,
     },
 
     /// Deploy a binary to an app
@@ -52,20 +66,72 @@ enum Commands {
 
     /// Start an app
     Start {
-        /// Name of the app
-        app_name: String,
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
     },
 
     /// Stop an app
     Stop {
-        /// Name of the app
-        app_name: String,
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
     },
 
     /// Restart an app
     Restart {
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
+    },
+
+    /// Put an app to sleep after a number of idle minutes. A sleeping app
+    /// is started again by the next request to it.
+    IdleTimeout {
+        /// Name of the app
+        app_name: String,
+
+        /// Minutes without proxied requests before the app is stopped
+        #[arg(required_unless_present = "off")]
+        minutes: Option<u32>,
+
+        /// Keep the app running however long it is idle
+        #[arg(long, conflicts_with = "minutes")]
+        off: bool,
+    },
+
+    /// Set how many processes of an app to run. The proxy spreads requests
+    /// over them, and restarts replace them one at a time.
+    Scale {
         /// Name of the app
         app_name: String,
+
+        /// Number of instances
+        instances: u32,
+    },
+
+    /// Limit the requests the proxy forwards to an app. Without options,
+    /// show its current limits. A limit of 0 removes it.
+    Limits {
+        /// Name of the app
+        app_name: String,
+
+        /// Requests per second allowed from each client IP
+        #[arg(long)]
+        rps: Option<u32>,
+
+        /// Requests a client may make at once above --rps
+        #[arg(long)]
+        burst: Option<u32>,
+
+        /// Requests forwarded to the app at the same time
+        #[arg(long)]
+        max_concurrent: Option<u32>,
+
+        /// Largest request body accepted, e.g. 512K or 10M
+        #[arg(long, value_parser = parse_size)]
+        max_body: Option<u64>,
+
+        /// Remove all limits before applying any others given
+        #[arg(long)]
+        clear: bool,
     },
 
     /// Show app status
@@ -76,8 +142,8 @@ enum Commands {
 
     /// View app logs
     Logs {
-        /// Name of the app
-        app_name: String,
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
 
         /// Number of lines to show
         #[arg(short, long, default_value = "50")]
@@ -86,49 +152,514 @@ enum Commands {
         /// Follow logs in real time
         #[arg(short, long)]
         follow: bool,
+
+        /// Show the requests the proxy forwarded to the app instead of its
+        /// output
+        #[arg(long)]
+        access: bool,
+    },
+
+    /// Serve a maintenance page in place of an app, leaving its process
+    /// running
+    Maintenance {
+        #[command(subcommand)]
+        command: MaintenanceCommands,
+    },
+
+    /// Have the proxy compress an app's responses for clients that accept
+    /// gzip, brotli or zstd
+    Compression {
+        #[command(subcommand)]
+        command: CompressionCommands,
+    },
+
+    /// Serve an app over raw TCP on a port of its own, for apps that do not
+    /// speak HTTP such as databases
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommands,
+    },
+
+    /// Protect an app with basic auth or an IP allowlist in the proxy
+    Access {
+        #[command(subcommand)]
+        command: AccessCommands,
+    },
+
+    /// Manage the hostnames routed to an app
+    Domains {
+        #[command(subcommand)]
+        command: DomainCommands,
+    },
+
+    /// Manage path-based routes that mount apps under a hostname
+    Routes {
+        #[command(subcommand)]
+        command: RouteCommands,
+    },
+
+    /// Show the audit log of actions taken on the server
+    Audit {
+        /// Only show entries for this app
+        #[arg(long)]
+        app: Option<String>,
+
+        /// Only show entries newer than this, e.g. `2h`, `7d` or an RFC 3339 timestamp
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
     },
 
     /// Start the BinaryDrop server
     Serve,
 
+    /// Manage the BinaryDrop server (run on the server itself)
+    Server {
+        #[command(subcommand)]
+        command: ServerCommands,
+    },
+
+    /// Manage the servers this client can talk to
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+
     Config,
 }
 
+#[derive(Subcommand)]
+enum ContextCommands {
+    /// Add or update a context
+    Add {
+        /// Name of the context, e.g. staging or production
+        name: String,
+
+        /// Admin API URL of the server
+        #[arg(long)]
+        url: String,
+
+        /// API token for the server
+        #[arg(long)]
+        token: Option<String>,
+
+        /// App used when a command is run without an app name
+        #[arg(long)]
+        default_app: Option<String>,
+    },
+
+    /// Switch the current context
+    Use {
+        /// Name of the context
+        name: String,
+    },
+
+    /// List contexts
+    List,
+
+    /// Remove a context
+    Remove {
+        /// Name of the context
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommands {
+    /// Answer requests to an app with a 503 maintenance page
+    On {
+        /// Name of the app
+        app_name: String,
+
+        /// HTML page to serve instead of the server's error page
+        #[arg(long)]
+        page: Option<String>,
+
+        /// Address or CIDR network still let through to the app (repeatable)
+        #[arg(long)]
+        allow: Vec<String>,
+
+        /// Seconds clients are told to wait before trying again
+        #[arg(long)]
+        retry_after: Option<u32>,
+    },
+
+    /// Send requests to an app again
+    Off {
+        /// Name of the app
+        app_name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CompressionCommands {
+    /// Compress text responses of an app above the server's size threshold
+    On {
+        /// Name of the app
+        app_name: String,
+    },
+
+    /// Pass an app's responses through as they are
+    Off {
+        /// Name of the app
+        app_name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceCommands {
+    /// Give an app a public TCP port, passed through to its `PORT` as is
+    Tcp {
+        /// Name of the app
+        app_name: String,
+    },
+
+    /// Serve an app through the HTTP proxy again, freeing its TCP port
+    Http {
+        /// Name of the app
+        app_name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccessCommands {
+    /// Show the users and networks allowed through to an app
+    Show {
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
+    },
+
+    /// Require basic auth for an app and add a user, or change a user's
+    /// password
+    AddUser {
+        /// Name of the app
+        app_name: String,
+
+        /// Username to sign in with
+        username: String,
+
+        /// Password, read from standard input when not given
+        #[arg(long)]
+        password: Option<String>,
+    },
+
+    /// Remove a basic auth user. Without users left, no sign in is asked for.
+    RemoveUser {
+        /// Name of the app
+        app_name: String,
+
+        /// Username to remove
+        username: String,
+    },
+
+    /// Only accept requests to an app from these addresses or networks
+    Allow {
+        /// Name of the app
+        app_name: String,
+
+        /// Address or CIDR network, e.g. 10.0.0.0/8
+        #[arg(required = true)]
+        networks: Vec<String>,
+    },
+
+    /// Remove addresses or networks from an app's allowlist. Without any
+    /// left, requests are accepted from anywhere.
+    Disallow {
+        /// Name of the app
+        app_name: String,
+
+        /// Address or CIDR network, e.g. 10.0.0.0/8
+        #[arg(required_unless_present = "all")]
+        networks: Vec<String>,
+
+        /// Remove the whole allowlist
+        #[arg(long, conflicts_with = "networks")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DomainCommands {
+    /// Route a hostname, or a wildcard like `*.example.com`, to an app
+    Add {
+        /// Name of the app
+        app_name: String,
+
+        /// Hostname, e.g. www.example.com
+        hostname: String,
+    },
+
+    /// Stop routing a hostname to an app
+    Remove {
+        /// Name of the app
+        app_name: String,
+
+        /// Hostname, e.g. www.example.com
+        hostname: String,
+    },
+
+    /// List the hostnames routed to an app
+    List {
+        /// Name of the app (defaults to the context's default app)
+        app_name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RouteCommands {
+    /// Send requests for a hostname under a path prefix to an app. The
+    /// longest matching prefix wins.
+    Add {
+        /// Hostname, e.g. example.com
+        hostname: String,
+
+        /// Path prefix, e.g. /api
+        path_prefix: String,
+
+        /// Name of the app
+        app_name: String,
+
+        /// Remove the prefix from the path before forwarding to the app
+        #[arg(long)]
+        strip_prefix: bool,
+    },
+
+    /// Remove a route
+    Remove {
+        /// Hostname, e.g. example.com
+        hostname: String,
+
+        /// Path prefix, e.g. /api
+        path_prefix: String,
+    },
+
+    /// List routes
+    List {
+        /// Only routes of this hostname
+        hostname: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServerCommands {
+    /// Manage admin API tokens
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommands {
+    /// Create a new API token and print it once
+    Create {
+        /// Name to identify the token, e.g. the machine it is used from
+        #[arg(long)]
+        name: String,
+
+        /// Scope to grant: read, deploy, control or admin (repeatable, defaults to admin)
+        #[arg(long = "scope")]
+        scopes: Vec<TokenScope>,
+
+        /// Limit the token to an app (repeatable, defaults to all apps)
+        #[arg(long = "app")]
+        apps: Vec<String>,
+    },
+
+    /// List API tokens
+    List,
+
+    /// Revoke an API token
+    Revoke {
+        /// Name of the token
+        name: String,
+    },
+}
+
 #[tracing::instrument]
 pub async fn run() -> Result<()> {
     let cli = Cli::parse();
+    let format = cli.output;
     let config = ClientConfig::load()?;
-    let api_client = ApiClient::new(config);
+    let context = || config.resolve_context(cli.context.as_deref());
+    let api_client = || context().map(ApiClient::new);
 
     match cli.command {
-        Commands::Create { app_name } => api_client.create_app(&app_name).await,
-        Commands::Start { app_name } => api_client.start_app(&app_name).await,
-        Commands::Stop { app_name } => api_client.stop_app(&app_name).await,
-        Commands::Restart { app_name } => api_client.restart_app(&app_name).await,
-        Commands::Delete { app_name } => api_client.delete_app(&app_name).await,
+        Commands::Create { app_name } => {
+            let app = api_client()?.create_app(&app_name).await?;
+            print_message(
+                &format!("App '{}' created successfully", app.name),
+                &app,
+                format,
+            )
+        }
+        Commands::Start { app_name } => {
+            let app_name = app_or_default(app_name, &context()?)?;
+            let app = api_client()?.start_app(&app_name).await?;
+            print_message(
+                &format!("App '{}' started successfully", app.name),
+                &app,
+                format,
+            )
+        }
+        Commands::Stop { app_name } => {
+            let app_name = app_or_default(app_name, &context()?)?;
+            let app = api_client()?.stop_app(&app_name).await?;
+            print_message(
+                &format!("App '{}' stopped successfully", app.name),
+                &app,
+                format,
+            )
+        }
+        Commands::Restart { app_name } => {
+            let app_name = app_or_default(app_name, &context()?)?;
+            let app = api_client()?.restart_app(&app_name).await?;
+            print_message(
+                &format!("App '{}' restarted successfully", app.name),
+                &app,
+                format,
+            )
+        }
+        Commands::Delete { app_name } => {
+            let message = api_client()?.delete_app(&app_name).await?;
+            print_message(&message.message, &message, format)
+        }
         Commands::Deploy {
             app_name,
             binary_path,
-        } => api_client.deploy_app(&app_name, &binary_path).await,
+        } => {
+            // With a single argument it is the binary and the app comes from the context
+            let (app_name, binary_path) = match binary_path {
+                Some(binary_path) => (app_name, binary_path),
+                None => (app_or_default(None, &context()?)?, app_name),
+            };
+            let app = api_client()?.deploy_app(&app_name, &binary_path).await?;
+            print_message(
+                &format!("App '{}' deployed successfully", app.name),
+                &app,
+                format,
+            )
+        }
         Commands::Env {
             app_name,
             key,
             value,
             delete,
-        } => api_client.set_env(&app_name, &key, &value, delete).await,
-        Commands::Status { app_name } => api_client.get_status(app_name.as_deref()).await,
+        } => {
+            let app = api_client()?
+                .set_env(&app_name, &key, &value, delete)
+                .await?;
+            let message = if delete {
+                format!(
+                    "Deleted environment variable {} from app '{}'",
+                    key, app.name
+                )
+            } else {
+                format!("Set environment variable {} on app '{}'", key, app.name)
+            };
+            print_message(&message, &app, format)
+        }
+        Commands::IdleTimeout {
+            app_name,
+            minutes,
+            off: _,
+        } => {
+            let app = api_client()?.set_idle_timeout(&app_name, minutes).await?;
+            let message = match minutes {
+                Some(minutes) => format!(
+                    "App '{}' will sleep after {} minute(s) without requests",
+                    app.name, minutes
+                ),
+                None => format!("App '{}' will no longer sleep when idle", app.name),
+            };
+            print_message(&message, &app, format)
+        }
+        Commands::Scale {
+            app_name,
+            instances,
+        } => {
+            let app = api_client()?.scale_app(&app_name, instances).await?;
+            print_message(
+                &format!("App '{}' scaled to {} instance(s)", app.name, app.instances),
+                &app,
+                format,
+            )
+        }
+        Commands::Limits {
+            app_name,
+            rps,
+            burst,
+            max_concurrent,
+            max_body,
+            clear,
+        } => {
+            let client = api_client()?;
+            let mut limits = client.get_app_info(&app_name).await?.limits;
+            let unchanged =
+                rps.is_none() && burst.is_none() && max_concurrent.is_none() && max_body.is_none();
+            if unchanged && !clear {
+                return print_one(&limits, format);
+            }
+
+            if clear {
+                limits = AppLimits::default();
+            }
+            let nonzero = |value: u32| (value > 0).then_some(value);
+            if let Some(rps) = rps {
+                limits.requests_per_second = nonzero(rps);
+            }
+            if let Some(burst) = burst {
+                limits.burst = nonzero(burst);
+            }
+            if let Some(max_concurrent) = max_concurrent {
+                limits.max_concurrent = nonzero(max_concurrent);
+            }
+            if let Some(max_body) = max_body {
+                limits.max_body_bytes = (max_body > 0).then_some(max_body);
+            }
+            // A burst only applies on top of a rate
+            if limits.requests_per_second.is_none() {
+                limits.burst = None;
+            }
+
+            let app = client.set_limits(&app_name, &limits).await?;
+            print_message(
+                &format!("Updated limits of app '{}'", app.name),
+                &app,
+                format,
+            )
+        }
+        Commands::Status { app_name } => match app_name {
+            Some(app_name) => print_one(&api_client()?.get_app_info(&app_name).await?, format),
+            None => print_list(&api_client()?.list_apps().await?, format, "No apps found"),
+        },
         Commands::Logs {
             app_name,
             lines,
             follow,
+            access,
         } => {
-            match api_client.get_logs(&app_name, lines, follow).await? {
-                crate::api_client::LogStream::Full(logs) => println!("{}", logs),
-                crate::api_client::LogStream::Lines(mut stream) => {
+            let app_name = app_or_default(app_name, &context()?)?;
+            match api_client()?
+                .get_logs(&app_name, lines, follow, access)
+                .await?
+            {
+                LogStream::Full(logs) if format == OutputFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&logs)?)
+                }
+                LogStream::Full(logs) => {
+                    for line in logs.lines {
+                        println!("{}", line);
+                    }
+                }
+                LogStream::Lines(mut stream) => {
                     use futures_util::StreamExt;
                     while let Some(line) = stream.next().await {
                         match line {
-                            Ok(l) => print!("{}", l),
+                            // One JSON string per line so the stream can be piped into jq
+                            Ok(l) if format == OutputFormat::Json => {
+                                println!("{}", serde_json::to_string(&l)?)
+                            }
+                            Ok(l) => println!("{}", l),
                             Err(e) => eprintln!("Error: {}", e),
                         }
                     }
@@ -136,16 +667,234 @@ pub async fn run() -> Result<()> {
             }
             Ok(())
         }
+        Commands::Maintenance { command } => match command {
+            MaintenanceCommands::On {
+                app_name,
+                page,
+                allow,
+                retry_after,
+            } => {
+                let page = match page {
+                    Some(path) => Some(
+                        std::fs::read_to_string(&path)
+                            .map_err(|e| anyhow!("Failed to read {}: {}", path, e))?,
+                    ),
+                    None => None,
+                };
+                let app = api_client()?
+                    .enable_maintenance(
+                        &app_name,
+                        &EnableMaintenanceRequest {
+                            page,
+                            allow,
+                            retry_after,
+                        },
+                    )
+                    .await?;
+                print_message(
+                    &format!("App '{}' is in maintenance mode", app.name),
+                    &app,
+                    format,
+                )
+            }
+            MaintenanceCommands::Off { app_name } => {
+                let app = api_client()?.disable_maintenance(&app_name).await?;
+                print_message(
+                    &format!("App '{}' is out of maintenance mode", app.name),
+                    &app,
+                    format,
+                )
+            }
+        },
+        Commands::Compression { command } => {
+            let (app_name, enabled) = match command {
+                CompressionCommands::On { app_name } => (app_name, true),
+                CompressionCommands::Off { app_name } => (app_name, false),
+            };
+            let app = api_client()?.set_compression(&app_name, enabled).await?;
+            let message = match enabled {
+                true => format!("Responses of app '{}' will be compressed", app.name),
+                false => format!("Responses of app '{}' will not be compressed", app.name),
+            };
+            print_message(&message, &app, format)
+        }
+        Commands::Service { command } => {
+            let (app_name, service) = match command {
+                ServiceCommands::Tcp { app_name } => (app_name, ServiceType::Tcp),
+                ServiceCommands::Http { app_name } => (app_name, ServiceType::Http),
+            };
+            let app = api_client()?.set_service(&app_name, service).await?;
+            let message = match app.service {
+                Service::Tcp { listen_port } => format!(
+                    "App '{}' accepts TCP connections on port {}",
+                    app.name, listen_port
+                ),
+                Service::Http => format!("App '{}' is served over HTTP", app.name),
+            };
+            print_message(&message, &app, format)
+        }
+        Commands::Access { command } => match command {
+            AccessCommands::Show { app_name } => {
+                let app_name = app_or_default(app_name, &context()?)?;
+                print_one(&api_client()?.get_app_info(&app_name).await?.access, format)
+            }
+            AccessCommands::AddUser {
+                app_name,
+                username,
+                password,
+            } => {
+                // The username is part of the URL, so it is checked up front
+                if !BasicAuthUser::is_valid_username(&username) {
+                    return Err(anyhow!(
+                        "Invalid username: {}. Use letters, digits, `.`, `_`, `@` and `-`.",
+                        username
+                    ));
+                }
+                let password = match password {
+                    Some(password) => password,
+                    None => read_password()?,
+                };
+                let app = api_client()?
+                    .set_access_user(&app_name, &username, &password)
+                    .await?;
+                print_message(
+                    &format!("Set password of user '{}' on app '{}'", username, app.name),
+                    &app,
+                    format,
+                )
+            }
+            AccessCommands::RemoveUser { app_name, username } => {
+                let app = api_client()?
+                    .remove_access_user(&app_name, &username)
+                    .await?;
+                print_message(
+                    &format!("Removed user '{}' from app '{}'", username, app.name),
+                    &app,
+                    format,
+                )
+            }
+            AccessCommands::Allow { app_name, networks } => {
+                let client = api_client()?;
+                let mut allow = client.get_app_info(&app_name).await?.access.allow;
+                allow.extend(networks);
+                let app = client.set_allowlist(&app_name, allow).await?;
+                print_message(
+                    &format!("Updated allowlist of app '{}'", app.name),
+                    &app,
+                    format,
+                )
+            }
+            AccessCommands::Disallow {
+                app_name,
+                networks,
+                all,
+            } => {
+                let client = api_client()?;
+                let mut allow = client.get_app_info(&app_name).await?.access.allow;
+                if all {
+                    allow.clear();
+                } else {
+                    // The server stores networks in canonical form, so
+                    // compare them parsed
+                    let remove = networks
+                        .iter()
+                        .map(|network| forwarding::parse_network(network))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    allow.retain(|network| {
+                        forwarding::parse_network(network)
+                            .map_or(true, |network| !remove.contains(&network))
+                    });
+                }
+                let app = client.set_allowlist(&app_name, allow).await?;
+                print_message(
+                    &format!("Updated allowlist of app '{}'", app.name),
+                    &app,
+                    format,
+                )
+            }
+        },
+        Commands::Domains { command } => match command {
+            DomainCommands::Add { app_name, hostname } => {
+                let domain = api_client()?.add_domain(&app_name, &hostname).await?;
+                print_message(
+                    &format!("Routed {} to app '{}'", domain.hostname, domain.app_name),
+                    &domain,
+                    format,
+                )
+            }
+            DomainCommands::Remove { app_name, hostname } => {
+                let message = api_client()?.remove_domain(&app_name, &hostname).await?;
+                print_message(&message.message, &message, format)
+            }
+            DomainCommands::List { app_name } => {
+                let app_name = app_or_default(app_name, &context()?)?;
+                let domains = api_client()?.list_domains(&app_name).await?;
+                print_list(&domains, format, "No domains found")
+            }
+        },
+        Commands::Routes { command } => match command {
+            RouteCommands::Add {
+                hostname,
+                path_prefix,
+                app_name,
+                strip_prefix,
+            } => {
+                let route = api_client()?
+                    .add_route(&AddRouteRequest {
+                        hostname,
+                        path_prefix,
+                        app: app_name,
+                        strip_prefix,
+                    })
+                    .await?;
+                print_message(
+                    &format!(
+                        "Routed {}{} to app '{}'",
+                        route.hostname, route.path_prefix, route.app_name
+                    ),
+                    &route,
+                    format,
+                )
+            }
+            RouteCommands::Remove {
+                hostname,
+                path_prefix,
+            } => {
+                let message = api_client()?.remove_route(&hostname, &path_prefix).await?;
+                print_message(&message.message, &message, format)
+            }
+            RouteCommands::List { hostname } => {
+                let routes = api_client()?.list_routes(hostname.as_deref()).await?;
+                print_list(&routes, format, "No routes found")
+            }
+        },
+        Commands::Audit { app, since } => {
+            let entries = api_client()?.get_audit_log(app.as_deref(), since).await?;
+            print_list(&entries, format, "No audit log entries found")
+        }
         Commands::Serve => {
             let config = ServerConfig::load()?;
             serve::execute(config).await
         }
+        Commands::Server { command } => match command {
+            ServerCommands::Token { command } => run_token_command(command, format).await,
+        },
+        Commands::Context { command } => run_context_command(command, format),
         Commands::Config => {
             let client_config = ClientConfig::load()?;
             let client_config_path = ClientConfig::get_config_path()?;
             let server_config = ServerConfig::load()?;
             let server_config_path = ServerConfig::get_config_path()?;
 
+            if format == OutputFormat::Json {
+                let value = serde_json::json!({
+                    "client": { "path": client_config_path, "config": client_config },
+                    "server": { "path": server_config_path, "config": server_config },
+                });
+                println!("{}", serde_json::to_string_pretty(&value)?);
+                return Ok(());
+            }
+
             println!("Client config: {}", client_config_path.display());
             println!("{}", toml::to_string(&client_config)?);
 
@@ -156,3 +905,237 @@ pub async fn run() -> Result<()> {
         }
     }
 }
+
+/// Use the given app name, falling back to the context's default app
+fn app_or_default(app_name: Option<String>, context: &ClientContext) -> Result<String> {
+    app_name
+        .or_else(|| context.default_app.clone())
+        .ok_or_else(|| anyhow!("No app name given and the current context has no default app"))
+}
+
+fn run_context_command(command: ContextCommands, format: OutputFormat) -> Result<()> {
+    let mut config = ClientConfig::load()?;
+
+    match command {
+        ContextCommands::Add {
+            name,
+            url,
+            token,
+            default_app,
+        } => {
+            let context = ClientContext {
+                url: url.trim_end_matches('/').to_string(),
+                token,
+                default_app,
+            };
+            let replaced = config.contexts.insert(name.clone(), context).is_some();
+            if config.current_context.is_none() {
+                config.current_context = Some(name.clone());
+            }
+            config.save()?;
+
+            if replaced {
+                println!("Updated context '{}'", name);
+            } else {
+                println!("Added context '{}'", name);
+            }
+        }
+        ContextCommands::Use { name } => {
+            if !config.contexts.contains_key(&name) {
+                return Err(anyhow!("Context '{}' not found", name));
+            }
+            config.current_context = Some(name.clone());
+            config.save()?;
+            println!("Switched to context '{}'", name);
+        }
+        ContextCommands::List => {
+            let contexts: Vec<ContextInfo> = config
+                .contexts
+                .iter()
+                .map(|(name, context)| ContextInfo {
+                    name: name.clone(),
+                    current: config.current_context.as_deref() == Some(name),
+                    url: context.url.clone(),
+                    token: context.token.is_some(),
+                    default_app: context.default_app.clone(),
+                })
+                .collect();
+            print_list(&contexts, format, "No contexts found")?;
+        }
+        ContextCommands::Remove { name } => {
+            if config.contexts.remove(&name).is_none() {
+                return Err(anyhow!("Context '{}' not found", name));
+            }
+            if config.current_context.as_deref() == Some(name.as_str()) {
+                config.current_context = None;
+            }
+            config.save()?;
+            println!("Removed context '{}'", name);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_token_command(command: TokenCommands, format: OutputFormat) -> Result<()> {
+    let pool = crate::db::init_pool().await?;
+
+    match command {
+        TokenCommands::Create { name, scopes, apps } => {
+            let apps = if apps.is_empty() { None } else { Some(apps) };
+            let result = token::create(&pool, &name, scopes.clone(), apps.clone()).await;
+            let entry = AuditEntry::new(LOCAL_ACTOR, "create_token")
+                .with_parameters(serde_json::json!({
+                    "name": name,
+                    "scopes": scopes,
+                    "apps": apps,
+                }))
+                .with_result(&result);
+            audit::record(&pool, entry).await;
+
+            let (api_token, secret) = result?;
+            if format == OutputFormat::Json {
+                let value = serde_json::json!({
+                    "name": api_token.name,
+                    "token": secret,
+                    "scopes": api_token.scopes,
+                    "apps": api_token.apps,
+                });
+                println!("{}", serde_json::to_string_pretty(&value)?);
+                return Ok(());
+            }
+
+            println!("Created token '{}'. It will not be shown again:", name);
+            println!();
+            println!("    {}", secret);
+            println!();
+            println!("Permissions: {}", api_token.permissions());
+            println!("Add it to the client config as `token = \"{}\"`", secret);
+        }
+        TokenCommands::List => {
+            print_list(&token::list(&pool).await?, format, "No tokens found")?;
+        }
+        TokenCommands::Revoke { name } => {
+            let result = token::revoke(&pool, &name).await;
+            let entry = AuditEntry::new(LOCAL_ACTOR, "revoke_token")
+                .with_parameters(serde_json::json!({ "name": name }))
+                .with_result(&result);
+            audit::record(&pool, entry).await;
+            result?;
+            print_message(
+                &format!("Revoked token '{}'", name),
+                &serde_json::json!({ "name": name }),
+                format,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A client context as shown by `bindrop context list`
+#[derive(serde::Serialize)]
+struct ContextInfo {
+    name: String,
+    current: bool,
+    url: String,
+    token: bool,
+    default_app: Option<String>,
+}
+
+impl Render for ContextInfo {
+    fn headers() -> Vec<&'static str> {
+        vec!["", "NAME", "URL", "TOKEN", "DEFAULT APP"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            if self.current { "*" } else { "" }.to_string(),
+            self.name.clone(),
+            self.url.clone(),
+            if self.token { "yes" } else { "no" }.to_string(),
+            self.default_app.clone().unwrap_or_else(|| "-".to_string()),
+        ]
+    }
+}
+
+/// Parse `--since` as either a relative duration (`30m`, `2h`, `7d`) or an
+/// RFC 3339 timestamp
+/// Read a password from the first line of standard input
+fn read_password() -> Result<String> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if std::io::stdin().is_terminal() {
+        eprint!("Password: ");
+        std::io::stderr().flush()?;
+    }
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let split = value.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| {
+        format!(
+            "Invalid time '{}'. Use e.g. 30m, 2h, 7d or an RFC 3339 timestamp",
+            value
+        )
+    })?;
+
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => return Err(format!("Invalid time unit '{}'. Use s, m, h or d", unit)),
+    };
+
+    Ok(Utc::now() - duration)
+}
+
+/// Parse a size in bytes with an optional `K`, `M` or `G` suffix
+fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size '{}'. Use e.g. 4096, 512K or 10M", value);
+    let (amount, multiplier) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1024),
+        Some((i, 'M' | 'm')) => (&value[..i], 1024 * 1024),
+        Some((i, 'G' | 'g')) => (&value[..i], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    amount.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512K"), Ok(512 * 1024));
+        assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert!(parse_size("10MB").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let got = parse_since("2h").unwrap();
+        let want = Utc::now() - Duration::hours(2);
+        assert!((got - want).num_seconds().abs() < 5);
+
+        let got = parse_since("2026-10-01T00:00:00Z").unwrap();
+        assert_eq!(got.to_rfc3339(), "2026-10-01T00:00:00+00:00");
+
+        assert!(parse_since("2w").is_err());
+        assert!(parse_since("soon").is_err());
+    }
+}
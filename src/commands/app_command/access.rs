@@ -0,0 +1,183 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::commands::server_command::forwarding;
+use crate::db;
+use crate::models::{App, BasicAuthUser};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("User '{username}' not found on app '{app}'")]
+    UserNotFound { username: String, app: String },
+    #[error("Invalid username: {0}")]
+    InvalidUsername(String),
+    #[error("Password must not be empty")]
+    EmptyPassword,
+    #[error(transparent)]
+    InvalidNetwork(#[from] forwarding::ForwardingError),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, AccessError>;
+
+/// Add a basic auth user to an app, or change the password of an existing one
+#[instrument(skip(pool, password))]
+pub async fn set_user(
+    pool: &Pool<Sqlite>,
+    app_name: &str,
+    username: &str,
+    password: &str,
+) -> Result<App> {
+    if !BasicAuthUser::is_valid_username(username) {
+        return Err(AccessError::InvalidUsername(username.to_string()));
+    }
+    if password.is_empty() {
+        return Err(AccessError::EmptyPassword);
+    }
+
+    let mut app = get_app(pool, app_name).await?;
+    let user = BasicAuthUser::new(username, password);
+    match app.access.users.iter_mut().find(|u| u.username == username) {
+        Some(existing) => *existing = user,
+        None => app.access.users.push(user),
+    }
+    save(pool, &mut app).await?;
+
+    info!("Set basic auth user '{}' of app '{}'", username, app_name);
+
+    Ok(app)
+}
+
+/// Remove a basic auth user from an app. Once the last user is gone the app
+/// no longer asks for credentials.
+#[instrument(skip(pool))]
+pub async fn remove_user(pool: &Pool<Sqlite>, app_name: &str, username: &str) -> Result<App> {
+    let mut app = get_app(pool, app_name).await?;
+    let before = app.access.users.len();
+    app.access.users.retain(|u| u.username != username);
+    if app.access.users.len() == before {
+        return Err(AccessError::UserNotFound {
+            username: username.to_string(),
+            app: app_name.to_string(),
+        });
+    }
+    save(pool, &mut app).await?;
+
+    info!(
+        "Removed basic auth user '{}' of app '{}'",
+        username, app_name
+    );
+
+    Ok(app)
+}
+
+/// Replace the addresses and networks an app accepts requests from. An empty
+/// list accepts requests from anywhere.
+#[instrument(skip(pool))]
+pub async fn set_allowlist(
+    pool: &Pool<Sqlite>,
+    app_name: &str,
+    networks: &[String],
+) -> Result<App> {
+    let mut allow = Vec::new();
+    for network in networks {
+        let network = forwarding::parse_network(network)?.to_string();
+        if !allow.contains(&network) {
+            allow.push(network);
+        }
+    }
+
+    let mut app = get_app(pool, app_name).await?;
+    app.access.allow = allow;
+    save(pool, &mut app).await?;
+
+    info!(
+        "Set allowlist of app '{}': {:?}",
+        app_name, app.access.allow
+    );
+
+    Ok(app)
+}
+
+async fn get_app(pool: &Pool<Sqlite>, app_name: &str) -> Result<App> {
+    db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| AccessError::AppNotFound(app_name.to_string()))
+}
+
+async fn save(pool: &Pool<Sqlite>, app: &mut App) -> Result<()> {
+    app.updated_at = Utc::now();
+    db::apps::save(pool, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_users() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        set_user(&pool, "web", "alice", "first").await.unwrap();
+        set_user(&pool, "web", "alice", "second").await.unwrap();
+        let app = set_user(&pool, "web", "bob", "hunter2").await.unwrap();
+        assert_eq!(app.access.users.len(), 2);
+
+        // Only the hash of the latest password is stored
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        let alice = &saved.access.users[0];
+        assert!(!alice.password_hash.contains("second"));
+        assert!(alice.verify("second"));
+        assert!(!alice.verify("first"));
+
+        assert!(matches!(
+            set_user(&pool, "web", "eve:x", "pw").await,
+            Err(AccessError::InvalidUsername(_))
+        ));
+        assert!(matches!(
+            set_user(&pool, "web", "eve/x", "pw").await,
+            Err(AccessError::InvalidUsername(_))
+        ));
+        assert!(matches!(
+            set_user(&pool, "web", "eve", "").await,
+            Err(AccessError::EmptyPassword)
+        ));
+
+        remove_user(&pool, "web", "alice").await.unwrap();
+        let app = remove_user(&pool, "web", "bob").await.unwrap();
+        assert!(app.access.is_open());
+        assert!(matches!(
+            remove_user(&pool, "web", "bob").await,
+            Err(AccessError::UserNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        let networks = ["10.0.0.0/8", "192.168.1.10", "10.0.0.0/8"].map(String::from);
+        let app = set_allowlist(&pool, "web", &networks).await.unwrap();
+        assert_eq!(app.access.allow, ["10.0.0.0/8", "192.168.1.10/32"]);
+
+        assert!(matches!(
+            set_allowlist(&pool, "web", &["10.0.0.0/40".to_string()]).await,
+            Err(AccessError::InvalidNetwork(_))
+        ));
+
+        let app = set_allowlist(&pool, "web", &[]).await.unwrap();
+        assert!(app.access.is_open());
+    }
+}
@@ -0,0 +1,63 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::App;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompressionError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, CompressionError>;
+
+/// Turn compression of an app's responses by the proxy on or off
+#[instrument(skip(pool))]
+pub async fn set(pool: &Pool<Sqlite>, app_name: &str, enabled: bool) -> Result<App> {
+    let mut app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| CompressionError::AppNotFound(app_name.to_string()))?;
+
+    app.compression = enabled;
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    info!(
+        "Turned {} compression of app '{}'",
+        if enabled { "on" } else { "off" },
+        app_name
+    );
+
+    Ok(app)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_set_compression() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        set(&pool, "web", true).await.unwrap();
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        assert!(saved.compression);
+
+        set(&pool, "web", false).await.unwrap();
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        assert!(!saved.compression);
+
+        assert!(matches!(
+            set(&pool, "missing", true).await,
+            Err(CompressionError::AppNotFound(_))
+        ));
+    }
+}
@@ -29,7 +29,7 @@ pub async fn execute<H: Handle>(
     provider: impl Provider<Handle = H>,
 ) -> Result<()> {
     // Check if app already exists
-    if let Some(_) = db::apps::get_by_name(pool, app_name).await? {
+    if db::apps::get_by_name(pool, app_name).await?.is_some() {
         return Err(AppCreateError::AppAlreadyExists(app_name.to_string()));
     }
 
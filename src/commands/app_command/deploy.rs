@@ -47,7 +47,7 @@ pub async fn execute(pool: &Pool<Sqlite>, app_name: &str, binary_data: &[u8]) ->
 
     // Update and save to database
     let app = app.deployed(target_path, hash);
-    db::apps::save(&pool, &app).await?;
+    db::apps::save(pool, &app).await?;
 
     info!("Deployed binary to app '{}'", app_name);
 
@@ -74,7 +74,7 @@ fn copy_and_set_permissions(target_path: &str, binary_data: &[u8]) -> Result<()>
             .map_err(|err| DeployError::PermissionError(err.to_string()))?
             .permissions();
         perms.set_mode(0o755);
-        fs::set_permissions(&target_path, perms)
+        fs::set_permissions(target_path, perms)
             .map_err(|err| DeployError::PermissionError(err.to_string()))?;
     }
     Ok(())
@@ -0,0 +1,241 @@
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::Domain;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DomainError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Invalid hostname: {0}")]
+    InvalidHostname(String),
+    #[error("Domain {hostname} is already routed to app '{app}'")]
+    DomainTaken { hostname: String, app: String },
+    #[error("Domain {hostname} is not routed to app '{app}'")]
+    DomainNotFound { hostname: String, app: String },
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, DomainError>;
+
+/// Route a hostname, or a wildcard like `*.example.com`, to an app
+#[instrument(skip(pool))]
+pub async fn add(pool: &Pool<Sqlite>, app_name: &str, hostname: &str) -> Result<Domain> {
+    let hostname = normalize_hostname(hostname)?;
+    let app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| DomainError::AppNotFound(app_name.to_string()))?;
+
+    if let Some(existing) = db::domains::get_by_hostname(pool, &hostname).await? {
+        return Err(DomainError::DomainTaken {
+            hostname,
+            app: existing.app_name,
+        });
+    }
+
+    let domain = Domain::new(&hostname, &app);
+    db::domains::save(pool, &domain).await?;
+
+    info!("Routed {} to app '{}'", hostname, app_name);
+
+    Ok(domain)
+}
+
+/// Stop routing a hostname to an app
+#[instrument(skip(pool))]
+pub async fn remove(pool: &Pool<Sqlite>, app_name: &str, hostname: &str) -> Result<()> {
+    let hostname = normalize_hostname(hostname)?;
+    let app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| DomainError::AppNotFound(app_name.to_string()))?;
+
+    if db::domains::delete(pool, &app.id, &hostname).await? == 0 {
+        return Err(DomainError::DomainNotFound {
+            hostname,
+            app: app_name.to_string(),
+        });
+    }
+
+    info!("Removed {} from app '{}'", hostname, app_name);
+
+    Ok(())
+}
+
+/// List the hostnames routed to an app
+#[instrument(skip(pool))]
+pub async fn list(pool: &Pool<Sqlite>, app_name: &str) -> Result<Vec<Domain>> {
+    let app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| DomainError::AppNotFound(app_name.to_string()))?;
+
+    Ok(db::domains::get_by_app_id(pool, &app.id).await?)
+}
+
+/// Find the app a request's `Host` header is routed to: an exact custom
+/// domain first, then a wildcard for its parent domain, then
+/// `<app>.<base_domain>`. Without a base domain the first label of the host
+/// is the app name.
+#[instrument(skip(pool))]
+pub async fn resolve_app(
+    pool: &Pool<Sqlite>,
+    host: &str,
+    base_domain: Option<&str>,
+) -> std::result::Result<Option<String>, db::DatabaseError> {
+    let hostname = strip_port(host).trim_end_matches('.').to_ascii_lowercase();
+    if hostname.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(domain) = db::domains::get_by_hostname(pool, &hostname).await? {
+        return Ok(Some(domain.app_name));
+    }
+
+    if let Some((_, parent)) = hostname.split_once('.') {
+        let wildcard = format!("*.{}", parent);
+        if let Some(domain) = db::domains::get_by_hostname(pool, &wildcard).await? {
+            return Ok(Some(domain.app_name));
+        }
+    }
+
+    let app_name = match base_domain {
+        Some(base_domain) => hostname
+            .strip_suffix(&base_domain.trim_matches('.').to_ascii_lowercase())
+            .and_then(|prefix| prefix.strip_suffix('.'))
+            .filter(|label| !label.is_empty() && !label.contains('.')),
+        None => hostname.split('.').next(),
+    };
+
+    Ok(app_name.map(str::to_string))
+}
+
+/// Hostname without the port of a `Host` header
+pub fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((hostname, port)) if port.chars().all(|c| c.is_ascii_digit()) => hostname,
+        _ => host,
+    }
+}
+
+/// Lowercase a hostname and check it is a valid domain name, allowing a
+/// leading `*.` wildcard label
+pub fn normalize_hostname(hostname: &str) -> Result<String> {
+    let normalized = hostname.trim().trim_end_matches('.').to_ascii_lowercase();
+    let name = normalized.strip_prefix("*.").unwrap_or(&normalized);
+
+    let valid = name.len() <= 253
+        && name.contains('.')
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !valid {
+        return Err(DomainError::InvalidHostname(hostname.to_string()));
+    }
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+    use crate::models::App;
+
+    async fn create_app(pool: &Pool<Sqlite>, name: &str) {
+        db::apps::save(pool, &App::new(name).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_normalize_hostname() {
+        assert_eq!(
+            normalize_hostname("WWW.Example.com.").unwrap(),
+            "www.example.com"
+        );
+        assert_eq!(
+            normalize_hostname("*.example.com").unwrap(),
+            "*.example.com"
+        );
+        assert!(normalize_hostname("localhost").is_err());
+        assert!(normalize_hostname("*.com").is_err());
+        assert!(normalize_hostname("www.*.example.com").is_err());
+        assert!(normalize_hostname("-web.example.com").is_err());
+        assert!(normalize_hostname("web..example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove() {
+        let pool = get_test_pool().await;
+        create_app(&pool, "web").await;
+        create_app(&pool, "api").await;
+
+        add(&pool, "web", "www.customer.com").await.unwrap();
+        add(&pool, "web", "*.customer.com").await.unwrap();
+
+        let got = add(&pool, "api", "WWW.customer.com").await.unwrap_err();
+        match got {
+            DomainError::DomainTaken { ref app, .. } if app == "web" => {}
+            _ => panic!("Expected DomainTaken, got: {:?}", got),
+        }
+
+        let hostnames: Vec<String> = list(&pool, "web")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| d.hostname)
+            .collect();
+        assert_eq!(hostnames, vec!["*.customer.com", "www.customer.com"]);
+
+        let got = remove(&pool, "api", "www.customer.com").await.unwrap_err();
+        match got {
+            DomainError::DomainNotFound { ref app, .. } if app == "api" => {}
+            _ => panic!("Expected DomainNotFound, got: {:?}", got),
+        }
+
+        remove(&pool, "web", "www.customer.com").await.unwrap();
+        assert_eq!(list(&pool, "web").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_app() {
+        let pool = get_test_pool().await;
+        create_app(&pool, "web").await;
+        create_app(&pool, "shop").await;
+        add(&pool, "shop", "www.customer.com").await.unwrap();
+        add(&pool, "web", "*.customer.com").await.unwrap();
+
+        let resolve = |host: &'static str, base: Option<&'static str>| {
+            let pool = pool.clone();
+            async move { resolve_app(&pool, host, base).await.unwrap() }
+        };
+
+        // Exact match wins over the wildcard
+        assert_eq!(
+            resolve("www.customer.com:8080", None).await.as_deref(),
+            Some("shop")
+        );
+        assert_eq!(
+            resolve("blog.customer.com", None).await.as_deref(),
+            Some("web")
+        );
+
+        // Subdomains of the base domain only
+        let base = Some("apps.example.com");
+        assert_eq!(
+            resolve("web.apps.example.com", base).await.as_deref(),
+            Some("web")
+        );
+        assert_eq!(resolve("web.other.net", base).await, None);
+        assert_eq!(resolve("a.web.apps.example.com", base).await, None);
+        assert_eq!(resolve("apps.example.com", base).await, None);
+
+        // Without a base domain the first label is the app
+        assert_eq!(resolve("web.other.net", None).await.as_deref(), Some("web"));
+    }
+}
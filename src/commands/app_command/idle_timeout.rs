@@ -0,0 +1,76 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::App;
+
+#[derive(Debug, thiserror::Error)]
+pub enum IdleTimeoutError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Idle timeout must be at least one minute")]
+    InvalidTimeout,
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, IdleTimeoutError>;
+
+/// Put an app to sleep after `minutes` without proxied requests, or keep it
+/// running regardless with `None`
+#[instrument(skip(pool))]
+pub async fn set(pool: &Pool<Sqlite>, app_name: &str, minutes: Option<u32>) -> Result<App> {
+    if minutes == Some(0) {
+        return Err(IdleTimeoutError::InvalidTimeout);
+    }
+
+    let mut app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| IdleTimeoutError::AppNotFound(app_name.to_string()))?;
+
+    app.idle_timeout = minutes.map(|m| m.saturating_mul(60));
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    match minutes {
+        Some(minutes) => info!(
+            "App '{}' will sleep after {} minute(s) idle",
+            app_name, minutes
+        ),
+        None => info!("App '{}' will no longer sleep when idle", app_name),
+    }
+
+    Ok(app)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_set_idle_timeout() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("tool").unwrap())
+            .await
+            .unwrap();
+
+        let app = set(&pool, "tool", Some(15)).await.unwrap();
+        assert_eq!(app.idle_timeout, Some(900));
+        let saved = db::apps::get_by_name(&pool, "tool").await.unwrap().unwrap();
+        assert_eq!(saved.idle_timeout, Some(900));
+
+        let app = set(&pool, "tool", None).await.unwrap();
+        assert_eq!(app.idle_timeout, None);
+
+        assert!(matches!(
+            set(&pool, "tool", Some(0)).await,
+            Err(IdleTimeoutError::InvalidTimeout)
+        ));
+        assert!(matches!(
+            set(&pool, "missing", Some(5)).await,
+            Err(IdleTimeoutError::AppNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::{App, AppLimits};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LimitsError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Invalid limit: {0}")]
+    InvalidLimit(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, LimitsError>;
+
+/// Replace the limits the proxy enforces on requests to an app
+#[instrument(skip(pool))]
+pub async fn set(pool: &Pool<Sqlite>, app_name: &str, limits: AppLimits) -> Result<App> {
+    validate(&limits)?;
+
+    let mut app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| LimitsError::AppNotFound(app_name.to_string()))?;
+
+    app.limits = limits;
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    info!("Set limits of app '{}': {:?}", app_name, app.limits);
+
+    Ok(app)
+}
+
+/// Limits of zero would block every request, so they are rejected rather
+/// than taken to mean unlimited
+fn validate(limits: &AppLimits) -> Result<()> {
+    let zero = [
+        (
+            "requests per second",
+            limits.requests_per_second.map(u64::from),
+        ),
+        ("burst", limits.burst.map(u64::from)),
+        (
+            "max concurrent requests",
+            limits.max_concurrent.map(u64::from),
+        ),
+        ("max body size", limits.max_body_bytes),
+    ]
+    .into_iter()
+    .find(|(_, value)| *value == Some(0));
+    if let Some((name, _)) = zero {
+        return Err(LimitsError::InvalidLimit(format!(
+            "{} must be at least 1",
+            name
+        )));
+    }
+
+    if limits.burst.is_some() && limits.requests_per_second.is_none() {
+        return Err(LimitsError::InvalidLimit(
+            "burst needs a requests per second limit".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_set_limits() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        let limits = AppLimits {
+            requests_per_second: Some(10),
+            burst: Some(20),
+            max_concurrent: Some(50),
+            max_body_bytes: Some(1024 * 1024),
+        };
+        set(&pool, "web", limits.clone()).await.unwrap();
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        assert_eq!(saved.limits, limits);
+
+        set(&pool, "web", AppLimits::default()).await.unwrap();
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        assert!(saved.limits.is_unlimited());
+
+        assert!(matches!(
+            set(&pool, "missing", AppLimits::default()).await,
+            Err(LimitsError::AppNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_limits() {
+        let pool = get_test_pool().await;
+        for limits in [
+            AppLimits {
+                requests_per_second: Some(0),
+                ..Default::default()
+            },
+            AppLimits {
+                max_body_bytes: Some(0),
+                ..Default::default()
+            },
+            AppLimits {
+                burst: Some(5),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                set(&pool, "web", limits).await,
+                Err(LimitsError::InvalidLimit(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::commands::server_command::forwarding;
+use crate::db;
+use crate::models::{App, Maintenance};
+
+/// Largest custom maintenance page accepted
+pub const MAX_PAGE_BYTES: usize = 1024 * 1024;
+
+/// Retry-After sent during maintenance unless told otherwise
+pub const DEFAULT_RETRY_AFTER: u32 = 300;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MaintenanceError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Maintenance page is larger than {} bytes", MAX_PAGE_BYTES)]
+    PageTooLarge,
+    #[error(transparent)]
+    InvalidNetwork(#[from] forwarding::ForwardingError),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, MaintenanceError>;
+
+/// Put an app into maintenance mode. The proxy answers requests to it with a
+/// 503 page, except those from `allow`, and its process is left running.
+#[instrument(skip(pool, page))]
+pub async fn enable(
+    pool: &Pool<Sqlite>,
+    app_name: &str,
+    page: Option<String>,
+    allow: &[String],
+    retry_after: Option<u32>,
+) -> Result<App> {
+    if page
+        .as_ref()
+        .is_some_and(|page| page.len() > MAX_PAGE_BYTES)
+    {
+        return Err(MaintenanceError::PageTooLarge);
+    }
+    let allow = allow
+        .iter()
+        .map(|network| Ok(forwarding::parse_network(network)?.to_string()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut app = get_app(pool, app_name).await?;
+    // Turning it on again updates the page without resetting when it began
+    let since = app.maintenance.as_ref().map_or_else(Utc::now, |m| m.since);
+    app.maintenance = Some(Maintenance {
+        since,
+        page,
+        allow,
+        retry_after: retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+    });
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    info!("Put app '{}' into maintenance mode", app_name);
+
+    Ok(app)
+}
+
+/// Take an app out of maintenance mode, if it is in it
+#[instrument(skip(pool))]
+pub async fn disable(pool: &Pool<Sqlite>, app_name: &str) -> Result<App> {
+    let mut app = get_app(pool, app_name).await?;
+    if app.maintenance.take().is_some() {
+        app.updated_at = Utc::now();
+        db::apps::save(pool, &app).await?;
+        info!("Took app '{}' out of maintenance mode", app_name);
+    }
+
+    Ok(app)
+}
+
+async fn get_app(pool: &Pool<Sqlite>, app_name: &str) -> Result<App> {
+    db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| MaintenanceError::AppNotFound(app_name.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_enable_and_disable() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        let page = "<h1>Back soon</h1>".to_string();
+        let app = enable(
+            &pool,
+            "web",
+            Some(page.clone()),
+            &["10.0.0.1".to_string()],
+            None,
+        )
+        .await
+        .unwrap();
+        let since = app.maintenance.unwrap().since;
+
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        let maintenance = saved.maintenance.unwrap();
+        assert_eq!(maintenance.page, Some(page));
+        assert_eq!(maintenance.allow, ["10.0.0.1/32"]);
+        assert_eq!(maintenance.retry_after, DEFAULT_RETRY_AFTER);
+
+        // Changing the page keeps the original start
+        let app = enable(&pool, "web", None, &[], Some(60)).await.unwrap();
+        let maintenance = app.maintenance.unwrap();
+        assert_eq!(maintenance.since, since);
+        assert_eq!(maintenance.retry_after, 60);
+
+        disable(&pool, "web").await.unwrap();
+        let saved = db::apps::get_by_name(&pool, "web").await.unwrap().unwrap();
+        assert!(saved.maintenance.is_none());
+        assert!(disable(&pool, "web").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_maintenance() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("web").unwrap())
+            .await
+            .unwrap();
+
+        let page = "x".repeat(MAX_PAGE_BYTES + 1);
+        assert!(matches!(
+            enable(&pool, "web", Some(page), &[], None).await,
+            Err(MaintenanceError::PageTooLarge)
+        ));
+        assert!(matches!(
+            enable(&pool, "web", None, &["nope".to_string()], None).await,
+            Err(MaintenanceError::InvalidNetwork(_))
+        ));
+        assert!(matches!(
+            enable(&pool, "missing", None, &[], None).await,
+            Err(MaintenanceError::AppNotFound(_))
+        ));
+    }
+}
@@ -1,10 +1,19 @@
 // src/commands/app_command/mod.rs
+pub mod access;
 pub mod app_env;
+pub mod compression;
 pub mod create;
 pub mod delete;
 pub mod deploy;
+pub mod domains;
+pub mod idle_timeout;
+pub mod limits;
 pub mod logs;
+pub mod maintenance;
 pub mod restart;
+pub mod routes;
+pub mod scale;
+pub mod service;
 pub mod start;
 pub mod status;
 pub mod stop;
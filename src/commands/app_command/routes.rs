@@ -0,0 +1,230 @@
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use super::domains;
+use crate::db;
+use crate::models::Route;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Invalid hostname: {0}")]
+    InvalidHostname(String),
+    #[error("Invalid path prefix: {0}. Prefixes must start with '/' and have no query.")]
+    InvalidPathPrefix(String),
+    #[error("Route {hostname}{path_prefix} already goes to app '{app}'")]
+    RouteExists {
+        hostname: String,
+        path_prefix: String,
+        app: String,
+    },
+    #[error("No route for {hostname}{path_prefix}")]
+    RouteNotFound {
+        hostname: String,
+        path_prefix: String,
+    },
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, RouteError>;
+
+/// Send requests for `hostname` under `path_prefix` to an app
+#[instrument(skip(pool))]
+pub async fn add(
+    pool: &Pool<Sqlite>,
+    hostname: &str,
+    path_prefix: &str,
+    app_name: &str,
+    strip_prefix: bool,
+) -> Result<Route> {
+    let hostname = normalize_hostname(hostname)?;
+    let path_prefix = normalize_path_prefix(path_prefix)?;
+    let app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| RouteError::AppNotFound(app_name.to_string()))?;
+
+    let existing = db::routes::get(pool, Some(&hostname)).await?;
+    if let Some(route) = existing.into_iter().find(|r| r.path_prefix == path_prefix) {
+        return Err(RouteError::RouteExists {
+            hostname,
+            path_prefix,
+            app: route.app_name,
+        });
+    }
+
+    let route = Route::new(&hostname, &path_prefix, &app, strip_prefix);
+    db::routes::save(pool, &route).await?;
+
+    info!("Routed {}{} to app '{}'", hostname, path_prefix, app_name);
+
+    Ok(route)
+}
+
+/// Remove the route for `hostname` and `path_prefix`
+#[instrument(skip(pool))]
+pub async fn remove(pool: &Pool<Sqlite>, hostname: &str, path_prefix: &str) -> Result<Route> {
+    let hostname = normalize_hostname(hostname)?;
+    let path_prefix = normalize_path_prefix(path_prefix)?;
+
+    let route = db::routes::get(pool, Some(&hostname))
+        .await?
+        .into_iter()
+        .find(|r| r.path_prefix == path_prefix)
+        .ok_or_else(|| RouteError::RouteNotFound {
+            hostname: hostname.clone(),
+            path_prefix: path_prefix.clone(),
+        })?;
+    db::routes::delete(pool, &hostname, &path_prefix).await?;
+
+    info!("Removed route {}{}", hostname, path_prefix);
+
+    Ok(route)
+}
+
+/// List routes, optionally only those of one hostname
+#[instrument(skip(pool))]
+pub async fn list(pool: &Pool<Sqlite>, hostname: Option<&str>) -> Result<Vec<Route>> {
+    let hostname = hostname.map(normalize_hostname).transpose()?;
+    Ok(db::routes::get(pool, hostname.as_deref()).await?)
+}
+
+/// Find the route with the longest prefix matching a request's `Host` header
+/// and path
+#[instrument(skip(pool))]
+pub async fn resolve(
+    pool: &Pool<Sqlite>,
+    host: &str,
+    path: &str,
+) -> std::result::Result<Option<Route>, db::DatabaseError> {
+    let hostname = domains::strip_port(host)
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+
+    // Routes come back longest prefix first
+    let routes = db::routes::get(pool, Some(&hostname)).await?;
+    Ok(routes.into_iter().find(|route| route.matches(path)))
+}
+
+/// Path and query to forward to the app, with the route's prefix removed
+/// when it strips prefixes
+pub fn forwarded_path(route: &Route, path_and_query: &str) -> String {
+    if !route.strip_prefix {
+        return path_and_query.to_string();
+    }
+
+    let rest = path_and_query
+        .strip_prefix(route.path_prefix.trim_end_matches('/'))
+        .unwrap_or(path_and_query);
+    if rest.starts_with('/') {
+        rest.to_string()
+    } else {
+        format!("/{}", rest)
+    }
+}
+
+/// Routes belong to a single hostname, so wildcards are not allowed
+fn normalize_hostname(hostname: &str) -> Result<String> {
+    domains::normalize_hostname(hostname)
+        .ok()
+        .filter(|hostname| !hostname.starts_with("*."))
+        .ok_or_else(|| RouteError::InvalidHostname(hostname.to_string()))
+}
+
+/// Check a path prefix and remove any trailing slash, so `/api/` and `/api`
+/// are the same route
+pub fn normalize_path_prefix(path_prefix: &str) -> Result<String> {
+    let valid = path_prefix.starts_with('/')
+        && !path_prefix
+            .chars()
+            .any(|c| c == '?' || c == '#' || c.is_whitespace());
+    if !valid {
+        return Err(RouteError::InvalidPathPrefix(path_prefix.to_string()));
+    }
+
+    match path_prefix.trim_end_matches('/') {
+        "" => Ok("/".to_string()),
+        prefix => Ok(prefix.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+    use crate::models::App;
+
+    async fn create_app(pool: &Pool<Sqlite>, name: &str) {
+        db::apps::save(pool, &App::new(name).unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_normalize_path_prefix() {
+        assert_eq!(normalize_path_prefix("/api/").unwrap(), "/api");
+        assert_eq!(normalize_path_prefix("/").unwrap(), "/");
+        assert!(normalize_path_prefix("api").is_err());
+        assert!(normalize_path_prefix("/api?x=1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_longest_prefix_wins() {
+        let pool = get_test_pool().await;
+        create_app(&pool, "web").await;
+        create_app(&pool, "api").await;
+        create_app(&pool, "admin").await;
+        add(&pool, "example.com", "/", "web", false).await.unwrap();
+        add(&pool, "example.com", "/api", "api", true)
+            .await
+            .unwrap();
+        add(&pool, "example.com", "/api/admin", "admin", false)
+            .await
+            .unwrap();
+
+        let app_for = |path: &'static str| {
+            let pool = pool.clone();
+            async move {
+                resolve(&pool, "Example.com:443", path)
+                    .await
+                    .unwrap()
+                    .map(|r| r.app_name)
+            }
+        };
+        assert_eq!(app_for("/").await.as_deref(), Some("web"));
+        assert_eq!(app_for("/apis").await.as_deref(), Some("web"));
+        assert_eq!(app_for("/api").await.as_deref(), Some("api"));
+        assert_eq!(app_for("/api/users").await.as_deref(), Some("api"));
+        assert_eq!(app_for("/api/admin/x").await.as_deref(), Some("admin"));
+
+        let other = resolve(&pool, "other.com", "/api").await.unwrap();
+        assert!(other.is_none());
+
+        let got = add(&pool, "example.com", "/api/", "web", false)
+            .await
+            .unwrap_err();
+        match got {
+            RouteError::RouteExists { ref app, .. } if app == "api" => {}
+            _ => panic!("Expected RouteExists, got: {:?}", got),
+        }
+
+        remove(&pool, "example.com", "/api").await.unwrap();
+        assert_eq!(app_for("/api/users").await.as_deref(), Some("web"));
+    }
+
+    #[test]
+    fn test_forwarded_path() {
+        let app = App::new("api").unwrap();
+        let route = Route::new("example.com", "/api", &app, true);
+        assert_eq!(forwarded_path(&route, "/api/users?page=2"), "/users?page=2");
+        assert_eq!(forwarded_path(&route, "/api"), "/");
+        assert_eq!(forwarded_path(&route, "/api?x=1"), "/?x=1");
+
+        let route = Route::new("example.com", "/api", &app, false);
+        assert_eq!(forwarded_path(&route, "/api/users"), "/api/users");
+
+        let route = Route::new("example.com", "/", &app, true);
+        assert_eq!(forwarded_path(&route, "/users"), "/users");
+    }
+}
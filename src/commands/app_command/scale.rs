@@ -0,0 +1,132 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::config;
+use crate::db;
+use crate::models::{App, Instance};
+use crate::supervisor::SUPERVISOR;
+
+/// Most instances a single app may run
+pub const MAX_INSTANCES: u32 = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScaleError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("Invalid instance count: {0}. Apps run between 1 and {MAX_INSTANCES} instances.")]
+    InvalidInstances(u32),
+    #[error("Failed to apply instance count: {0}")]
+    SupervisorError(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+    #[error("Config error: {0}")]
+    ConfigError(#[from] crate::config::ConfigError),
+}
+
+type Result<T> = std::result::Result<T, ScaleError>;
+
+/// Set how many instances of an app to run, giving each new instance a port
+/// of its own. A running app is scaled straight away.
+#[instrument(skip(pool))]
+pub async fn execute(pool: &Pool<Sqlite>, app_name: &str, instances: u32) -> Result<App> {
+    if !(1..=MAX_INSTANCES).contains(&instances) {
+        return Err(ScaleError::InvalidInstances(instances));
+    }
+
+    let mut app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| ScaleError::AppNotFound(app_name.to_string()))?;
+
+    // The first instance uses the app's port, the rest have their own
+    let extra = db::instances::get_by_app_id(pool, &app.id).await?;
+    if extra.len() as u32 >= instances {
+        db::instances::delete_from(pool, &app.id, instances).await?;
+    } else {
+        for index in extra.len() as u32 + 1..instances {
+            let port = config::get_next_available_port(pool).await?;
+            let instance = Instance {
+                app_id: app.id.clone(),
+                index,
+                port,
+            };
+            db::instances::save(pool, &instance).await?;
+        }
+    }
+
+    app.instances = instances;
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    info!("Scaled app '{}' to {} instance(s)", app_name, instances);
+
+    if let Some(supervisor) = SUPERVISOR.get() {
+        if supervisor.is_app_running(app_name) {
+            supervisor
+                .scale_app(app_name)
+                .await
+                .map_err(|e| ScaleError::SupervisorError(e.to_string()))?;
+        }
+    }
+
+    Ok(app)
+}
+
+/// Ports of all instances of an app, in instance order
+#[instrument(skip(pool, app))]
+pub async fn ports(pool: &Pool<Sqlite>, app: &App) -> Result<Vec<u16>> {
+    let Some(port) = app.port else {
+        return Ok(vec![]);
+    };
+
+    let extra = db::instances::get_by_app_id(pool, &app.id).await?;
+    Ok(std::iter::once(port)
+        .chain(extra.into_iter().map(|instance| instance.port))
+        .take(app.instances as usize)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_scale_allocates_ports() {
+        let pool = get_test_pool().await;
+        let web = App::new("web").unwrap().with_port(8000);
+        db::apps::save(&pool, &web).await.unwrap();
+        let api = App::new("api").unwrap().with_port(8001);
+        db::apps::save(&pool, &api).await.unwrap();
+
+        let web = execute(&pool, "web", 3).await.unwrap();
+        assert_eq!(web.instances, 3);
+        assert_eq!(ports(&pool, &web).await.unwrap(), vec![8000, 8002, 8003]);
+
+        // New ports skip those taken by other instances
+        let api = execute(&pool, "api", 2).await.unwrap();
+        assert_eq!(ports(&pool, &api).await.unwrap(), vec![8001, 8004]);
+
+        let web = execute(&pool, "web", 1).await.unwrap();
+        assert_eq!(ports(&pool, &web).await.unwrap(), vec![8000]);
+        assert!(db::instances::get_by_app_id(&pool, &web.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_instances() {
+        let pool = get_test_pool().await;
+        for instances in [0, MAX_INSTANCES + 1] {
+            assert!(matches!(
+                execute(&pool, "web", instances).await,
+                Err(ScaleError::InvalidInstances(_))
+            ));
+        }
+        assert!(matches!(
+            execute(&pool, "web", 2).await,
+            Err(ScaleError::AppNotFound(_))
+        ));
+    }
+}
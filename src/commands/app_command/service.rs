@@ -0,0 +1,175 @@
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+use std::ops::RangeInclusive;
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::{App, HealthCheck, HealthCheckType, Service, ServiceType};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServiceError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("No free port for TCP services between {0} and {1}")]
+    NoFreePort(u16, u16),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, ServiceError>;
+
+/// Declare whether an app serves HTTP through the proxy or raw TCP. A TCP
+/// service is given the first free port of `ports` on `host`, which it
+/// keeps for as long as it stays TCP.
+#[instrument(skip(pool))]
+pub async fn set(
+    pool: &Pool<Sqlite>,
+    app_name: &str,
+    service_type: ServiceType,
+    host: &str,
+    ports: RangeInclusive<u16>,
+) -> Result<App> {
+    let mut app = db::apps::get_by_name(pool, app_name)
+        .await?
+        .ok_or_else(|| ServiceError::AppNotFound(app_name.to_string()))?;
+
+    app.service = match (service_type, app.service) {
+        (ServiceType::Http, _) => Service::Http,
+        (ServiceType::Tcp, Service::Tcp { listen_port }) => Service::Tcp { listen_port },
+        (ServiceType::Tcp, Service::Http) => Service::Tcp {
+            listen_port: free_port(pool, host, ports).await?,
+        },
+    };
+
+    // An HTTP health check would fail an app that does not speak HTTP
+    if let Some(health_check) = &mut app.health_check {
+        health_check.check_type = match (service_type, &health_check.check_type) {
+            (ServiceType::Tcp, HealthCheckType::HttpGet { .. }) => HealthCheckType::TcpConnect,
+            (ServiceType::Http, HealthCheckType::TcpConnect) => HealthCheck::default().check_type,
+            (_, check_type) => check_type.clone(),
+        };
+    }
+
+    app.updated_at = Utc::now();
+    db::apps::save(pool, &app).await?;
+
+    match app.service {
+        Service::Http => info!("App '{}' serves HTTP", app_name),
+        Service::Tcp { listen_port } => {
+            info!("App '{}' serves TCP on port {}", app_name, listen_port)
+        }
+    }
+
+    Ok(app)
+}
+
+/// First port of `ports` that no other app uses and nothing listens on yet
+async fn free_port(pool: &Pool<Sqlite>, host: &str, ports: RangeInclusive<u16>) -> Result<u16> {
+    let taken = db::apps::get_used_ports(pool).await?;
+
+    ports
+        .clone()
+        .filter(|port| !taken.contains(port))
+        .find(|port| std::net::TcpListener::bind((host, *port)).is_ok())
+        .ok_or_else(|| ServiceError::NoFreePort(*ports.start(), *ports.end()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+    use crate::models::Instance;
+
+    const PORTS: RangeInclusive<u16> = 42710..=42719;
+
+    #[tokio::test]
+    async fn test_set_service() {
+        let pool = get_test_pool().await;
+        for name in ["db", "cache"] {
+            db::apps::save(&pool, &App::new(name).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let db_app = set(&pool, "db", ServiceType::Tcp, "127.0.0.1", PORTS)
+            .await
+            .unwrap();
+        let port = db_app.service.listen_port().unwrap();
+        assert!(PORTS.contains(&port));
+        assert!(matches!(
+            db_app.health_check.unwrap().check_type,
+            HealthCheckType::TcpConnect
+        ));
+
+        // Each service has a port of its own, kept when declared again
+        let cache = set(&pool, "cache", ServiceType::Tcp, "127.0.0.1", PORTS)
+            .await
+            .unwrap();
+        assert_ne!(cache.service.listen_port(), Some(port));
+        let db_app = set(&pool, "db", ServiceType::Tcp, "127.0.0.1", PORTS)
+            .await
+            .unwrap();
+        assert_eq!(db_app.service.listen_port(), Some(port));
+
+        let db_app = set(&pool, "db", ServiceType::Http, "127.0.0.1", PORTS)
+            .await
+            .unwrap();
+        assert_eq!(db_app.service, Service::Http);
+        assert!(matches!(
+            db_app.health_check.unwrap().check_type,
+            HealthCheckType::HttpGet { .. }
+        ));
+        let saved = db::apps::get_by_name(&pool, "db").await.unwrap().unwrap();
+        assert_eq!(saved.service, Service::Http);
+    }
+
+    #[tokio::test]
+    async fn test_no_free_port() {
+        let pool = get_test_pool().await;
+        db::apps::save(&pool, &App::new("db").unwrap())
+            .await
+            .unwrap();
+
+        // Ports something else listens on are skipped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(matches!(
+            set(&pool, "db", ServiceType::Tcp, "127.0.0.1", port..=port).await,
+            Err(ServiceError::NoFreePort(..))
+        ));
+
+        assert!(matches!(
+            set(&pool, "missing", ServiceType::Tcp, "127.0.0.1", PORTS).await,
+            Err(ServiceError::AppNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ports_are_not_shared() {
+        let pool = get_test_pool().await;
+        let web = App::new("web").unwrap();
+        let mut cache = App::new("cache").unwrap();
+        cache.service = Service::Tcp { listen_port: 42721 };
+        for app in [&web, &cache, &App::new("db").unwrap()] {
+            db::apps::save(&pool, app).await.unwrap();
+        }
+        let instance = Instance {
+            app_id: web.id.clone(),
+            index: 1,
+            port: 42720,
+        };
+        db::instances::save(&pool, &instance).await.unwrap();
+
+        // A TCP service skips the ports of instances and other services
+        let db_app = set(&pool, "db", ServiceType::Tcp, "127.0.0.1", 42720..=42722)
+            .await
+            .unwrap();
+        assert_eq!(db_app.service.listen_port(), Some(42722));
+
+        // and app ports skip the public ports of TCP services
+        cache.service = Service::Tcp { listen_port: 8000 };
+        db::apps::save(&pool, &cache).await.unwrap();
+        let port = crate::config::get_next_available_port(&pool).await.unwrap();
+        assert_eq!(port, 8001);
+    }
+}
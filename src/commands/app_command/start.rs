@@ -2,7 +2,7 @@ use sqlx::{Pool, Sqlite};
 use tracing::{info, instrument};
 
 use crate::db;
-use crate::models::ProcessHistory;
+use crate::models::{App, ProcessHistory};
 use crate::providers::{Handle, Provider};
 
 #[derive(Debug, PartialEq, thiserror::Error)]
@@ -74,6 +74,29 @@ pub async fn execute<H: Handle>(
     Ok(handle)
 }
 
+/// Start another process of an app that is already running, listening on
+/// `port` instead of the app's own port
+#[instrument(skip(app, provider))]
+pub async fn start_instance<H: Handle>(
+    app: &App,
+    port: u16,
+    provider: impl Provider<Handle = H>,
+) -> Result<H> {
+    let handle = provider
+        .start(&app.with_port(port))
+        .await
+        .map_err(|e| StartError::AppStartFailed(e.to_string()))?;
+
+    info!(
+        "Started instance of app '{}' on port {} with PID {}",
+        app.name,
+        port,
+        handle.id()
+    );
+
+    Ok(handle)
+}
+
 #[cfg(test)]
 mod test {
     use crate::db::apps;
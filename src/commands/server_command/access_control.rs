@@ -0,0 +1,160 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use hyper::header::AUTHORIZATION;
+use hyper::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use super::forwarding;
+use crate::models::AppAccess;
+
+/// Credentials remembered as verified before the cache starts over
+const MAX_VERIFIED: usize = 1_000;
+
+/// Why a request was kept from an app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// The client's address is not on the app's allowlist
+    Forbidden,
+    /// Basic auth credentials are missing or wrong
+    Unauthorized,
+}
+
+/// Checks requests against apps' access rules. Password hashes are slow to
+/// verify on purpose, so credentials that matched are remembered.
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    verified: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl AccessControl {
+    pub async fn check(
+        self: &Arc<Self>,
+        access: &AppAccess,
+        headers: &HeaderMap,
+        client_ip: IpAddr,
+    ) -> Result<(), Denied> {
+        if !access.allow.is_empty() && !allows(&access.allow, client_ip) {
+            return Err(Denied::Forbidden);
+        }
+
+        if access.users.is_empty() {
+            return Ok(());
+        }
+        let (username, password) = basic_credentials(headers).ok_or(Denied::Unauthorized)?;
+        let user = access
+            .users
+            .iter()
+            .find(|user| user.username == username)
+            .ok_or(Denied::Unauthorized)?;
+
+        // The key covers the stored hash, so a changed password is verified anew
+        let key: [u8; 32] = Sha256::new()
+            .chain_update(&user.password_hash)
+            .chain_update([0])
+            .chain_update(&password)
+            .finalize()
+            .into();
+        if self.verified.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+
+        let user = user.clone();
+        let valid = tokio::task::spawn_blocking(move || user.verify(&password))
+            .await
+            .unwrap_or(false);
+        if !valid {
+            return Err(Denied::Unauthorized);
+        }
+
+        let mut verified = self.verified.lock().unwrap();
+        if verified.len() >= MAX_VERIFIED {
+            verified.clear();
+        }
+        verified.insert(key);
+        Ok(())
+    }
+}
+
+/// Whether an address is in any of a list of stored networks
+pub fn allows(networks: &[String], ip: IpAddr) -> bool {
+    let ip = ip.to_canonical();
+    networks.iter().any(|network| {
+        forwarding::parse_network(network).is_ok_and(|network| network.contains(&ip))
+    })
+}
+
+/// Username and password of an `Authorization: Basic` header
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::BasicAuthUser;
+    use hyper::header::HeaderValue;
+
+    fn basic(credentials: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = format!("Basic {}", STANDARD.encode(credentials));
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&value).unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let control = Arc::new(AccessControl::default());
+        let access = AppAccess {
+            allow: vec!["10.0.0.0/8".to_string(), "2001:db8::/32".to_string()],
+            ..Default::default()
+        };
+        let check = |ip: &str| {
+            let control = Arc::clone(&control);
+            let access = access.clone();
+            let ip: IpAddr = ip.parse().unwrap();
+            async move { control.check(&access, &HeaderMap::new(), ip).await }
+        };
+
+        assert_eq!(check("10.1.2.3").await, Ok(()));
+        assert_eq!(check("::ffff:10.1.2.3").await, Ok(()));
+        assert_eq!(check("2001:db8::5").await, Ok(()));
+        assert_eq!(check("192.168.1.1").await, Err(Denied::Forbidden));
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let control = Arc::new(AccessControl::default());
+        let access = AppAccess {
+            users: vec![BasicAuthUser::new("alice", "s3cret:with:colons")],
+            ..Default::default()
+        };
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+
+        for headers in [
+            HeaderMap::new(),
+            basic("alice:wrong"),
+            basic("bob:s3cret:with:colons"),
+        ] {
+            assert_eq!(
+                control.check(&access, &headers, ip).await,
+                Err(Denied::Unauthorized)
+            );
+        }
+
+        // Verified once, then remembered
+        let headers = basic("alice:s3cret:with:colons");
+        assert_eq!(control.check(&access, &headers, ip).await, Ok(()));
+        assert_eq!(control.verified.lock().unwrap().len(), 1);
+        assert_eq!(control.check(&access, &headers, ip).await, Ok(()));
+    }
+}
@@ -0,0 +1,331 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::StreamExt;
+use hyper::header::USER_AGENT;
+use hyper::{Body, Request, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::forwarding::Forwarded;
+use crate::config::{self, AccessLogConfig, AccessLogFormat};
+
+/// Entries waiting to be written before new ones are dropped
+const QUEUE_SIZE: usize = 10_000;
+
+/// One request forwarded to an app
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    /// When the request arrived
+    pub timestamp: DateTime<Utc>,
+    pub host: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Size of the response body sent to the client
+    pub bytes: u64,
+    /// Milliseconds until the app's response headers arrived, if the
+    /// request reached the app at all
+    pub upstream_latency_ms: Option<u64>,
+    pub client_ip: IpAddr,
+    pub user_agent: Option<String>,
+    /// `X-Request-Id` passed to the app
+    pub request_id: String,
+}
+
+impl AccessLogEntry {
+    /// Start an entry for a request as it arrives, before it is rewritten
+    /// for the app
+    pub fn new(req: &Request<Body>, host: &str, forwarded: &Forwarded) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            host: host.to_string(),
+            method: req.method().to_string(),
+            path: req
+                .uri()
+                .path_and_query()
+                .map_or("/", |p| p.as_str())
+                .to_string(),
+            status: 0,
+            bytes: 0,
+            upstream_latency_ms: None,
+            client_ip: forwarded.client_ip,
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|ua| ua.to_str().ok())
+                .map(str::to_string),
+            request_id: forwarded.request_id.clone(),
+        }
+    }
+
+    /// The entry as a single line, without the trailing newline
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Json => {
+                serde_json::to_string(self).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+            }
+            AccessLogFormat::Text => format!(
+                "{} {} {} \"{} {}\" {} {} {} \"{}\" {}",
+                self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+                self.client_ip,
+                self.host,
+                self.method,
+                self.path,
+                self.status,
+                self.bytes,
+                self.upstream_latency_ms
+                    .map_or("-".to_string(), |ms| format!("{}ms", ms)),
+                self.user_agent.as_deref().unwrap_or("-"),
+                self.request_id,
+            ),
+        }
+    }
+}
+
+/// Writes access log entries for all apps on a background thread, so
+/// requests never wait on the disk
+#[derive(Debug)]
+pub struct AccessLog {
+    tx: Option<mpsc::Sender<(String, AccessLogEntry)>>,
+}
+
+impl AccessLog {
+    /// Start the writer, or a log that records nothing if disabled
+    pub fn start(config: &AccessLogConfig) -> Self {
+        if !config.enabled {
+            return Self::disabled();
+        }
+
+        let (tx, mut rx) = mpsc::channel::<(String, AccessLogEntry)>(QUEUE_SIZE);
+        let format = config.format;
+        let mut files = LogFiles::new(config.max_size_mb * 1024 * 1024, config.max_files);
+        std::thread::spawn(move || {
+            while let Some((app_name, entry)) = rx.blocking_recv() {
+                let result = config::get_app_access_log_path(&app_name)
+                    .map_err(|e| io::Error::other(e.to_string()))
+                    .and_then(|path| files.append(&path, &entry.format(format)));
+                if let Err(e) = result {
+                    error!("Failed to write access log for app '{}': {}", app_name, e);
+                }
+            }
+        });
+
+        Self { tx: Some(tx) }
+    }
+
+    pub fn disabled() -> Self {
+        Self { tx: None }
+    }
+
+    /// Queue an entry for an app's access log. Entries are dropped rather
+    /// than slowing down requests when the writer falls behind.
+    pub fn record(&self, app_name: &str, entry: AccessLogEntry) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if tx.try_send((app_name.to_string(), entry)).is_err() {
+            warn!(
+                "Access log queue is full, dropping entry for app '{}'",
+                app_name
+            );
+        }
+    }
+
+    /// Record a response once its body has been sent, so the entry has the
+    /// number of bytes the client received
+    pub fn track(
+        self: &Arc<Self>,
+        app_name: &str,
+        mut entry: AccessLogEntry,
+        resp: Response<Body>,
+        upstream_latency: Option<Duration>,
+    ) -> Response<Body> {
+        if self.tx.is_none() {
+            return resp;
+        }
+
+        entry.status = resp.status().as_u16();
+        entry.bytes = 0;
+        entry.upstream_latency_ms = upstream_latency.map(|latency| latency.as_millis() as u64);
+        let mut pending = PendingEntry {
+            log: Arc::clone(self),
+            app_name: app_name.to_string(),
+            entry: Some(entry),
+        };
+
+        let (parts, body) = resp.into_parts();
+        let body = body.map(move |chunk| {
+            if let (Ok(chunk), Some(entry)) = (&chunk, pending.entry.as_mut()) {
+                entry.bytes += chunk.len() as u64;
+            }
+            chunk
+        });
+        Response::from_parts(parts, Body::wrap_stream(body))
+    }
+}
+
+/// An entry recorded when the response body is done or abandoned
+struct PendingEntry {
+    log: Arc<AccessLog>,
+    app_name: String,
+    entry: Option<AccessLogEntry>,
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.log.record(&self.app_name, entry);
+        }
+    }
+}
+
+/// Open log files, rotated once they reach `max_bytes`
+struct LogFiles {
+    max_bytes: u64,
+    max_files: usize,
+    open: HashMap<PathBuf, (File, u64)>,
+}
+
+impl LogFiles {
+    fn new(max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            max_bytes,
+            max_files,
+            open: HashMap::new(),
+        }
+    }
+
+    fn append(&mut self, path: &Path, line: &str) -> io::Result<()> {
+        // Reopen logs that were moved or deleted along with their app
+        if !path.exists() {
+            self.open.remove(path);
+        }
+        if !self.open.contains_key(path) {
+            self.open.insert(path.to_path_buf(), open_log(path)?);
+        }
+
+        let len = line.len() as u64 + 1;
+        if self.open[path].1 > 0 && self.open[path].1 + len > self.max_bytes {
+            self.open.remove(path);
+            rotate_files(path, self.max_files)?;
+            self.open.insert(path.to_path_buf(), open_log(path)?);
+        }
+
+        let (file, size) = self.open.get_mut(path).unwrap();
+        writeln!(file, "{}", line)?;
+        *size += len;
+        Ok(())
+    }
+}
+
+/// Open a log for appending, along with its current size
+fn open_log(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Shift `path.1`.. up by one, dropping the oldest, and move `path` to
+/// `path.1`. Without any rotated files to keep, the log starts over.
+fn rotate_files(path: &Path, max_files: usize) -> io::Result<()> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+
+    let _ = fs::remove_file(rotated(max_files));
+    for n in (1..max_files).rev() {
+        if rotated(n).exists() {
+            fs::rename(rotated(n), rotated(n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/items?page=2")
+            .header(USER_AGENT, "curl/8.5.0")
+            .body(Body::empty())
+            .unwrap();
+        let forwarded = Forwarded {
+            client_ip: "10.0.0.7".parse().unwrap(),
+            request_id: "4f1c2b7e".to_string(),
+        };
+        let mut entry = AccessLogEntry::new(&req, "web.example.com", &forwarded);
+        entry.timestamp = "2026-10-18T12:00:00.250Z".parse().unwrap();
+        entry.status = 201;
+        entry.bytes = 512;
+        entry.upstream_latency_ms = Some(12);
+        entry
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Text),
+            "2026-10-18T12:00:00.250Z 10.0.0.7 web.example.com \"POST /api/items?page=2\" 201 512 12ms \"curl/8.5.0\" 4f1c2b7e"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&entry().format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["path"], "/api/items?page=2");
+        assert_eq!(json["status"], 201);
+        assert_eq!(json["upstream_latency_ms"], 12);
+        assert_eq!(json["client_ip"], "10.0.0.7");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let rotated = |n: usize| dir.path().join(format!("access.log.{}", n));
+
+        // Room for two 9 byte lines per file
+        let mut files = LogFiles::new(20, 2);
+        for n in 1..=7 {
+            files.append(&path, &format!("request {}", n)).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "request 7\n");
+        assert_eq!(
+            fs::read_to_string(rotated(1)).unwrap(),
+            "request 5\nrequest 6\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated(2)).unwrap(),
+            "request 3\nrequest 4\n"
+        );
+        assert!(!rotated(3).exists());
+    }
+
+    #[tokio::test]
+    async fn test_track_counts_body_bytes() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let log = Arc::new(AccessLog { tx: Some(tx) });
+
+        let resp = Response::new(Body::from("hello world"));
+        let resp = log.track("web", entry(), resp, Some(Duration::from_millis(3)));
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "hello world");
+
+        let (app_name, entry) = rx.recv().await.unwrap();
+        assert_eq!(app_name, "web");
+        assert_eq!(entry.status, 200);
+        assert_eq!(entry.bytes, 11);
+        assert_eq!(entry.upstream_latency_ms, Some(3));
+    }
+}
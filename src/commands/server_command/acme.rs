@@ -0,0 +1,720 @@
+//! Certificate issuance over ACME (RFC 8555) using HTTP-01 challenges, which
+//! the proxy answers from [`AcmeChallenges`]. Issued certificates are written
+//! into the certs dir, where the [`CertStore`] picks them up.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{error, info, instrument, warn};
+
+use super::tls::{self, CertStore, CERT_FILE, KEY_FILE};
+use crate::config::{get_acme_dir, AcmeConfig, TlsConfig};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AcmeError {
+    #[error("Request to ACME server failed: {0}")]
+    HttpError(#[from] reqwest::Error),
+    #[error("ACME server responded {status}: {detail}")]
+    ServerError { status: u16, detail: String },
+    #[error("ACME server response is missing {0}")]
+    InvalidResponse(&'static str),
+    #[error("Cannot obtain a certificate for {0}: {1}")]
+    InvalidDomain(String, &'static str),
+    #[error("Validation of {domain} failed: {detail}")]
+    ValidationFailed { domain: String, detail: String },
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+    #[error("Failed to read or write {0}: {1}")]
+    IoError(PathBuf, std::io::Error),
+    #[error("Invalid account key: {0}")]
+    KeyError(String),
+    #[error("Failed to generate certificate request: {0}")]
+    CsrError(#[from] rcgen::RcgenError),
+    #[error("Config error: {0}")]
+    ConfigError(#[from] crate::config::ConfigError),
+}
+
+type Result<T> = std::result::Result<T, AcmeError>;
+
+/// Path prefix the ACME server fetches HTTP-01 key authorizations from
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+const BAD_NONCE: &str = "urn:ietf:params:acme:error:badNonce";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 60;
+
+/// Key authorizations of pending HTTP-01 challenges, by token
+#[derive(Debug, Default)]
+pub struct AcmeChallenges {
+    tokens: RwLock<HashMap<String, String>>,
+}
+
+impl AcmeChallenges {
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.tokens.read().unwrap().get(token).cloned()
+    }
+
+    pub(super) fn insert(&self, token: &str, key_authorization: String) {
+        self.tokens
+            .write()
+            .unwrap()
+            .insert(token.to_string(), key_authorization);
+    }
+
+    fn remove(&self, token: &str) {
+        self.tokens.write().unwrap().remove(token);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// Account registered with an ACME server, stored next to its key
+#[derive(Debug, Serialize, Deserialize)]
+struct AccountInfo {
+    directory_url: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+    error: Option<Problem>,
+}
+
+/// Error document returned by ACME servers (RFC 7807)
+#[derive(Debug, Default, Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+/// Client for one ACME account on one directory
+pub struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    /// Account URL, used as the key ID once the account is registered
+    account_url: Option<String>,
+    nonce: Mutex<Option<String>>,
+    rng: SystemRandom,
+}
+
+impl AcmeClient {
+    /// Connect to the configured directory and register the account, or
+    /// reuse the account stored for that directory in `account_dir`
+    #[instrument(skip(config))]
+    pub async fn connect(config: &AcmeConfig, account_dir: &Path) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .danger_accept_invalid_certs(config.insecure)
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let directory: Directory = check(http.get(&config.directory_url).send().await?)
+            .await?
+            .json()
+            .await?;
+
+        let rng = SystemRandom::new();
+        let key = load_or_create_key(&account_dir.join(KEY_FILE), &rng)?;
+
+        let mut client = Self {
+            http,
+            directory,
+            key,
+            account_url: None,
+            nonce: Mutex::new(None),
+            rng,
+        };
+
+        let info_path = account_dir.join("account.json");
+        let stored = fs::read_to_string(&info_path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<AccountInfo>(&s).ok())
+            .filter(|info| info.directory_url == config.directory_url);
+        client.account_url = Some(match stored {
+            Some(info) => info.url,
+            None => {
+                let url = client.register(config.email.as_deref()).await?;
+                let info = AccountInfo {
+                    directory_url: config.directory_url.clone(),
+                    url: url.clone(),
+                };
+                write_file(&info_path, &serde_json::to_string_pretty(&info).unwrap())?;
+                info!("Registered ACME account {}", url);
+                url
+            }
+        });
+
+        Ok(client)
+    }
+
+    /// Create the account, or look up the existing one for this key
+    async fn register(&self, email: Option<&str>) -> Result<String> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = email {
+            payload["contact"] = json!([format!("mailto:{}", email)]);
+        }
+
+        let response = self
+            .post(&self.directory.new_account, Some(&payload))
+            .await?;
+        location(&response)
+    }
+
+    /// Obtain a certificate for `domain`, returning the PEM certificate chain
+    /// and private key
+    #[instrument(skip(self, challenges))]
+    pub async fn issue(
+        &self,
+        domain: &str,
+        challenges: &AcmeChallenges,
+    ) -> Result<(String, String)> {
+        validate_domain(domain)?;
+
+        let payload = json!({ "identifiers": [{ "type": "dns", "value": domain }] });
+        let response = self.post(&self.directory.new_order, Some(&payload)).await?;
+        let order_url = location(&response)?;
+        let order: Order = response.json().await?;
+
+        for authorization_url in &order.authorizations {
+            self.authorize(authorization_url, challenges).await?;
+        }
+
+        let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let cert_key = rcgen::Certificate::from_params(params)?;
+        let csr = URL_SAFE_NO_PAD.encode(cert_key.serialize_request_der()?);
+        self.post(&order.finalize, Some(&json!({ "csr": csr })))
+            .await?;
+
+        let order = self
+            .poll(
+                &order_url,
+                "certificate order",
+                |order: &Order| match order.status.as_str() {
+                    "valid" => Some(Ok(())),
+                    "invalid" => Some(Err(order
+                        .error
+                        .as_ref()
+                        .map_or("order is invalid".to_string(), |p| p.detail.clone()))),
+                    _ => None,
+                },
+            )
+            .await
+            .map_err(|e| with_domain(e, domain))?;
+
+        let certificate_url = order
+            .certificate
+            .ok_or(AcmeError::InvalidResponse("certificate URL"))?;
+        let chain = self.post(&certificate_url, None).await?.text().await?;
+
+        Ok((chain, cert_key.serialize_private_key_pem()))
+    }
+
+    /// Answer the HTTP-01 challenge of an authorization and wait for the
+    /// server to validate it
+    async fn authorize(&self, url: &str, challenges: &AcmeChallenges) -> Result<()> {
+        let authorization: Authorization = self.post(url, None).await?.json().await?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+
+        let domain = authorization.identifier.value;
+        let challenge = authorization
+            .challenges
+            .into_iter()
+            .find(|c| c.kind == "http-01")
+            .ok_or(AcmeError::InvalidDomain(
+                domain.clone(),
+                "the server offered no HTTP-01 challenge",
+            ))?;
+
+        let key_authorization = format!("{}.{}", challenge.token, self.thumbprint());
+        challenges.insert(&challenge.token, key_authorization);
+
+        let result = async {
+            self.post(&challenge.url, Some(&json!({}))).await?;
+            self.poll(
+                url,
+                "authorization",
+                |authorization: &Authorization| match authorization.status.as_str() {
+                    "valid" => Some(Ok(())),
+                    "pending" | "processing" => None,
+                    status => Some(Err(authorization
+                        .challenges
+                        .iter()
+                        .find_map(|c| c.error.as_ref())
+                        .map_or(format!("authorization is {}", status), |p| p.detail.clone()))),
+                },
+            )
+            .await
+        }
+        .await;
+
+        challenges.remove(&challenge.token);
+        result.map(|_| ()).map_err(|e| with_domain(e, &domain))
+    }
+
+    /// POST-as-GET `url` until `done` returns a result
+    async fn poll<T, F>(&self, url: &str, what: &str, done: F) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+        F: Fn(&T) -> Option<std::result::Result<(), String>>,
+    {
+        for _ in 0..POLL_ATTEMPTS {
+            let resource: T = self.post(url, None).await?.json().await?;
+            match done(&resource) {
+                Some(Ok(())) => return Ok(resource),
+                Some(Err(detail)) => {
+                    return Err(AcmeError::ValidationFailed {
+                        domain: String::new(),
+                        detail,
+                    })
+                }
+                None => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+
+        Err(AcmeError::Timeout(what.to_string()))
+    }
+
+    /// Send a signed request, or a POST-as-GET when `payload` is `None`,
+    /// retrying when the server rejects the nonce
+    async fn post(&self, url: &str, payload: Option<&Value>) -> Result<reqwest::Response> {
+        let mut attempts = 0;
+        loop {
+            let nonce = self.nonce().await?;
+            let body = self.sign(url, &nonce, payload)?;
+            let response = self
+                .http
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await?;
+            self.save_nonce(&response);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let status = response.status().as_u16();
+            let problem: Problem = response.json().await.unwrap_or_default();
+            attempts += 1;
+            if problem.kind == BAD_NONCE && attempts < 3 {
+                continue;
+            }
+            return Err(AcmeError::ServerError {
+                status,
+                detail: problem.detail,
+            });
+        }
+    }
+
+    /// A fresh nonce, reusing the one from the last response if unused
+    async fn nonce(&self) -> Result<String> {
+        if let Some(nonce) = self.nonce.lock().unwrap().take() {
+            return Ok(nonce);
+        }
+
+        let response = check(self.http.head(&self.directory.new_nonce).send().await?).await?;
+        replay_nonce(&response).ok_or(AcmeError::InvalidResponse("Replay-Nonce header"))
+    }
+
+    fn save_nonce(&self, response: &reqwest::Response) {
+        if let Some(nonce) = replay_nonce(response) {
+            *self.nonce.lock().unwrap() = Some(nonce);
+        }
+    }
+
+    /// JWS in flattened JSON serialization, identifying the account by its
+    /// URL once registered and by its public key before that
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<Value> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.account_url {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = payload.map_or(String::new(), |p| URL_SAFE_NO_PAD.encode(p.to_string()));
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| AcmeError::KeyError("signing failed".to_string()))?;
+
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+
+    /// Public account key as a JWK, with members in the lexicographic order
+    /// RFC 7638 thumbprints require
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 followed by the x and y coordinates
+        let point = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    fn thumbprint(&self) -> String {
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.jwk().to_string().as_bytes());
+        URL_SAFE_NO_PAD.encode(digest.as_ref())
+    }
+}
+
+/// Error for a failed response that isn't signed, such as the directory
+async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status().as_u16();
+    let detail = response.text().await.unwrap_or_default();
+    Err(AcmeError::ServerError { status, detail })
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("Replay-Nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn location(response: &reqwest::Response) -> Result<String> {
+    response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or(AcmeError::InvalidResponse("Location header"))
+}
+
+fn with_domain(e: AcmeError, domain: &str) -> AcmeError {
+    match e {
+        AcmeError::ValidationFailed { detail, .. } => AcmeError::ValidationFailed {
+            domain: domain.to_string(),
+            detail,
+        },
+        e => e,
+    }
+}
+
+/// HTTP-01 can only validate a single, fully qualified host name
+pub fn validate_domain(domain: &str) -> Result<()> {
+    let reason = if domain.starts_with("*.") {
+        "wildcard domains cannot be validated over HTTP-01"
+    } else if domain.is_empty() || !domain.contains('.') {
+        "not a fully qualified domain name"
+    } else if !domain
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        "domain contains invalid characters"
+    } else {
+        return Ok(());
+    };
+
+    Err(AcmeError::InvalidDomain(domain.to_string(), reason))
+}
+
+/// Load the ECDSA P-256 account key, generating it on first use
+fn load_or_create_key(path: &Path, rng: &SystemRandom) -> Result<EcdsaKeyPair> {
+    let pkcs8 = if path.exists() {
+        let pem = fs::read(path).map_err(|e| AcmeError::IoError(path.to_path_buf(), e))?;
+        rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())
+            .map_err(|e| AcmeError::IoError(path.to_path_buf(), e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| AcmeError::KeyError(format!("no PKCS#8 key in {}", path.display())))?
+    } else {
+        let document = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, rng)
+            .map_err(|_| AcmeError::KeyError("key generation failed".to_string()))?;
+        write_file(path, &to_pem("PRIVATE KEY", document.as_ref()))?;
+        document.as_ref().to_vec()
+    };
+
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, rng)
+        .map_err(|e| AcmeError::KeyError(e.to_string()))
+}
+
+fn to_pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// Write a file readable only by the server's user, replacing it atomically
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let io_error = |e| AcmeError::IoError(path.to_path_buf(), e);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(io_error)?;
+    fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600)).map_err(io_error)?;
+    fs::rename(&tmp, path).map_err(io_error)
+}
+
+/// Whether the certificate at `cert_path` is missing, unreadable or expires
+/// within `renew_before`
+pub fn needs_certificate(cert_path: &Path, renew_before: Duration) -> bool {
+    let Ok(pem) = fs::read(cert_path) else {
+        return true;
+    };
+    let Ok((_, pem)) = x509_parser::pem::parse_x509_pem(&pem) else {
+        return true;
+    };
+    let Ok(cert) = pem.parse_x509() else {
+        return true;
+    };
+
+    let not_after = cert.validity().not_after.timestamp();
+    let renew_at = not_after - renew_before.as_secs() as i64;
+    chrono::Utc::now().timestamp() >= renew_at
+}
+
+/// Directory the account for `directory_url` is stored in, so switching
+/// between e.g. staging and production keeps both accounts
+fn account_dir(directory_url: &str) -> Result<PathBuf> {
+    let digest = ring::digest::digest(&ring::digest::SHA256, directory_url.as_bytes());
+    Ok(get_acme_dir()?.join(&hex::encode(digest.as_ref())[..16]))
+}
+
+/// Issue certificates for every configured domain that lacks one or is due
+/// for renewal, returning how many were issued
+#[instrument(skip_all)]
+pub async fn renew_certificates(
+    tls: &TlsConfig,
+    acme: &AcmeConfig,
+    challenges: &AcmeChallenges,
+) -> Result<usize> {
+    let certs_dir = tls.certs_dir()?;
+    let renew_before = Duration::from_secs(u64::from(acme.renew_before_days) * 24 * 60 * 60);
+    let due: Vec<&String> = acme
+        .domains
+        .iter()
+        .filter(|domain| {
+            let (cert_path, key_path) = (
+                certs_dir.join(domain).join(CERT_FILE),
+                certs_dir.join(domain).join(KEY_FILE),
+            );
+            // A pair left mismatched by an interrupted write is issued anew
+            needs_certificate(&cert_path, renew_before)
+                || tls::load_certified_key(&cert_path, &key_path).is_err()
+        })
+        .collect();
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let client = AcmeClient::connect(acme, &account_dir(&acme.directory_url)?).await?;
+    let mut issued = 0;
+    for domain in due {
+        match client.issue(domain, challenges).await {
+            Ok((chain, key)) => {
+                // The certificate goes first: the reloader skips the new
+                // certificate until its key is in place too, keeping the
+                // previous pair
+                let domain_dir = certs_dir.join(domain);
+                write_file(&domain_dir.join(CERT_FILE), &chain)?;
+                write_file(&domain_dir.join(KEY_FILE), &key)?;
+                info!("Issued certificate for {}", domain);
+                issued += 1;
+            }
+            Err(e) => error!("Failed to issue certificate for {}: {}", domain, e),
+        }
+    }
+
+    Ok(issued)
+}
+
+/// Issue and renew certificates in the background, loading new ones into
+/// `store` as soon as they are written
+pub fn spawn_manager(
+    store: Arc<CertStore>,
+    tls: TlsConfig,
+    acme: AcmeConfig,
+    challenges: Arc<AcmeChallenges>,
+) {
+    for domain in &acme.domains {
+        if let Err(e) = validate_domain(domain) {
+            warn!("{}", e);
+        }
+    }
+
+    let interval = Duration::from_secs(acme.check_interval_secs.max(60));
+    tokio::spawn(async move {
+        loop {
+            match renew_certificates(&tls, &acme, &challenges).await {
+                Ok(0) => {}
+                Ok(_) => {
+                    if let Err(e) = store.reload(&tls) {
+                        error!("Failed to load issued certificates: {}", e);
+                    }
+                }
+                Err(e) => error!("ACME certificate renewal failed: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+
+    fn test_client() -> AcmeClient {
+        let rng = SystemRandom::new();
+        let dir = tempfile::tempdir().unwrap();
+        let key = load_or_create_key(&dir.path().join(KEY_FILE), &rng).unwrap();
+        AcmeClient {
+            http: reqwest::Client::new(),
+            directory: Directory {
+                new_nonce: "https://acme.test/nonce".to_string(),
+                new_account: "https://acme.test/account".to_string(),
+                new_order: "https://acme.test/order".to_string(),
+            },
+            key,
+            account_url: None,
+            nonce: Mutex::new(None),
+            rng,
+        }
+    }
+
+    #[test]
+    fn test_account_key_persists() {
+        let rng = SystemRandom::new();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(KEY_FILE);
+
+        let first = load_or_create_key(&path, &rng).unwrap();
+        let second = load_or_create_key(&path, &rng).unwrap();
+        assert_eq!(first.public_key().as_ref(), second.public_key().as_ref());
+    }
+
+    #[test]
+    fn test_jws_signature_verifies() {
+        let mut client = test_client();
+        let payload = json!({ "identifiers": [] });
+
+        let jws = client
+            .sign("https://acme.test/order", "abc", Some(&payload))
+            .unwrap();
+        let protected: Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(jws["protected"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(protected["nonce"], "abc");
+        assert_eq!(protected["jwk"]["kty"], "EC");
+        assert!(protected.get("kid").is_none());
+
+        let message = format!(
+            "{}.{}",
+            jws["protected"].as_str().unwrap(),
+            jws["payload"].as_str().unwrap()
+        );
+        let signature = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, client.key.public_key().as_ref())
+            .verify(message.as_bytes(), &signature)
+            .unwrap();
+
+        client.account_url = Some("https://acme.test/acct/1".to_string());
+        let jws = client.sign("https://acme.test/order", "def", None).unwrap();
+        let protected: Value = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(jws["protected"].as_str().unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(protected["kid"], "https://acme.test/acct/1");
+        assert!(protected.get("jwk").is_none());
+        assert_eq!(jws["payload"], "");
+    }
+
+    #[test]
+    fn test_validate_domain() {
+        assert!(validate_domain("web.example.com").is_ok());
+        assert!(validate_domain("*.example.com").is_err());
+        assert!(validate_domain("localhost").is_err());
+        assert!(validate_domain("web/example.com").is_err());
+    }
+
+    #[test]
+    fn test_needs_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CERT_FILE);
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert!(needs_certificate(&path, day));
+
+        let mut params = rcgen::CertificateParams::new(vec!["example.com".to_string()]);
+        params.not_after = rcgen::date_time_ymd(2999, 1, 1);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        fs::write(&path, cert.serialize_pem().unwrap()).unwrap();
+        assert!(!needs_certificate(&path, 30 * day));
+
+        let mut params = rcgen::CertificateParams::new(vec!["example.com".to_string()]);
+        params.not_after = rcgen::date_time_ymd(2000, 1, 1);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        fs::write(&path, cert.serialize_pem().unwrap()).unwrap();
+        assert!(needs_certificate(&path, day));
+    }
+}
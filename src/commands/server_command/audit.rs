@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Sqlite};
+use tracing::{error, instrument};
+
+use crate::db;
+use crate::models::AuditEntry;
+
+/// Maximum number of entries returned by a single query
+pub const MAX_ENTRIES: i64 = 1000;
+
+/// Record an audit log entry. Failing to write the audit log never fails the
+/// action being audited, so errors are only logged.
+#[instrument(skip(pool, entry), fields(action = %entry.action, actor = %entry.actor))]
+pub async fn record(pool: &Pool<Sqlite>, entry: AuditEntry) {
+    if let Err(e) = db::audit_log::save(pool, &entry).await {
+        error!("Failed to write audit log entry: {}", e);
+    }
+}
+
+/// List audit log entries, newest first
+#[instrument(skip(pool))]
+pub async fn list(
+    pool: &Pool<Sqlite>,
+    app_name: Option<&str>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<AuditEntry>, db::DatabaseError> {
+    db::audit_log::get(pool, app_name, since, MAX_ENTRIES).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+    use crate::models::AuditResult;
+
+    #[tokio::test]
+    async fn test_record_redacts_secrets() {
+        let pool = get_test_pool().await;
+        let entry = AuditEntry::new("laptop", "set_env")
+            .with_app("web")
+            .with_parameters(serde_json::json!({ "key": "DATABASE_URL", "value": "secret" }))
+            .with_result(&Ok::<_, String>(()));
+        record(&pool, entry).await;
+
+        let entries = list(&pool, None, None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, "laptop");
+        assert_eq!(entries[0].result, AuditResult::Success);
+        assert_eq!(
+            entries[0].parameters,
+            Some(serde_json::json!({ "key": "DATABASE_URL", "value": "[REDACTED]" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_filters() {
+        let pool = get_test_pool().await;
+        record(&pool, AuditEntry::new("laptop", "start").with_app("web")).await;
+        record(
+            &pool,
+            AuditEntry::new("supervisor", "restart")
+                .with_app("api")
+                .with_result(&Err::<(), _>("health check failed")),
+        )
+        .await;
+
+        let entries = list(&pool, Some("api"), None).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].result, AuditResult::Failure);
+        assert_eq!(entries[0].message.as_deref(), Some("health check failed"));
+
+        let future = Utc::now() + chrono::Duration::hours(1);
+        assert!(list(&pool, None, Some(future)).await.unwrap().is_empty());
+        assert_eq!(list(&pool, None, None).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Spreads requests over the instances of each app. Each request goes to the
+/// instance with the fewest requests in flight, taking turns between
+/// instances that are equally busy.
+#[derive(Debug, Default)]
+pub struct Balancer {
+    apps: Mutex<HashMap<String, AppBalance>>,
+}
+
+#[derive(Debug, Default)]
+struct AppBalance {
+    /// Where the next search for the least busy instance starts
+    next: usize,
+    /// Requests in flight by instance port
+    in_flight: HashMap<u16, Arc<AtomicUsize>>,
+}
+
+/// A request in flight to an instance, counted until dropped
+#[derive(Debug)]
+pub struct Lease {
+    pub port: u16,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Balancer {
+    /// Pick one of `ports`, the instances currently in rotation
+    pub fn pick(&self, app_name: &str, ports: &[u16]) -> Option<Lease> {
+        if ports.is_empty() {
+            return None;
+        }
+
+        let mut apps = self.apps.lock().unwrap();
+        let app = apps.entry(app_name.to_string()).or_default();
+
+        // Forget instances that went away once their requests are done
+        app.in_flight
+            .retain(|port, count| ports.contains(port) || count.load(Ordering::Relaxed) > 0);
+
+        let start = app.next % ports.len();
+        app.next = app.next.wrapping_add(1);
+        let (port, in_flight) = (0..ports.len())
+            .map(|offset| ports[(start + offset) % ports.len()])
+            .map(|port| (port, Arc::clone(app.in_flight.entry(port).or_default())))
+            .min_by_key(|(_, count)| count.load(Ordering::Relaxed))?;
+
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(Lease { port, in_flight })
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_when_idle() {
+        let balancer = Balancer::default();
+        let ports = [8000, 8001, 8002];
+        let picked: Vec<u16> = (0..6)
+            .map(|_| balancer.pick("web", &ports).unwrap().port)
+            .collect();
+        assert_eq!(picked, vec![8000, 8001, 8002, 8000, 8001, 8002]);
+    }
+
+    #[test]
+    fn test_least_connections() {
+        let balancer = Balancer::default();
+        let ports = [8000, 8001];
+
+        let busy = balancer.pick("web", &ports).unwrap();
+        assert_eq!(busy.port, 8000);
+        let _busier = balancer.pick("web", &ports).unwrap();
+        let _busiest = balancer.pick("web", &ports).unwrap();
+
+        // 8000 has two requests in flight, 8001 one
+        assert_eq!(balancer.pick("web", &ports).unwrap().port, 8001);
+        drop(busy);
+        assert_eq!(balancer.pick("web", &ports).unwrap().port, 8000);
+    }
+
+    #[test]
+    fn test_only_given_ports() {
+        let balancer = Balancer::default();
+        assert!(balancer.pick("web", &[]).is_none());
+
+        let _lease = balancer.pick("web", &[8000, 8001]).unwrap();
+        for _ in 0..3 {
+            assert_eq!(balancer.pick("web", &[8001]).unwrap().port, 8001);
+        }
+    }
+}
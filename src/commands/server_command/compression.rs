@@ -0,0 +1,321 @@
+use async_compression::tokio::write::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+use async_compression::Level;
+use bytes::Bytes;
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+    ETAG, VARY,
+};
+use hyper::{Body, HeaderMap, Response, StatusCode};
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Encodings the proxy compresses with, in order of preference when a
+/// client accepts several equally
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    const ALL: [Encoding; 3] = [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Pick the encoding a client prefers from its `Accept-Encoding` header
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let mut weights = [None; Encoding::ALL.len()];
+    let mut wildcard = None;
+    for value in headers.get_all(ACCEPT_ENCODING) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or("").trim();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if coding == "*" {
+                wildcard = Some(q);
+            } else if let Some(i) = Encoding::ALL
+                .iter()
+                .position(|e| coding.eq_ignore_ascii_case(e.as_str()))
+            {
+                weights[i] = Some(q);
+            }
+        }
+    }
+
+    // Encodings not named are covered by `*`, and a weight of 0 refuses one
+    let mut best: Option<(Encoding, f32)> = None;
+    for (encoding, weight) in Encoding::ALL.into_iter().zip(weights) {
+        let Some(q) = weight.or(wildcard).filter(|q| *q > 0.0) else {
+            continue;
+        };
+        if best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Whether a response is worth compressing: a compressible type at least
+/// `min_bytes` long that the app has not encoded itself. Event streams are
+/// left alone so each event reaches the client as soon as it is sent.
+pub fn should_compress(resp: &Response<Body>, min_bytes: u64) -> bool {
+    let status = resp.status();
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || status == StatusCode::PARTIAL_CONTENT
+    {
+        return false;
+    }
+
+    let headers = resp.headers();
+    if headers.contains_key(CONTENT_ENCODING) {
+        return false;
+    }
+    let length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length < min_bytes) {
+        return false;
+    }
+
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(is_compressible)
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if essence == "text/event-stream" {
+        return false;
+    }
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-ndjson"
+                | "image/svg+xml"
+        )
+}
+
+/// Compress a response body as it streams, flushing after every chunk the
+/// app sends so that nothing is held back waiting for more data
+pub fn compress(resp: Response<Body>, encoding: Encoding) -> Response<Body> {
+    let (mut parts, body) = resp.into_parts();
+    let body = match encoding {
+        Encoding::Zstd => encode(body, ZstdEncoder::new(Vec::new()), ZstdEncoder::get_mut),
+        // The default quality is meant for static assets and far too slow
+        // for compressing on the fly
+        Encoding::Brotli => encode(
+            body,
+            BrotliEncoder::with_quality(Vec::new(), Level::Precise(4)),
+            BrotliEncoder::get_mut,
+        ),
+        Encoding::Gzip => encode(body, GzipEncoder::new(Vec::new()), GzipEncoder::get_mut),
+    };
+
+    let headers = &mut parts.headers;
+    headers.insert(
+        CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    headers.remove(CONTENT_LENGTH);
+    headers.remove(ACCEPT_RANGES);
+    headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    // The compressed body is no longer byte for byte what a strong ETag
+    // promises
+    if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+        if !etag.starts_with("W/") {
+            if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                headers.insert(ETAG, weak);
+            }
+        }
+    }
+
+    Response::from_parts(parts, body)
+}
+
+fn encode<E>(body: Body, encoder: E, output: fn(&mut E) -> &mut Vec<u8>) -> Body
+where
+    E: AsyncWrite + Unpin + Send + 'static,
+{
+    let stream = futures_util::stream::unfold(Some((body, encoder)), move |state| async move {
+        let (mut body, mut encoder) = state?;
+        loop {
+            let result = match body.data().await {
+                Some(Ok(chunk)) => encoder.write_all(&chunk).await.and(encoder.flush().await),
+                Some(Err(e)) => Err(io::Error::other(e)),
+                None => {
+                    let result = encoder.shutdown().await;
+                    let rest = Bytes::from(std::mem::take(output(&mut encoder)));
+                    return Some((result.map(|_| rest), None));
+                }
+            };
+            if let Err(e) = result {
+                return Some((Err(e), None));
+            }
+            let compressed = std::mem::take(output(&mut encoder));
+            if !compressed.is_empty() {
+                return Some((Ok(Bytes::from(compressed)), Some((body, encoder))));
+            }
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZstdDecoder};
+    use tokio::io::AsyncReadExt;
+
+    fn accepting(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    fn response(content_type: &str, body: &str) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, body.len())
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(&HeaderMap::new()), None);
+        assert_eq!(negotiate(&accepting("identity")), None);
+        assert_eq!(negotiate(&accepting("gzip")), Some(Encoding::Gzip));
+        assert_eq!(
+            negotiate(&accepting("gzip, deflate, br, zstd")),
+            Some(Encoding::Zstd)
+        );
+        assert_eq!(
+            negotiate(&accepting("gzip;q=1.0, br;q=0.8")),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(
+            negotiate(&accepting("*;q=0.5, zstd;q=0")),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(negotiate(&accepting("GZIP;q=0")), None);
+    }
+
+    #[test]
+    fn test_should_compress() {
+        let large = "a".repeat(2048);
+        assert!(should_compress(
+            &response("text/html; charset=utf-8", &large),
+            1024
+        ));
+        assert!(should_compress(
+            &response("application/problem+json", &large),
+            1024
+        ));
+        assert!(!should_compress(&response("text/html", "small"), 1024));
+        assert!(!should_compress(&response("image/png", &large), 1024));
+        assert!(!should_compress(
+            &response("text/event-stream", &large),
+            1024
+        ));
+
+        let mut encoded = response("text/html", &large);
+        encoded
+            .headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        assert!(!should_compress(&encoded, 1024));
+
+        // Streamed responses of unknown length are compressed
+        let mut streamed = response("application/json", "[]");
+        streamed.headers_mut().remove(CONTENT_LENGTH);
+        assert!(should_compress(&streamed, 1024));
+    }
+
+    #[test]
+    fn test_min_bytes() {
+        // Responses below the minimum size are left as they are
+        let small = "a".repeat(1023);
+        assert!(!should_compress(&response("text/plain", &small), 1024));
+        let large = "a".repeat(1024);
+        assert!(should_compress(&response("text/plain", &large), 1024));
+        assert!(!should_compress(&response("text/plain", &large), 4096));
+    }
+
+    #[tokio::test]
+    async fn test_compress_round_trip() {
+        let text = "hello compressed world ".repeat(200);
+        for encoding in Encoding::ALL {
+            let mut resp = response("text/plain", &text);
+            resp.headers_mut()
+                .insert(ETAG, HeaderValue::from_static("\"abc\""));
+            let resp = compress(resp, encoding);
+            assert_eq!(resp.headers()[CONTENT_ENCODING], encoding.as_str());
+            assert_eq!(resp.headers()[ETAG], "W/\"abc\"");
+            assert!(!resp.headers().contains_key(CONTENT_LENGTH));
+
+            let compressed = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+            assert!(compressed.len() < text.len());
+            let mut decoded = String::new();
+            match encoding {
+                Encoding::Zstd => ZstdDecoder::new(&compressed[..])
+                    .read_to_string(&mut decoded)
+                    .await
+                    .unwrap(),
+                Encoding::Brotli => BrotliDecoder::new(&compressed[..])
+                    .read_to_string(&mut decoded)
+                    .await
+                    .unwrap(),
+                Encoding::Gzip => GzipDecoder::new(&compressed[..])
+                    .read_to_string(&mut decoded)
+                    .await
+                    .unwrap(),
+            };
+            assert_eq!(decoded, text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compress_streams_chunks() {
+        let (mut sender, body) = Body::channel();
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+            .unwrap();
+        let mut body = compress(resp, Encoding::Gzip).into_body();
+
+        // Each chunk comes out without waiting for the next
+        sender.send_data(Bytes::from("{\"n\":1}\n")).await.unwrap();
+        assert!(!body.data().await.unwrap().unwrap().is_empty());
+        sender.send_data(Bytes::from("{\"n\":2}\n")).await.unwrap();
+        assert!(!body.data().await.unwrap().unwrap().is_empty());
+    }
+}
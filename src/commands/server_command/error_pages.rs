@@ -0,0 +1,126 @@
+use hyper::StatusCode;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+
+/// Key of the page served for errors without a page of their own
+const DEFAULT_PAGE: &str = "default";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ErrorPageError {
+    #[error("Invalid status code for error page: {0}")]
+    InvalidStatus(String),
+    #[error("Failed to read error page {path}: {source}")]
+    ReadError {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// HTML pages the proxy serves in place of its plain text error responses.
+/// Pages may use the placeholders `{{status}}`, `{{reason}}` and
+/// `{{message}}`.
+#[derive(Debug, Default)]
+pub struct ErrorPages {
+    pages: HashMap<u16, String>,
+    default: Option<String>,
+}
+
+impl ErrorPages {
+    /// Read the pages configured by status code, or `default` for any status
+    pub fn load(config: &BTreeMap<String, PathBuf>) -> Result<Self, ErrorPageError> {
+        let mut pages = Self::default();
+        for (key, path) in config {
+            let page =
+                std::fs::read_to_string(path).map_err(|source| ErrorPageError::ReadError {
+                    path: path.clone(),
+                    source,
+                })?;
+            if key == DEFAULT_PAGE {
+                pages.default = Some(page);
+                continue;
+            }
+            let status = key
+                .parse::<u16>()
+                .ok()
+                .filter(|status| (400..600).contains(status))
+                .ok_or_else(|| ErrorPageError::InvalidStatus(key.clone()))?;
+            pages.pages.insert(status, page);
+        }
+        Ok(pages)
+    }
+
+    /// The configured page for a status, if any
+    pub fn get(&self, status: StatusCode) -> Option<&str> {
+        self.pages
+            .get(&status.as_u16())
+            .or(self.default.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Fill in the placeholders of a page, escaping the values for HTML
+pub fn render(page: &str, status: StatusCode, message: &str) -> String {
+    page.replace("{{status}}", status.as_str())
+        .replace(
+            "{{reason}}",
+            &escape(status.canonical_reason().unwrap_or("Error")),
+        )
+        .replace("{{message}}", &escape(message))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let config = BTreeMap::from([
+            ("503".to_string(), write("503.html", "unavailable")),
+            ("default".to_string(), write("error.html", "error")),
+        ]);
+
+        let pages = ErrorPages::load(&config).unwrap();
+        assert_eq!(
+            pages.get(StatusCode::SERVICE_UNAVAILABLE),
+            Some("unavailable")
+        );
+        assert_eq!(pages.get(StatusCode::BAD_GATEWAY), Some("error"));
+        assert_eq!(ErrorPages::default().get(StatusCode::BAD_GATEWAY), None);
+
+        let config = BTreeMap::from([("200".to_string(), write("ok.html", ""))]);
+        assert!(matches!(
+            ErrorPages::load(&config),
+            Err(ErrorPageError::InvalidStatus(_))
+        ));
+        let config = BTreeMap::from([("404".to_string(), dir.path().join("missing.html"))]);
+        assert!(matches!(
+            ErrorPages::load(&config),
+            Err(ErrorPageError::ReadError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_render() {
+        let page = "<h1>{{status}} {{reason}}</h1><p>{{message}}</p>";
+        assert_eq!(
+            render(page, StatusCode::BAD_GATEWAY, "App '<web>' is not running"),
+            "<h1>502 Bad Gateway</h1><p>App &#39;&lt;web&gt;&#39; is not running</p>"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use hyper::header::{HeaderName, HeaderValue, HOST};
+use hyper::{Body, HeaderMap, Request};
+use ipnet::IpNet;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use super::proxy::ClientConnection;
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+pub const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+pub const X_REAL_IP: &str = "x-real-ip";
+pub const FORWARDED: &str = "forwarded";
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+/// Headers describing the client that only a trusted proxy may set
+const FORWARDING_HEADERS: [&str; 7] = [
+    X_FORWARDED_FOR,
+    X_FORWARDED_HOST,
+    X_FORWARDED_PROTO,
+    X_FORWARDED_PREFIX,
+    X_REAL_IP,
+    FORWARDED,
+    X_REQUEST_ID,
+];
+
+/// Longest request ID accepted from a trusted proxy
+const MAX_REQUEST_ID_LEN: usize = 200;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ForwardingError {
+    #[error("Invalid address or network: {0}")]
+    InvalidNetwork(String),
+}
+
+/// Parse an address or a CIDR network, taking a bare address as a network
+/// of just that address
+pub fn parse_network(s: &str) -> Result<IpNet, ForwardingError> {
+    let s = s.trim();
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| ForwardingError::InvalidNetwork(s.to_string()))
+}
+
+/// Proxies in front of the server, such as a cloud load balancer, whose
+/// forwarding headers are believed
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn parse(entries: &[String]) -> Result<Self, ForwardingError> {
+        let networks = entries
+            .iter()
+            .map(|entry| parse_network(entry))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+}
+
+/// The client a request came from, as passed on to the app
+#[derive(Debug, Clone)]
+pub struct Forwarded {
+    pub client_ip: IpAddr,
+    pub request_id: String,
+}
+
+/// Set the forwarding headers of a request bound for an app. Headers that
+/// came with the request are only kept, and added to, when it came through a
+/// trusted proxy. From anyone else they could be forged.
+pub fn apply(
+    req: &mut Request<Body>,
+    conn: ClientConnection,
+    trusted: &TrustedProxies,
+) -> Forwarded {
+    let peer = conn.remote_addr.ip().to_canonical();
+    let from_proxy = trusted.contains(peer);
+    let headers = req.headers_mut();
+    if !from_proxy {
+        for name in FORWARDING_HEADERS {
+            headers.remove(name);
+        }
+    }
+
+    // The client is the last address before the chain of trusted proxies
+    let forwarded_for = joined(headers, X_FORWARDED_FOR);
+    let mut client_ip = peer;
+    if let Some(chain) = &forwarded_for {
+        for hop in chain.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => {
+                    client_ip = ip;
+                    if !trusted.contains(ip) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let proto = conn.scheme();
+    let request_id = headers
+        .get(X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let forwarded_for = match forwarded_for {
+        Some(chain) => format!("{}, {}", chain, peer),
+        None => peer.to_string(),
+    };
+    let element = format!(
+        "for={};host={};proto={}",
+        forwarded_node(peer),
+        quoted_if_needed(&host),
+        proto
+    );
+    let forwarded = match joined(headers, FORWARDED) {
+        Some(existing) => format!("{}, {}", existing, element),
+        None => element,
+    };
+
+    set(headers, X_FORWARDED_FOR, &forwarded_for);
+    set(headers, X_REAL_IP, &client_ip.to_string());
+    set(headers, FORWARDED, &forwarded);
+    set(headers, X_REQUEST_ID, &request_id);
+    if !headers.contains_key(X_FORWARDED_HOST) {
+        set(headers, X_FORWARDED_HOST, &host);
+    }
+    if !headers.contains_key(X_FORWARDED_PROTO) {
+        set(headers, X_FORWARDED_PROTO, proto);
+    }
+
+    Forwarded {
+        client_ip,
+        request_id,
+    }
+}
+
+/// All values of a header as one comma-separated list
+fn joined(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+fn set(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+/// An address as a `Forwarded` node, where IPv6 addresses are bracketed and
+/// quoted (RFC 7239)
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("\"[{}]\"", ip),
+    }
+}
+
+/// Quote a `Forwarded` value unless it is a plain token
+fn quoted_if_needed(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request<Body> {
+        let mut req = Request::builder().uri("/").header(HOST, "web.example.com");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    fn conn(addr: &str) -> ClientConnection {
+        ClientConnection {
+            remote_addr: format!("{}:40000", addr).parse().unwrap(),
+            tls: false,
+        }
+    }
+
+    fn header<'a>(req: &'a Request<Body>, name: &str) -> &'a str {
+        req.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn test_parse_network() {
+        assert_eq!(
+            parse_network("10.0.0.0/8").unwrap(),
+            "10.0.0.0/8".parse::<IpNet>().unwrap()
+        );
+        assert_eq!(
+            parse_network("192.168.1.10").unwrap(),
+            "192.168.1.10/32".parse::<IpNet>().unwrap()
+        );
+        assert_eq!(
+            parse_network("::1").unwrap(),
+            "::1/128".parse::<IpNet>().unwrap()
+        );
+        assert!(parse_network("10.0.0.0/33").is_err());
+        assert!(parse_network("example.com").is_err());
+    }
+
+    #[test]
+    fn test_direct_client() {
+        let trusted = TrustedProxies::default();
+        let mut req = request(&[
+            (X_FORWARDED_FOR, "6.6.6.6"),
+            (X_REAL_IP, "6.6.6.6"),
+            (X_REQUEST_ID, "forged"),
+        ]);
+
+        let forwarded = apply(&mut req, conn("203.0.113.9"), &trusted);
+        assert_eq!(forwarded.client_ip.to_string(), "203.0.113.9");
+        assert_ne!(forwarded.request_id, "forged");
+
+        // Headers sent by the client itself are replaced
+        assert_eq!(header(&req, X_FORWARDED_FOR), "203.0.113.9");
+        assert_eq!(header(&req, X_REAL_IP), "203.0.113.9");
+        assert_eq!(header(&req, X_FORWARDED_PROTO), "http");
+        assert_eq!(header(&req, X_FORWARDED_HOST), "web.example.com");
+        assert_eq!(
+            header(&req, FORWARDED),
+            "for=203.0.113.9;host=web.example.com;proto=http"
+        );
+        assert_eq!(header(&req, X_REQUEST_ID), forwarded.request_id);
+    }
+
+    #[test]
+    fn test_behind_trusted_proxy() {
+        let trusted = TrustedProxies::parse(&["10.0.0.0/8".to_string()]).unwrap();
+        let mut req = request(&[
+            (X_FORWARDED_FOR, "6.6.6.6, 198.51.100.7"),
+            (X_FORWARDED_FOR, "10.1.2.3"),
+            (X_FORWARDED_PROTO, "https"),
+            (FORWARDED, "for=198.51.100.7;proto=https"),
+            (X_REQUEST_ID, "lb-123"),
+        ]);
+
+        let forwarded = apply(&mut req, conn("10.0.0.2"), &trusted);
+
+        // The last untrusted hop is the client, not what it claims to be
+        assert_eq!(forwarded.client_ip.to_string(), "198.51.100.7");
+        assert_eq!(forwarded.request_id, "lb-123");
+        assert_eq!(
+            header(&req, X_FORWARDED_FOR),
+            "6.6.6.6, 198.51.100.7, 10.1.2.3, 10.0.0.2"
+        );
+        assert_eq!(header(&req, X_REAL_IP), "198.51.100.7");
+        assert_eq!(header(&req, X_FORWARDED_PROTO), "https");
+        assert_eq!(
+            header(&req, FORWARDED),
+            "for=198.51.100.7;proto=https, for=10.0.0.2;host=web.example.com;proto=http"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_quoting() {
+        let trusted = TrustedProxies::default();
+        let mut req = Request::builder()
+            .uri("/")
+            .header(HOST, "localhost:8080")
+            .body(Body::empty())
+            .unwrap();
+        let conn = ClientConnection {
+            remote_addr: "[2001:db8::1]:40000".parse().unwrap(),
+            tls: true,
+        };
+
+        apply(&mut req, conn, &trusted);
+        assert_eq!(
+            header(&req, FORWARDED),
+            "for=\"[2001:db8::1]\";host=\"localhost:8080\";proto=https"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use futures_util::StreamExt;
+use hyper::Body;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of clients tracked before buckets that have filled up again are
+/// forgotten
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Time between two prunes, so a busy server does not walk every bucket on
+/// each request
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Enforces apps' rate and concurrency limits across all their requests
+#[derive(Debug, Default)]
+pub struct Limiter {
+    buckets: Mutex<Buckets>,
+    in_flight: Mutex<HashMap<String, u32>>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    clients: HashMap<(String, IpAddr), Bucket>,
+    pruned_at: Option<Instant>,
+}
+
+/// Token bucket of one client of an app
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket is full again if no more requests come, after which
+    /// it can be dropped without the client noticing
+    full_at: Instant,
+}
+
+/// A request counted against its app's concurrency limit until dropped
+#[derive(Debug)]
+pub struct Permit {
+    limiter: Arc<Limiter>,
+    app_name: String,
+}
+
+impl Limiter {
+    /// Take a token from the client's bucket, which holds up to `burst`
+    /// tokens and refills at `rate` per second. When empty, returns how long
+    /// until the next token.
+    pub fn check_rate(
+        &self,
+        app_name: &str,
+        client_ip: IpAddr,
+        rate: u32,
+        burst: u32,
+    ) -> Result<(), Duration> {
+        self.check_rate_at(app_name, client_ip, rate, burst, Instant::now())
+    }
+
+    fn check_rate_at(
+        &self,
+        app_name: &str,
+        client_ip: IpAddr,
+        rate: u32,
+        burst: u32,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let (rate, burst) = (f64::from(rate.max(1)), f64::from(burst.max(1)));
+        let mut buckets = self.buckets.lock().unwrap();
+        let prune_due = buckets
+            .pruned_at
+            .is_none_or(|pruned_at| now.saturating_duration_since(pruned_at) >= PRUNE_INTERVAL);
+        if buckets.clients.len() >= PRUNE_THRESHOLD && prune_due {
+            buckets.clients.retain(|_, bucket| bucket.full_at > now);
+            buckets.pruned_at = Some(now);
+        }
+
+        let bucket = buckets
+            .clients
+            .entry((app_name.to_string(), client_ip))
+            .or_insert(Bucket {
+                tokens: burst,
+                updated: now,
+                full_at: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate));
+        }
+        bucket.tokens -= 1.0;
+        bucket.full_at = now + Duration::from_secs_f64((burst - bucket.tokens) / rate);
+        Ok(())
+    }
+
+    /// Count a request against an app that may have at most `max` requests
+    /// in flight, or `None` if it already has that many
+    pub fn acquire(self: &Arc<Self>, app_name: &str, max: u32) -> Option<Permit> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(app_name.to_string()).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+
+        Some(Permit {
+            limiter: Arc::clone(self),
+            app_name: app_name.to_string(),
+        })
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.app_name) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.app_name);
+            }
+        }
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Request body is larger than {0} bytes")]
+struct BodyTooLarge(u64);
+
+/// Fail a request body once it grows past `max` bytes, setting `exceeded`
+/// so the failed upstream request can be told apart from other errors
+pub fn limit_body(body: Body, max: u64, exceeded: Arc<AtomicBool>) -> Body {
+    let mut received = 0u64;
+    Body::wrap_stream(body.map(move |chunk| -> Result<_, BoxError> {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if received > max {
+            exceeded.store(true, Ordering::Relaxed);
+            return Err(BodyTooLarge(max).into());
+        }
+        Ok(chunk)
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = Limiter::default();
+        let client: IpAddr = "203.0.113.9".parse().unwrap();
+        let start = Instant::now();
+
+        // A full bucket allows a burst, then one request per 1/rate seconds
+        for _ in 0..3 {
+            assert!(limiter.check_rate_at("web", client, 2, 3, start).is_ok());
+        }
+        let wait = limiter
+            .check_rate_at("web", client, 2, 3, start)
+            .unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_rate_at("web", client, 2, 3, later).is_ok());
+        assert!(limiter.check_rate_at("web", client, 2, 3, later).is_err());
+
+        // Other clients and apps have buckets of their own
+        let other: IpAddr = "198.51.100.7".parse().unwrap();
+        assert!(limiter.check_rate_at("web", other, 2, 3, later).is_ok());
+        assert!(limiter.check_rate_at("api", client, 2, 3, later).is_ok());
+    }
+
+    #[test]
+    fn test_prune_interval() {
+        let limiter = Limiter::default();
+        let start = Instant::now();
+        let client = |i: usize| IpAddr::from((i as u32).to_be_bytes());
+        for i in 0..PRUNE_THRESHOLD {
+            limiter
+                .check_rate_at("web", client(i), 1, 1, start)
+                .unwrap();
+        }
+
+        // Full buckets are dropped once the threshold is reached, then not
+        // again until the interval has passed
+        let later = start + Duration::from_secs(2);
+        limiter
+            .check_rate_at("web", client(0), 1, 1, later)
+            .unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 1);
+
+        for i in 1..PRUNE_THRESHOLD {
+            limiter
+                .check_rate_at("web", client(i), 1, 1, later)
+                .unwrap();
+        }
+        let soon = later + Duration::from_secs(2);
+        limiter.check_rate_at("web", client(0), 1, 1, soon).unwrap();
+        assert_eq!(
+            limiter.buckets.lock().unwrap().clients.len(),
+            PRUNE_THRESHOLD
+        );
+
+        limiter
+            .check_rate_at("web", client(0), 1, 1, later + PRUNE_INTERVAL)
+            .unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 1);
+    }
+
+    #[test]
+    fn test_concurrency() {
+        let limiter = Arc::new(Limiter::default());
+        let first = limiter.acquire("web", 2).unwrap();
+        let _second = limiter.acquire("web", 2).unwrap();
+        assert!(limiter.acquire("web", 2).is_none());
+        assert!(limiter.acquire("api", 2).is_some());
+
+        drop(first);
+        assert!(limiter.acquire("web", 2).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_limit_body() {
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let body = limit_body(Body::from("small"), 10, Arc::clone(&exceeded));
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "small");
+        assert!(!exceeded.load(Ordering::Relaxed));
+
+        let body = limit_body(Body::from("far too large"), 10, Arc::clone(&exceeded));
+        assert!(hyper::body::to_bytes(body).await.is_err());
+        assert!(exceeded.load(Ordering::Relaxed));
+    }
+}
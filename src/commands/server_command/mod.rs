@@ -1 +1,14 @@
+pub mod access_control;
+pub mod access_log;
+pub mod acme;
+pub mod audit;
+pub mod balancer;
+pub mod compression;
+pub mod error_pages;
+pub mod forwarding;
+pub mod limiter;
+pub mod proxy;
 pub mod serve;
+pub mod tcp_proxy;
+pub mod tls;
+pub mod token;
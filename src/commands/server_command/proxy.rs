@@ -0,0 +1,1097 @@
+use futures_util::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::header::{
+    HeaderValue, AUTHORIZATION, CONNECTION, CONTENT_LENGTH, RETRY_AFTER, UPGRADE, VARY,
+    WWW_AUTHENTICATE,
+};
+use hyper::{Body, Client, HeaderMap, Method, Request, Response, StatusCode};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::error::Error as _;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, instrument, warn};
+
+use crate::config::UpstreamConfig;
+use crate::db;
+use crate::models::{App, AppState, Service};
+use crate::supervisor::SUPERVISOR;
+
+use super::access_control::{self, AccessControl, Denied};
+use super::balancer::Balancer;
+use super::compression;
+use super::error_pages::{self, ErrorPages};
+use super::limiter::{self, Limiter};
+use super::serve::ProxyState;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    #[error("App not found: {0}")]
+    AppNotFound(String),
+    #[error("App serves TCP, not HTTP: {0}")]
+    NotHttp(String),
+    #[error("No app found for host: {0}")]
+    NoAppForHost(String),
+    #[error("App is in maintenance mode: {app}")]
+    Maintenance {
+        app: String,
+        /// Custom page of the app, used over the server's error pages
+        page: Option<String>,
+        retry_after: u32,
+    },
+    #[error("App is not running: {0}")]
+    AppNotRunning(String),
+    #[error("App did not become ready in time: {0}")]
+    AppNotReady(String),
+    #[error("Too many requests waiting for app: {0}")]
+    TooManyHeldRequests(String),
+    #[error("Client address not allowed by app: {0}")]
+    Forbidden(String),
+    #[error("Missing or wrong credentials for app: {0}")]
+    Unauthorized(String),
+    #[error("Client exceeded the request rate of app: {0}")]
+    RateLimited(String, Duration),
+    #[error("Too many requests in flight to app: {0}")]
+    TooManyConcurrentRequests(String),
+    #[error("Request body too large for app: {0}")]
+    BodyTooLarge(String, u64),
+    #[error("App is not accepting connections: {0}")]
+    ConnectionFailed(String, #[source] hyper::Error),
+    #[error("App is not accepting TCP connections: {0}")]
+    TcpConnectFailed(String, #[source] std::io::Error),
+    #[error("App did not respond in time: {0}")]
+    Timeout(String),
+    #[error("Invalid upstream request: {0}")]
+    InvalidRequest(#[from] http::Error),
+    #[error("Upstream error: {0}")]
+    UpstreamError(#[from] hyper::Error),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] db::DatabaseError),
+}
+
+impl ProxyError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProxyError::AppNotFound(_) | ProxyError::NotHttp(_) | ProxyError::NoAppForHost(_) => {
+                StatusCode::NOT_FOUND
+            }
+            ProxyError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ProxyError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ProxyError::Maintenance { .. }
+            | ProxyError::AppNotRunning(_)
+            | ProxyError::AppNotReady(_)
+            | ProxyError::TooManyHeldRequests(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ProxyError::RateLimited(..) | ProxyError::TooManyConcurrentRequests(_) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ProxyError::BodyTooLarge(..) => StatusCode::PAYLOAD_TOO_LARGE,
+            ProxyError::ConnectionFailed(..)
+            | ProxyError::TcpConnectFailed(..)
+            | ProxyError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::InvalidRequest(_) | ProxyError::DatabaseError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Seconds the client should wait before trying again, if it is being
+    /// held back
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            ProxyError::Maintenance { retry_after, .. } => Some(u64::from(*retry_after)),
+            ProxyError::RateLimited(_, wait) => Some(wait.as_secs_f64().ceil().max(1.0) as u64),
+            ProxyError::TooManyConcurrentRequests(_) => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Response sent to the client, as the configured error page for its
+    /// status if there is one and as plain text otherwise. Internal details
+    /// are only logged.
+    pub fn into_response(self, pages: &ErrorPages) -> Response<Body> {
+        let status = self.status();
+        let message = match &self {
+            ProxyError::AppNotFound(name) => format!("App '{}' not found", name),
+            ProxyError::NotHttp(name) => format!("App '{}' does not serve HTTP", name),
+            ProxyError::NoAppForHost(_) => "No app found for host".to_string(),
+            ProxyError::Maintenance { app, .. } => {
+                format!("App '{}' is down for maintenance, try again later", app)
+            }
+            ProxyError::AppNotRunning(name) => format!("App '{}' is not running", name),
+            ProxyError::AppNotReady(name) | ProxyError::TooManyHeldRequests(name) => {
+                format!("App '{}' is not ready yet, try again shortly", name)
+            }
+            ProxyError::Forbidden(_) => "Access denied".to_string(),
+            ProxyError::Unauthorized(_) => "Sign in required".to_string(),
+            ProxyError::RateLimited(..) | ProxyError::TooManyConcurrentRequests(_) => {
+                "Too many requests, try again later".to_string()
+            }
+            ProxyError::BodyTooLarge(_, max) => {
+                format!("Request body is larger than {} bytes", max)
+            }
+            _ => status
+                .canonical_reason()
+                .unwrap_or("Proxy error")
+                .to_string(),
+        };
+
+        if status.is_server_error() {
+            error!("Proxy error: {}", self);
+        }
+
+        let page = match &self {
+            ProxyError::Maintenance {
+                page: Some(page), ..
+            } => Some(page.as_str()),
+            _ => pages.get(status),
+        };
+        let (content_type, body) = match page {
+            Some(page) => (
+                "text/html; charset=utf-8",
+                error_pages::render(page, status, &message),
+            ),
+            None => ("text/plain; charset=utf-8", message),
+        };
+
+        let mut response = Response::builder()
+            .status(status)
+            .header("Content-Type", content_type);
+        if let Some(seconds) = self.retry_after() {
+            response = response.header(RETRY_AFTER, seconds);
+        }
+        if let ProxyError::Unauthorized(name) = &self {
+            response = response.header(
+                WWW_AUTHENTICATE,
+                format!("Basic realm=\"{}\", charset=\"UTF-8\"", name),
+            );
+        }
+        response.body(Body::from(body)).unwrap()
+    }
+}
+
+type Result<T> = std::result::Result<T, ProxyError>;
+
+/// How a client connected to the proxy
+#[derive(Debug, Clone, Copy)]
+pub struct ClientConnection {
+    pub remote_addr: SocketAddr,
+    /// Whether the connection came in over HTTPS
+    pub tls: bool,
+}
+
+impl ClientConnection {
+    pub fn scheme(&self) -> &'static str {
+        if self.tls {
+            "https"
+        } else {
+            "http"
+        }
+    }
+}
+
+/// Shared client used to forward requests to apps, keeping connections to
+/// each app alive between requests
+#[derive(Clone)]
+pub struct Upstream {
+    client: Client<HttpConnector>,
+    read_timeout: Duration,
+    upgrade_idle_timeout: Duration,
+    pub(super) hold: HoldPolicy,
+    pub(super) balancer: Arc<Balancer>,
+    pub(super) limiter: Arc<Limiter>,
+    access: Arc<AccessControl>,
+    compression_min_bytes: u64,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+/// How requests are held while their app starts or restarts
+#[derive(Clone)]
+pub(super) struct HoldPolicy {
+    wait: Duration,
+    max_per_app: usize,
+    held: Arc<HeldRequests>,
+}
+
+/// Number of requests currently held for each app
+#[derive(Debug, Default)]
+struct HeldRequests {
+    counts: Mutex<HashMap<String, usize>>,
+}
+
+/// A held request's place in its app's queue, freed when dropped
+struct HeldRequest {
+    held: Arc<HeldRequests>,
+    metrics: Arc<ProxyMetrics>,
+    app_name: String,
+}
+
+impl HeldRequests {
+    /// Take a place in the app's queue unless `max` requests already wait
+    fn hold(
+        self: &Arc<Self>,
+        app_name: &str,
+        max: usize,
+        metrics: &Arc<ProxyMetrics>,
+    ) -> Option<HeldRequest> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(app_name.to_string()).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        metrics.held_requests.fetch_add(1, Ordering::Relaxed);
+
+        Some(HeldRequest {
+            held: Arc::clone(self),
+            metrics: Arc::clone(metrics),
+            app_name: app_name.to_string(),
+        })
+    }
+}
+
+impl Drop for HeldRequest {
+    fn drop(&mut self) {
+        let mut counts = self.held.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.app_name) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.app_name);
+            }
+        }
+        self.metrics.held_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters for connections handled by the proxy
+#[derive(Debug, Default)]
+pub struct ProxyMetrics {
+    /// Upgraded (e.g. WebSocket) connections currently open
+    pub active_upgrades: AtomicU64,
+    /// Upgraded connections opened since the server started
+    pub total_upgrades: AtomicU64,
+    /// Requests waiting for their app to finish starting or restarting
+    pub held_requests: AtomicU64,
+    /// Requests turned away for exceeding their app's rate limit
+    pub rate_limited: AtomicU64,
+    /// Requests turned away while their app had too many in flight
+    pub concurrency_limited: AtomicU64,
+    /// Requests turned away for a body larger than their app accepts
+    pub body_too_large: AtomicU64,
+    /// Connections to TCP services currently open
+    pub active_tcp_connections: AtomicU64,
+    /// Connections to TCP services opened since the server started
+    pub total_tcp_connections: AtomicU64,
+}
+
+impl Upstream {
+    pub fn new(config: &UpstreamConfig) -> Self {
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(Duration::from_secs(config.connect_timeout_secs)));
+        connector.set_nodelay(true);
+
+        let client = Client::builder()
+            .pool_idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+            .pool_max_idle_per_host(config.max_idle_per_app)
+            .build(connector);
+
+        Self {
+            client,
+            read_timeout: Duration::from_secs(config.read_timeout_secs),
+            upgrade_idle_timeout: Duration::from_secs(config.upgrade_idle_timeout_secs),
+            hold: HoldPolicy {
+                wait: Duration::from_secs(config.restart_wait_secs),
+                max_per_app: config.max_held_requests,
+                held: Arc::default(),
+            },
+            balancer: Arc::default(),
+            limiter: Arc::default(),
+            access: Arc::default(),
+            compression_min_bytes: config.compression_min_bytes,
+            metrics: Arc::default(),
+        }
+    }
+}
+
+/// Proxy request to app
+#[instrument(skip(state, req))]
+pub async fn proxy_to_app(
+    state: Arc<RwLock<ProxyState>>,
+    app_name: &str,
+    mut req: Request<Body>,
+    client_ip: IpAddr,
+) -> Result<Response<Body>> {
+    let (pool, upstream) = {
+        let state = state.read().await;
+        (state.db_pool.clone(), state.upstream.clone())
+    };
+    let Upstream {
+        client,
+        read_timeout,
+        upgrade_idle_timeout: idle_timeout,
+        hold,
+        balancer,
+        limiter,
+        access,
+        compression_min_bytes,
+        metrics,
+    } = upstream;
+
+    let app = db::apps::get_by_name(&pool, app_name)
+        .await?
+        .ok_or_else(|| ProxyError::AppNotFound(app_name.to_string()))?;
+    if let Service::Tcp { .. } = app.service {
+        return Err(ProxyError::NotHttp(app_name.to_string()));
+    }
+
+    // Answer for an app in maintenance without touching its process
+    if let Some(maintenance) = &app.maintenance {
+        if !access_control::allows(&maintenance.allow, client_ip) {
+            return Err(ProxyError::Maintenance {
+                app: app_name.to_string(),
+                page: maintenance.page.clone(),
+                retry_after: maintenance.retry_after,
+            });
+        }
+    }
+
+    // Turn away requests over the app's rate before they can wake it or
+    // have their credentials checked, so passwords cannot be guessed any
+    // faster than the rate allows
+    let limits = &app.limits;
+    if let Some(rate) = limits.requests_per_second {
+        let burst = limits.burst.unwrap_or(rate);
+        if let Err(wait) = limiter.check_rate(app_name, client_ip, rate, burst) {
+            metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+            return Err(ProxyError::RateLimited(app_name.to_string(), wait));
+        }
+    }
+
+    // Keep out clients the app does not let in before they can wake it
+    if let Err(denied) = access.check(&app.access, req.headers(), client_ip).await {
+        return Err(match denied {
+            Denied::Forbidden => ProxyError::Forbidden(app_name.to_string()),
+            Denied::Unauthorized => ProxyError::Unauthorized(app_name.to_string()),
+        });
+    }
+    // The proxy's credentials are not the app's business
+    if !app.access.users.is_empty() {
+        req.headers_mut().remove(AUTHORIZATION);
+    }
+
+    let body_exceeded = Arc::new(AtomicBool::new(false));
+    if let Some(max) = limits.max_body_bytes {
+        let length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<u64>().ok());
+        if length.is_some_and(|length| length > max) {
+            metrics.body_too_large.fetch_add(1, Ordering::Relaxed);
+            return Err(ProxyError::BodyTooLarge(app_name.to_string(), max));
+        }
+        // Bodies without a length are cut off as they stream in
+        let body = std::mem::take(req.body_mut());
+        *req.body_mut() = limiter::limit_body(body, max, Arc::clone(&body_exceeded));
+    }
+
+    // Keep the app from going to sleep
+    if let Some(supervisor) = SUPERVISOR.get() {
+        supervisor.record_activity(app_name);
+    }
+
+    // Check if app is running, waking it or waiting for it if it is on its way
+    let app = wait_until_ready(&pool, app, &hold, &metrics).await?;
+
+    // Count the request against the app until its response has been sent
+    let permit = match app.limits.max_concurrent {
+        Some(max) => match limiter.acquire(app_name, max) {
+            Some(permit) => Some(permit),
+            None => {
+                metrics.concurrency_limited.fetch_add(1, Ordering::Relaxed);
+                return Err(ProxyError::TooManyConcurrentRequests(app_name.to_string()));
+            }
+        },
+        None => None,
+    };
+
+    // Keep a sleepy app awake for as long as the response streams or an
+    // upgraded connection stays open
+    let awake = KeepAwake::start(&app);
+
+    // Pick an instance, counting the request against it until it is done
+    let ports = SUPERVISOR
+        .get()
+        .and_then(|supervisor| supervisor.instance_ports(app_name))
+        .unwrap_or_else(|| app.port.into_iter().collect());
+    let lease = balancer
+        .pick(app_name, &ports)
+        .ok_or_else(|| ProxyError::AppNotRunning(app_name.to_string()))?;
+
+    // Create URI for proxying
+    let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("");
+    let uri = format!("http://{}:{}{}", app.host, lease.port, path_and_query);
+
+    // Upgrade requests (e.g. WebSockets) keep their `Connection` and
+    // `Upgrade` headers so the app can switch protocols
+    let client_upgrade = is_upgrade(req.headers()).then(|| hyper::upgrade::on(&mut req));
+
+    // Responses to HEAD requests have no body to compress
+    let compress = app.compression && req.method() != Method::HEAD;
+    let encoding = compress
+        .then(|| compression::negotiate(req.headers()))
+        .flatten();
+
+    // Create new request
+    let (parts, body) = req.into_parts();
+    let mut new_req = Request::builder().method(parts.method).uri(uri);
+
+    // Copy headers, including the forwarding headers set for the app
+    for (name, value) in parts.headers.iter() {
+        if name != "host" {
+            new_req = new_req.header(name, value);
+        }
+    }
+
+    // Build request
+    let new_req = new_req.body(body)?;
+
+    // Send request, waiting at most `read_timeout` for the response headers.
+    // The time taken to upload the body counts towards it.
+    let mut resp = match tokio::time::timeout(read_timeout, client.request(new_req)).await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(_)) if body_exceeded.load(Ordering::Relaxed) => {
+            metrics.body_too_large.fetch_add(1, Ordering::Relaxed);
+            let max = app.limits.max_body_bytes.unwrap_or_default();
+            return Err(ProxyError::BodyTooLarge(app_name.to_string(), max));
+        }
+        Ok(Err(e)) if is_timeout(&e) => return Err(ProxyError::Timeout(app_name.to_string())),
+        Ok(Err(e)) if e.is_connect() => {
+            warn!("Failed to connect to app '{}': {}", app_name, e);
+            return Err(ProxyError::ConnectionFailed(app_name.to_string(), e));
+        }
+        Ok(Err(e)) => return Err(ProxyError::UpstreamError(e)),
+        Err(_) => return Err(ProxyError::Timeout(app_name.to_string())),
+    };
+
+    // Once the app agrees to switch protocols, splice both connections
+    if let Some(client_upgrade) = client_upgrade {
+        if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+            let upstream_upgrade = hyper::upgrade::on(&mut resp);
+            let app_name = app_name.to_string();
+            tokio::spawn(async move {
+                let _held = (lease, permit, awake);
+                let (client, upstream) = match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                    Ok(upgraded) => upgraded,
+                    Err(e) => {
+                        warn!("Upgrade for app '{}' failed: {}", app_name, e);
+                        return;
+                    }
+                };
+
+                metrics.active_upgrades.fetch_add(1, Ordering::Relaxed);
+                metrics.total_upgrades.fetch_add(1, Ordering::Relaxed);
+                match splice(client, upstream, idle_timeout).await {
+                    Ok((sent, received)) => debug!(
+                        "Upgraded connection to app '{}' closed ({} bytes sent, {} received)",
+                        app_name, sent, received
+                    ),
+                    Err(e) => debug!("Upgraded connection to app '{}' failed: {}", app_name, e),
+                }
+                metrics.active_upgrades.fetch_sub(1, Ordering::Relaxed);
+            });
+            return Ok(resp);
+        }
+    }
+
+    if compress && compression::should_compress(&resp, compression_min_bytes) {
+        match encoding {
+            Some(encoding) => resp = compression::compress(resp, encoding),
+            // Caches must not hand this response to clients that accept
+            // compression, which would get it compressed from the proxy
+            None => {
+                resp.headers_mut()
+                    .append(VARY, HeaderValue::from_static("accept-encoding"));
+            }
+        }
+    }
+
+    // Uncounted requests to single instances of apps that never sleep pass
+    // their bodies through as is
+    if ports.len() < 2 && permit.is_none() && awake.is_none() {
+        return Ok(resp);
+    }
+    Ok(hold_until_sent(resp, (lease, permit, awake)))
+}
+
+/// How often a response or connection that is still open keeps its app
+/// from going to sleep
+const ACTIVITY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keeps an app from going to sleep until dropped, so that long responses
+/// and connections count as activity for as long as they are open rather
+/// than only when they start
+pub(super) struct KeepAwake(JoinHandle<()>);
+
+impl KeepAwake {
+    /// Start keeping an app awake, or `None` for apps that never sleep
+    pub(super) fn start(app: &App) -> Option<Self> {
+        app.idle_timeout?;
+        let supervisor = SUPERVISOR.get()?;
+        let app_name = app.name.clone();
+        Some(Self(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ACTIVITY_INTERVAL);
+            loop {
+                interval.tick().await;
+                supervisor.record_activity(&app_name);
+            }
+        })))
+    }
+}
+
+impl Drop for KeepAwake {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Keep a request counted against its instance and app until the response
+/// body has been sent
+fn hold_until_sent<T: Send + 'static>(resp: Response<Body>, held: T) -> Response<Body> {
+    let (parts, body) = resp.into_parts();
+    let body = body.map(move |chunk| {
+        let _ = &held;
+        chunk
+    });
+    Response::from_parts(parts, Body::wrap_stream(body))
+}
+
+/// How often a held request checks whether its app is ready
+const HOLD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait for an app that is starting or restarting to run and accept
+/// connections, waking it first if it is sleeping. Other apps that are not
+/// running fail straight away.
+pub(super) async fn wait_until_ready(
+    pool: &Pool<Sqlite>,
+    app: App,
+    hold: &HoldPolicy,
+    metrics: &Arc<ProxyMetrics>,
+) -> Result<App> {
+    match app.state {
+        AppState::Running => return Ok(app),
+        AppState::Sleeping => {
+            if let Some(supervisor) = SUPERVISOR.get() {
+                if let Err(e) = supervisor.wake_app(&app.name).await {
+                    error!("Failed to wake app '{}': {}", app.name, e);
+                    return Err(ProxyError::AppNotRunning(app.name));
+                }
+            }
+        }
+        AppState::Starting | AppState::Restarting => {}
+        _ => return Err(ProxyError::AppNotRunning(app.name)),
+    }
+    if hold.wait.is_zero() {
+        return Err(ProxyError::AppNotRunning(app.name));
+    }
+
+    let _held = hold
+        .held
+        .hold(&app.name, hold.max_per_app, metrics)
+        .ok_or_else(|| ProxyError::TooManyHeldRequests(app.name.clone()))?;
+    debug!("Holding request until app '{}' is ready", app.name);
+
+    let ready = async {
+        loop {
+            tokio::time::sleep(HOLD_POLL_INTERVAL).await;
+            let app = db::apps::get_by_name(pool, &app.name)
+                .await?
+                .ok_or_else(|| ProxyError::AppNotFound(app.name.clone()))?;
+            match app.state {
+                // The app is marked running as soon as its process spawns,
+                // which may be before it listens
+                AppState::Running if accepts_connections(&app).await => return Ok(app),
+                AppState::Running
+                | AppState::Starting
+                | AppState::Restarting
+                | AppState::Sleeping => {}
+                _ => return Err(ProxyError::AppNotRunning(app.name)),
+            }
+        }
+    };
+
+    tokio::time::timeout(hold.wait, ready)
+        .await
+        .unwrap_or_else(|_| Err(ProxyError::AppNotReady(app.name.clone())))
+}
+
+/// Whether an app's port accepts TCP connections
+async fn accepts_connections(app: &App) -> bool {
+    let Some(port) = app.port else {
+        return true;
+    };
+    let connect = TcpStream::connect((app.host.as_str(), port));
+    matches!(
+        tokio::time::timeout(HOLD_POLL_INTERVAL, connect).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Whether a request asks to switch protocols, e.g. to a WebSocket
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    let connection_upgrade = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    connection_upgrade && headers.contains_key(UPGRADE)
+}
+
+/// Copy data both ways between two connections until both sides are closed
+/// or neither has sent anything for `idle_timeout`. Returns the number of
+/// bytes sent from `client` to `upstream` and back.
+pub(super) async fn splice<C, U>(
+    client: C,
+    upstream: U,
+    idle_timeout: Duration,
+) -> std::io::Result<(u64, u64)>
+where
+    C: AsyncRead + AsyncWrite,
+    U: AsyncRead + AsyncWrite,
+{
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    let (mut upstream_read, mut upstream_write) = tokio::io::split(upstream);
+    let mut client_buf = vec![0u8; 8 * 1024];
+    let mut upstream_buf = vec![0u8; 8 * 1024];
+    let (mut client_done, mut upstream_done) = (false, false);
+    let (mut sent, mut received) = (0u64, 0u64);
+
+    while !(client_done && upstream_done) {
+        tokio::select! {
+            n = client_read.read(&mut client_buf), if !client_done => {
+                match n? {
+                    0 => {
+                        client_done = true;
+                        upstream_write.shutdown().await?;
+                    }
+                    n => {
+                        upstream_write.write_all(&client_buf[..n]).await?;
+                        sent += n as u64;
+                    }
+                }
+            }
+            n = upstream_read.read(&mut upstream_buf), if !upstream_done => {
+                match n? {
+                    0 => {
+                        upstream_done = true;
+                        client_write.shutdown().await?;
+                    }
+                    n => {
+                        client_write.write_all(&upstream_buf[..n]).await?;
+                        received += n as u64;
+                    }
+                }
+            }
+            _ = tokio::time::sleep(idle_timeout) => {
+                debug!("Closing idle connection");
+                break;
+            }
+        }
+    }
+
+    Ok((sent, received))
+}
+
+/// Whether a client error was caused by the connect timeout
+fn is_timeout(error: &hyper::Error) -> bool {
+    let mut source = error.source();
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::{AppAccess, AppLimits, BasicAuthUser, Maintenance};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    #[test]
+    fn test_error_statuses() {
+        assert_eq!(
+            ProxyError::AppNotFound("web".to_string()).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            ProxyError::Timeout("web".to_string()).status(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+
+        let response = ProxyError::Timeout("web".to_string()).into_response(&ErrorPages::default());
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_page() {
+        let maintenance = |page: Option<&str>| ProxyError::Maintenance {
+            app: "web".to_string(),
+            page: page.map(str::to_string),
+            retry_after: 120,
+        };
+        let body = |response: Response<Body>| async {
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let response = maintenance(None).into_response(&ErrorPages::default());
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "120");
+        assert_eq!(
+            body(response).await,
+            "App 'web' is down for maintenance, try again later"
+        );
+
+        // The app's own page wins over the server's
+        let response =
+            maintenance(Some("<p>{{message}}</p>")).into_response(&ErrorPages::default());
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            body(response).await,
+            "<p>App &#39;web&#39; is down for maintenance, try again later</p>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode() {
+        let pool = crate::db::test::get_test_pool().await;
+        let mut app = run_app(&pool, "web", "ok".to_string()).await;
+        let state = proxy_state(&pool).await;
+        app.maintenance = Some(Maintenance {
+            since: chrono::Utc::now(),
+            page: Some("<h1>Back soon</h1>".to_string()),
+            allow: vec!["10.0.0.1/32".to_string()],
+            retry_after: 300,
+        });
+        db::apps::save(&pool, &app).await.unwrap();
+
+        let response = send(&state, "web", get(), "203.0.113.9").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "300");
+        assert_eq!(body_text(response).await, "<h1>Back soon</h1>");
+
+        // Allowed clients still reach the app
+        let response = send(&state, "web", get(), "10.0.0.1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let pool = crate::db::test::get_test_pool().await;
+        let mut app = run_app(&pool, "web", "ok".to_string()).await;
+        let state = proxy_state(&pool).await;
+        app.limits = AppLimits {
+            requests_per_second: Some(1),
+            burst: Some(2),
+            max_body_bytes: Some(4),
+            ..Default::default()
+        };
+        db::apps::save(&pool, &app).await.unwrap();
+
+        for _ in 0..2 {
+            let response = send(&state, "web", get(), "203.0.113.9").await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = send(&state, "web", get(), "203.0.113.9").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "1");
+
+        // Other clients have a rate of their own, and large bodies are
+        // turned away
+        let upload = Request::post("/")
+            .header(CONTENT_LENGTH, "5")
+            .body(Body::from("large"))
+            .unwrap();
+        let response = send(&state, "web", upload, "198.51.100.7").await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_bad_credentials_are_rate_limited() {
+        let pool = crate::db::test::get_test_pool().await;
+        let mut app = run_app(&pool, "web", "ok".to_string()).await;
+        let state = proxy_state(&pool).await;
+        app.access.users = vec![BasicAuthUser::new("alice", "s3cret")];
+        app.limits = AppLimits {
+            requests_per_second: Some(1),
+            burst: Some(2),
+            ..Default::default()
+        };
+        db::apps::save(&pool, &app).await.unwrap();
+
+        // Requests without credentials use up the burst quickly, as checking
+        // a password takes longer than the bucket takes to refill
+        for _ in 0..2 {
+            let response = send(&state, "web", get(), "203.0.113.9").await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = send(&state, "web", get_as(Some("alice:guess")), "203.0.113.9").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_access_control() {
+        let pool = crate::db::test::get_test_pool().await;
+        let mut app = run_app(&pool, "web", "ok".to_string()).await;
+        let state = proxy_state(&pool).await;
+        app.access = AppAccess {
+            users: vec![BasicAuthUser::new("alice", "s3cret")],
+            allow: vec!["10.0.0.0/8".to_string()],
+        };
+        db::apps::save(&pool, &app).await.unwrap();
+        let status = |client_ip: &'static str, credentials: Option<&'static str>| {
+            let state = Arc::clone(&state);
+            async move {
+                send(&state, "web", get_as(credentials), client_ip)
+                    .await
+                    .status()
+            }
+        };
+
+        // Clients outside the allowlist are refused before being asked to
+        // sign in, and those inside still need a password
+        assert_eq!(
+            status("192.168.1.1", Some("alice:s3cret")).await,
+            StatusCode::FORBIDDEN
+        );
+        let response = send(&state, "web", get(), "10.1.2.3").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(WWW_AUTHENTICATE));
+        assert_eq!(
+            status("10.1.2.3", Some("alice:wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status("10.1.2.3", Some("alice:s3cret")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_is_upgrade() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "keep-alive, Upgrade".parse().unwrap());
+        assert!(!is_upgrade(&headers));
+
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        assert!(is_upgrade(&headers));
+
+        headers.insert(CONNECTION, "keep-alive".parse().unwrap());
+        assert!(!is_upgrade(&headers));
+    }
+
+    #[tokio::test]
+    async fn test_splice() {
+        let (client, mut client_peer) = tokio::io::duplex(64);
+        let (upstream, mut upstream_peer) = tokio::io::duplex(64);
+        let spliced = tokio::spawn(splice(client, upstream, Duration::from_secs(5)));
+
+        client_peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        upstream_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        upstream_peer.write_all(b"pong!").await.unwrap();
+        let mut buf = [0u8; 5];
+        client_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong!");
+
+        drop(client_peer);
+        drop(upstream_peer);
+        assert_eq!(spliced.await.unwrap().unwrap(), (4, 5));
+    }
+
+    #[tokio::test]
+    async fn test_splice_idle_timeout() {
+        let (client, _client_peer) = tokio::io::duplex(64);
+        let (upstream, _upstream_peer) = tokio::io::duplex(64);
+        let result = splice(client, upstream, Duration::from_millis(50)).await;
+        assert_eq!(result.unwrap(), (0, 0));
+    }
+
+    fn hold_policy(wait: Duration, max_per_app: usize) -> HoldPolicy {
+        HoldPolicy {
+            wait,
+            max_per_app,
+            held: Arc::default(),
+        }
+    }
+
+    async fn save_app(pool: &Pool<Sqlite>, name: &str, state: AppState, port: u16) -> App {
+        let mut app = App::new(name).unwrap();
+        app.host = "127.0.0.1".to_string();
+        app.port = Some(port);
+        app.state = state;
+        db::apps::save(pool, &app).await.unwrap();
+        app
+    }
+
+    #[tokio::test]
+    async fn test_sleeping_app_request_released_when_ready() {
+        let pool = crate::db::test::get_test_pool().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = save_app(&pool, "web", AppState::Sleeping, port).await;
+
+        let hold = hold_policy(Duration::from_secs(5), 10);
+        let metrics = Arc::default();
+        let running = app.running(4242);
+        let started = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            db::apps::save(&pool, &running).await.unwrap();
+        };
+        let (ready, _) = tokio::join!(wait_until_ready(&pool, app, &hold, &metrics), started);
+
+        assert_eq!(ready.unwrap().state, AppState::Running);
+        assert_eq!(metrics.held_requests.load(Ordering::Relaxed), 0);
+        assert!(hold.held.counts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_held_request_fails_after_wait() {
+        let pool = crate::db::test::get_test_pool().await;
+        let app = save_app(&pool, "web", AppState::Starting, 1).await;
+
+        let hold = hold_policy(Duration::from_millis(250), 10);
+        let got = wait_until_ready(&pool, app, &hold, &Arc::default()).await;
+        assert!(matches!(got, Err(ProxyError::AppNotReady(_))));
+
+        // Stopped apps are not waited for
+        let app = save_app(&pool, "api", AppState::Stopped, 1).await;
+        let got = wait_until_ready(&pool, app, &hold, &Arc::default()).await;
+        assert!(matches!(got, Err(ProxyError::AppNotRunning(_))));
+    }
+
+    #[test]
+    fn test_hold_queue_depth() {
+        let held = Arc::new(HeldRequests::default());
+        let metrics = Arc::default();
+
+        let first = held.hold("web", 2, &metrics).unwrap();
+        let _second = held.hold("web", 2, &metrics).unwrap();
+        assert!(held.hold("web", 2, &metrics).is_none());
+        assert!(held.hold("api", 2, &metrics).is_some());
+        assert_eq!(metrics.held_requests.load(Ordering::Relaxed), 2);
+
+        drop(first);
+        assert!(held.hold("web", 2, &metrics).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connection_refused_is_bad_gateway() {
+        // Nothing listens on port 1
+        let upstream = Upstream::new(&UpstreamConfig::default());
+        let req = Request::get("http://127.0.0.1:1/")
+            .body(Body::empty())
+            .unwrap();
+        let error = upstream.client.request(req).await.unwrap_err();
+        assert!(error.is_connect());
+        assert!(!is_timeout(&error));
+    }
+
+    fn get() -> Request<Body> {
+        Request::get("/").body(Body::empty()).unwrap()
+    }
+
+    /// A request signed in with `user:password` credentials, if any
+    fn get_as(credentials: Option<&str>) -> Request<Body> {
+        let mut req = Request::get("/");
+        if let Some(credentials) = credentials {
+            let value = format!("Basic {}", STANDARD.encode(credentials));
+            req = req.header(AUTHORIZATION, value);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    async fn body_text(response: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    /// Proxy state of a server with the default configuration
+    async fn proxy_state(pool: &Pool<Sqlite>) -> Arc<RwLock<ProxyState>> {
+        use crate::commands::server_command::{
+            access_log::AccessLog, acme::AcmeChallenges, forwarding::TrustedProxies,
+            tcp_proxy::TcpProxy,
+        };
+
+        let upstream = Upstream::new(&UpstreamConfig::default());
+        let tcp_proxy = TcpProxy::new(pool.clone(), upstream.clone(), &Default::default());
+        Arc::new(RwLock::new(ProxyState {
+            db_pool: pool.clone(),
+            admin_hostname: None,
+            base_domain: None,
+            https_redirect_port: None,
+            acme_challenges: Arc::new(AcmeChallenges::default()),
+            upstream,
+            access_log: Arc::new(AccessLog::disabled()),
+            trusted_proxies: Arc::new(TrustedProxies::default()),
+            error_pages: Arc::new(ErrorPages::default()),
+            tcp_proxy: Arc::new(tcp_proxy),
+        }))
+    }
+
+    /// A running app answering every request with `body` as plain text
+    async fn run_app(pool: &Pool<Sqlite>, name: &str, body: String) -> App {
+        use hyper::service::{make_service_fn, service_fn};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let make_svc = make_service_fn(move |_| {
+            let body = body.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |_| {
+                    let response = Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "text/plain")
+                        .body(Body::from(body.clone()));
+                    async move { response }
+                }))
+            }
+        });
+        let server = hyper::Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::spawn(server);
+
+        save_app(pool, name, AppState::Running, port).await
+    }
+
+    /// Send a request through the proxy, answering errors as the server does
+    async fn send(
+        state: &Arc<RwLock<ProxyState>>,
+        app_name: &str,
+        req: Request<Body>,
+        client_ip: &str,
+    ) -> Response<Body> {
+        let client_ip = client_ip.parse().unwrap();
+        proxy_to_app(Arc::clone(state), app_name, req, client_ip)
+            .await
+            .unwrap_or_else(|e| e.into_response(&ErrorPages::default()))
+    }
+}
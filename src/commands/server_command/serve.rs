@@ -1,53 +1,63 @@
 use anyhow::{Context, Result};
-use hyper::body::to_bytes;
+use axum::extract::{ConnectInfo, Extension, State};
+use axum::middleware;
+use axum::routing::get;
+use axum::Router;
+use futures_util::stream::unfold;
+use futures_util::FutureExt;
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::server::accept;
+use hyper::server::conn::{AddrStream, Http};
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Client, Request, Response, Server};
-use serde::{Deserialize, Serialize};
+use hyper::{Body, Request, Response, Server, StatusCode};
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, UnixListener};
 use tokio::sync::RwLock;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::UnixListenerStream;
 use tower::util::ServiceExt;
-use tracing::{error, info, instrument};
-
-use crate::api;
+use tracing::{debug, info, instrument, warn};
+
+use super::access_log::{AccessLog, AccessLogEntry};
+use super::acme::{self, AcmeChallenges};
+use super::error_pages::ErrorPages;
+use super::forwarding::{self, TrustedProxies};
+use super::proxy::{proxy_to_app, ClientConnection, ProxyError, Upstream};
+use super::tcp_proxy::TcpProxy;
+use super::tls::{self, CertStore};
+use crate::api::{self, ApiError};
+use crate::commands::app_command::{domains, routes};
 use crate::config::ServerConfig;
 use crate::db;
-use crate::models::AppState;
+use crate::models::{ApiToken, AppState, Route, TokenScope};
 use crate::supervisor;
 
-// Message types for communication between servers
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum ApiRequest {
-    ListApps,
-    GetApp { name: String },
-    CreateApp { name: String },
-    DeleteApp { id: String },
-    StartApp { id: String },
-    StopApp { id: String },
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum ApiResponse {
-    Apps(Vec<AppInfo>),
-    App(Option<AppInfo>),
-    Success(String),
-    Error(String),
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct AppInfo {
-    id: String,
-    name: String,
-    state: String,
-    host: String,
-    port: Option<u16>,
-    process_id: Option<u32>,
-}
-
 /// Shared state for the proxy server
 pub struct ProxyState {
     pub db_pool: sqlx::Pool<sqlx::Sqlite>,
+    /// Hostname the proxy forwards to the admin API and dashboard, if any
+    pub admin_hostname: Option<String>,
+    /// Apex domain apps are served under as `<app>.<base_domain>`
+    pub base_domain: Option<String>,
+    /// HTTPS port plain HTTP requests are redirected to, if enabled
+    pub https_redirect_port: Option<u16>,
+    /// Pending ACME HTTP-01 challenges, answered ahead of any routing
+    pub acme_challenges: Arc<AcmeChallenges>,
+    pub upstream: Upstream,
+    /// Per-app logs of the requests forwarded to apps
+    pub access_log: Arc<AccessLog>,
+    /// Proxies whose forwarding headers are passed on to apps
+    pub trusted_proxies: Arc<TrustedProxies>,
+    /// Pages served for the proxy's error responses
+    pub error_pages: Arc<ErrorPages>,
+    /// Listeners of apps serving raw TCP
+    pub tcp_proxy: Arc<TcpProxy>,
 }
 
 /// Start the BinaryDrop server
@@ -58,9 +68,31 @@ pub async fn execute(config: ServerConfig) -> Result<()> {
     supervisor::init(pool.clone()).await?;
 
     // Create shared state
+    let acme_challenges = Arc::new(AcmeChallenges::default());
+    let upstream = Upstream::new(&config.upstream);
+    let tcp_proxy = Arc::new(TcpProxy::new(pool.clone(), upstream.clone(), &config));
     let proxy_state = Arc::new(RwLock::new(ProxyState {
         db_pool: pool.clone(),
+        admin_hostname: config.admin_hostname.clone(),
+        base_domain: config.base_domain.clone(),
+        https_redirect_port: config
+            .tls
+            .as_ref()
+            .filter(|tls| tls.redirect_http)
+            .map(|tls| tls.port),
+        acme_challenges: Arc::clone(&acme_challenges),
+        upstream,
+        access_log: Arc::new(AccessLog::start(&config.access_log)),
+        trusted_proxies: Arc::new(
+            TrustedProxies::parse(&config.trusted_proxies).context("Invalid trusted_proxies")?,
+        ),
+        error_pages: Arc::new(
+            ErrorPages::load(&config.error_pages).context("Invalid error_pages")?,
+        ),
+        tcp_proxy: Arc::clone(&tcp_proxy),
     }));
+    let admin_state = Arc::clone(&proxy_state);
+    let https_state = Arc::clone(&proxy_state);
 
     // Parse host and port for proxy server
     let addr: SocketAddr = format!("{}:{}", config.host, config.proxy_port)
@@ -71,12 +103,17 @@ pub async fn execute(config: ServerConfig) -> Result<()> {
         ))?;
 
     // Create service for proxy server
-    let make_svc = make_service_fn(move |_conn| {
+    let make_svc = make_service_fn(move |conn: &AddrStream| {
         let state = Arc::clone(&proxy_state);
+        let remote_addr = conn.remote_addr();
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
                 let state = Arc::clone(&state);
-                async move { handle_request(req, state).await }
+                let conn = ClientConnection {
+                    remote_addr,
+                    tls: false,
+                };
+                async move { handle_request(req, state, conn).await }
             }))
         }
     });
@@ -84,6 +121,85 @@ pub async fn execute(config: ServerConfig) -> Result<()> {
     // Create proxy server
     let server = Server::bind(&addr).serve(make_svc);
 
+    // Terminate TLS on the HTTPS port when configured
+    let https_server = match &config.tls {
+        Some(tls_config) => {
+            let store = Arc::new(CertStore::default());
+            store.reload(tls_config)?;
+            tls::spawn_reloader(Arc::clone(&store), tls_config.clone());
+
+            let https_addr: SocketAddr = format!("{}:{}", config.host, tls_config.port)
+                .parse()
+                .context(format!(
+                    "Invalid host or HTTPS port: {}:{}",
+                    config.host, tls_config.port
+                ))?;
+            let listener = TcpListener::bind(https_addr)
+                .await
+                .context(format!("Failed to bind HTTPS port {}", https_addr))?;
+            println!(
+                "BinaryDrop HTTPS proxy running at https://{} with certificates for: {}",
+                https_addr,
+                store.domains().join(", ")
+            );
+
+            if let Some(acme_config) = &tls_config.acme {
+                println!(
+                    "Obtaining certificates from {} for: {}",
+                    acme_config.directory_url,
+                    acme_config.domains.join(", ")
+                );
+                acme::spawn_manager(
+                    Arc::clone(&store),
+                    tls_config.clone(),
+                    acme_config.clone(),
+                    acme_challenges,
+                );
+            }
+
+            serve_https(listener, tls::server_config(store), https_state).boxed()
+        }
+        None => futures_util::future::pending().boxed(),
+    };
+
+    // Listen for apps serving raw TCP
+    for (app_name, e) in tcp_proxy.sync().await? {
+        println!("Failed to listen for TCP service '{}': {}", app_name, e);
+    }
+    let tcp_services = tcp_proxy.listeners().await;
+    if !tcp_services.is_empty() {
+        println!(
+            "TCP services listening on {}: {}",
+            config.host,
+            tcp_services
+                .iter()
+                .map(|(app_name, port)| format!("{} ({})", app_name, port))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    // Serve the admin API and dashboard on their own listener
+    let admin_addr: SocketAddr = format!("{}:{}", config.admin_host, config.admin_port)
+        .parse()
+        .context(format!(
+            "Invalid admin host or port: {}:{}",
+            config.admin_host, config.admin_port
+        ))?;
+    let admin_server = Server::bind(&admin_addr).serve(
+        admin_router(Arc::clone(&admin_state)).into_make_service_with_connect_info::<SocketAddr>(),
+    );
+
+    // Serve the admin API on the local control socket
+    let socket_path = config.control_socket_path()?;
+    let control_listener = bind_control_socket(&socket_path)?;
+    let control_router = admin_router(admin_state).layer(Extension(api::LocalConnection));
+    let control_server = Server::builder(accept::from_stream(UnixListenerStream::new(
+        control_listener,
+    )))
+    .serve(control_router.into_make_service());
+    info!("Control socket listening at {}", socket_path.display());
+
     info!(
         "Starting BinaryDrop proxy server on http://{}:{}",
         config.host, config.proxy_port
@@ -92,6 +208,17 @@ pub async fn execute(config: ServerConfig) -> Result<()> {
         "BinaryDrop proxy server running at http://{}:{}",
         config.host, config.proxy_port
     );
+    println!(
+        "Admin API and dashboard running at http://{}:{}",
+        config.admin_host, config.admin_port
+    );
+    if let Some(hostname) = &config.admin_hostname {
+        println!(
+            "Admin also exposed through the proxy at http://{}",
+            hostname
+        );
+    }
+    println!("Local control socket at {}", socket_path.display());
     println!("Press Ctrl+C to stop");
 
     // Run proxy server
@@ -99,20 +226,126 @@ pub async fn execute(config: ServerConfig) -> Result<()> {
         result = server => {
             result.context("Proxy server error")?;
         }
+        result = https_server => {
+            result.context("HTTPS server error")?;
+        }
+        result = admin_server => {
+            result.context("Admin server error")?;
+        }
+        result = control_server => {
+            result.context("Control socket error")?;
+        }
         _ = tokio::signal::ctrl_c() => {
             println!("Shutting down...");
         }
     }
 
+    let _ = std::fs::remove_file(&socket_path);
+
     println!("API server stopped");
 
     Ok(())
 }
 
+/// Time a client has to complete the TLS handshake before it is dropped
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accept TLS connections and serve them like the plain HTTP proxy
+async fn serve_https(
+    listener: TcpListener,
+    tls_config: Arc<rustls::ServerConfig>,
+    state: Arc<RwLock<ProxyState>>,
+) -> Result<()> {
+    let acceptor = TlsAcceptor::from(tls_config);
+
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually out of file descriptors or a connection reset
+                // before it was accepted, which must not stop the server
+                warn!("Failed to accept HTTPS connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!("TLS handshake with {} failed: {}", remote_addr, e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("TLS handshake with {} timed out", remote_addr);
+                        return;
+                    }
+                };
+
+            let conn = ClientConnection {
+                remote_addr,
+                tls: true,
+            };
+            let service = service_fn(move |req| handle_request(req, Arc::clone(&state), conn));
+            if let Err(e) = Http::new()
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+            {
+                debug!("HTTPS connection from {} failed: {}", remote_addr, e);
+            }
+        });
+    }
+}
+
+/// Bind the control socket so only the server's user can connect to it
+fn bind_control_socket(path: &Path) -> Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // A socket left behind by a previous run would make bind fail
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            anyhow::bail!(
+                "Control socket {} is in use, is another server running?",
+                path.display()
+            );
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .context(format!("Failed to bind control socket {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+/// Router for the admin listener: the dashboard at `/` and the API below it.
+/// Both need a token, as the dashboard shows the audit log.
+fn admin_router(state: Arc<RwLock<ProxyState>>) -> Router {
+    Router::new()
+        .route("/", get(admin_interface))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            api::require_token,
+        ))
+        .with_state(Arc::clone(&state))
+        .merge(api::create_api_router(state))
+}
+
 /// Handle incoming requests to the proxy server
 async fn handle_request(
-    req: Request<Body>,
+    mut req: Request<Body>,
     state: Arc<RwLock<ProxyState>>,
+    conn: ClientConnection,
 ) -> Result<Response<Body>, Infallible> {
     let headers = req.headers().clone();
     let host = headers
@@ -120,43 +353,175 @@ async fn handle_request(
         .and_then(|h| h.to_str().ok())
         .unwrap_or("");
 
-    if host.starts_with("admin-api.") {
-        let api_router = api::create_api_router(Arc::clone(&state));
-        let response = api_router.oneshot(req).await.unwrap();
+    let (
+        db_pool,
+        admin_hostname,
+        base_domain,
+        https_redirect_port,
+        acme_challenges,
+        trusted_proxies,
+        error_pages,
+    ) = {
+        let state = state.read().await;
+        (
+            state.db_pool.clone(),
+            state.admin_hostname.clone(),
+            state.base_domain.clone(),
+            state.https_redirect_port,
+            Arc::clone(&state.acme_challenges),
+            Arc::clone(&state.trusted_proxies),
+            Arc::clone(&state.error_pages),
+        )
+    };
+
+    if let Some(response) = acme_challenge(&acme_challenges, req.uri().path()) {
+        return Ok(response);
+    }
+
+    if let Some(port) = https_redirect_port.filter(|_| !conn.tls) {
+        return Ok(https_redirect(host, req.uri(), port));
+    }
+
+    if admin_hostname.is_some_and(|h| is_host(host, &h)) {
+        req.extensions_mut().insert(ConnectInfo(conn.remote_addr));
+        let response = admin_router(Arc::clone(&state)).oneshot(req).await.unwrap();
         let (parts, body) = response.into_parts();
-        let body_bytes = to_bytes(body).await.unwrap_or_default();
-        Ok(Response::from_parts(parts, Body::from(body_bytes)))
-    } else if host.starts_with("admin.") {
-        // Regular admin interface
-        Ok(admin_interface(state).await)
+        // Stream the body so server-sent events such as `logs --follow` work
+        let body = Body::wrap_stream(unfold(body, |mut body| async move {
+            body.data().await.map(|chunk| (chunk, body))
+        }));
+        Ok(Response::from_parts(parts, body))
     } else {
-        // Extract app name from host
-        let app_name = host.split('.').next().unwrap_or("");
-        if app_name.is_empty() {
-            return Ok(Response::builder()
-                .status(404)
-                .body(Body::from("No app specified in host header"))
-                .unwrap());
-        }
+        let forwarded = forwarding::apply(&mut req, conn, &trusted_proxies);
+        let entry = AccessLogEntry::new(&req, host, &forwarded);
 
-        // Proxy to app
-        match proxy_to_app(state, app_name, req).await {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                error!("Proxy error: {}", e);
-                Ok(Response::builder()
-                    .status(500)
-                    .body(Body::from(format!("Proxy error: {}", e)))
-                    .unwrap())
+        // Path-based routes for the host take precedence over host routing
+        let route = match routes::resolve(&db_pool, host, req.uri().path()).await {
+            Ok(route) => route,
+            Err(e) => return Ok(ProxyError::from(e).into_response(&error_pages)),
+        };
+        if let Some(route) = route {
+            if route.strip_prefix {
+                if let Err(e) = strip_route_prefix(&mut req, &route) {
+                    return Ok(e.into_response(&error_pages));
+                }
             }
+            return Ok(proxy_and_log(state, &route.app_name, req, entry).await);
         }
+
+        // Find the app from custom domains or the subdomain
+        let app_name = match domains::resolve_app(&db_pool, host, base_domain.as_deref()).await {
+            Ok(Some(app_name)) => app_name,
+            Ok(None) => {
+                let e = ProxyError::NoAppForHost(host.to_string());
+                return Ok(e.into_response(&error_pages));
+            }
+            Err(e) => return Ok(ProxyError::from(e).into_response(&error_pages)),
+        };
+
+        // Proxy to app
+        Ok(proxy_and_log(state, &app_name, req, entry).await)
     }
 }
 
+/// Proxy a request to an app, recording it in the app's access log
+async fn proxy_and_log(
+    state: Arc<RwLock<ProxyState>>,
+    app_name: &str,
+    req: Request<Body>,
+    entry: AccessLogEntry,
+) -> Response<Body> {
+    let (access_log, error_pages) = {
+        let state = state.read().await;
+        (
+            Arc::clone(&state.access_log),
+            Arc::clone(&state.error_pages),
+        )
+    };
+    let request_id = HeaderValue::from_str(&entry.request_id).ok();
+    let started = Instant::now();
+    let mut response = match proxy_to_app(state, app_name, req, entry.client_ip).await {
+        Ok(response) => access_log.track(app_name, entry, response, Some(started.elapsed())),
+        Err(e) => access_log.track(app_name, entry, e.into_response(&error_pages), None),
+    };
+
+    // Let clients quote the request ID when reporting problems
+    if let Some(request_id) = request_id {
+        response
+            .headers_mut()
+            .entry(forwarding::X_REQUEST_ID)
+            .or_insert(request_id);
+    }
+    response
+}
+
+/// Remove a route's prefix from the request path, telling the app the prefix
+/// it is mounted under in `X-Forwarded-Prefix`
+fn strip_route_prefix(req: &mut Request<Body>, route: &Route) -> Result<(), ProxyError> {
+    let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = Some(
+        routes::forwarded_path(route, path_and_query)
+            .parse()
+            .map_err(http::Error::from)?,
+    );
+    *req.uri_mut() = hyper::Uri::from_parts(parts).map_err(http::Error::from)?;
+
+    let prefix = HeaderValue::from_str(&route.path_prefix).map_err(http::Error::from)?;
+    req.headers_mut().insert("X-Forwarded-Prefix", prefix);
+
+    Ok(())
+}
+
+/// Answer to a pending ACME HTTP-01 challenge. Other requests under the
+/// challenge path go to the apps, which may validate domains of their own.
+fn acme_challenge(challenges: &AcmeChallenges, path: &str) -> Option<Response<Body>> {
+    let token = path.strip_prefix(acme::CHALLENGE_PATH)?;
+    let key_authorization = challenges.get(token)?;
+    Some(
+        Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(key_authorization))
+            .unwrap(),
+    )
+}
+
+/// Permanent redirect of a plain HTTP request to the same URL over HTTPS
+fn https_redirect(host: &str, uri: &hyper::Uri, port: u16) -> Response<Body> {
+    let hostname = domains::strip_port(host);
+    let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+    let location = match port {
+        443 => format!("https://{}{}", hostname, path_and_query),
+        port => format!("https://{}:{}{}", hostname, port, path_and_query),
+    };
+
+    Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(hyper::header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Whether a `Host` header, which may include a port, names `hostname`
+fn is_host(host: &str, hostname: &str) -> bool {
+    domains::strip_port(host).eq_ignore_ascii_case(hostname)
+}
+
 /// Admin interface handler
-async fn admin_interface(state: Arc<RwLock<ProxyState>>) -> Response<Body> {
-    let pool = state.read().await.db_pool.clone();
+async fn admin_interface(
+    Extension(api_token): Extension<ApiToken>,
+    State(state): State<Arc<RwLock<ProxyState>>>,
+) -> Result<Response<Body>, ApiError> {
+    api::authorize(&api_token, TokenScope::Admin, None)?;
+
+    let (pool, metrics) = {
+        let state = state.read().await;
+        (state.db_pool.clone(), Arc::clone(&state.upstream.metrics))
+    };
     let apps = db::apps::get_all(&pool).await.unwrap_or_else(|_| vec![]);
+    let audit_entries = db::audit_log::get(&pool, None, None, 50)
+        .await
+        .unwrap_or_else(|_| vec![]);
 
     // Build HTML response
     let mut html = String::from(
@@ -173,7 +538,10 @@ async fn admin_interface(state: Arc<RwLock<ProxyState>>) -> Response<Body> {
         th { background-color: #4CAF50; color: white; }
         .running { color: green; }
         .stopped { color: red; }
+        .sleeping { color: gray; }
         .created { color: blue; }
+        .success { color: green; }
+        .failure { color: red; }
     </style>
 </head>
 <body>
@@ -195,6 +563,7 @@ async fn admin_interface(state: Arc<RwLock<ProxyState>>) -> Response<Body> {
         let status_class = match app.state {
             AppState::Running => "running",
             AppState::Stopped => "stopped",
+            AppState::Sleeping => "sleeping",
             _ => "created",
         };
 
@@ -223,84 +592,161 @@ async fn admin_interface(state: Arc<RwLock<ProxyState>>) -> Response<Body> {
         ));
     }
 
+    html.push_str(&format!(
+        r#"
+    </table>
+    <h2>Proxy</h2>
+    <table>
+        <tr><td>Open upgraded connections</td><td>{}</td></tr>
+        <tr><td>Upgraded connections since start</td><td>{}</td></tr>
+        <tr><td>Requests waiting for restarting apps</td><td>{}</td></tr>
+        <tr><td>Requests over rate limits</td><td>{}</td></tr>
+        <tr><td>Requests over concurrency limits</td><td>{}</td></tr>
+        <tr><td>Request bodies over size limits</td><td>{}</td></tr>
+        <tr><td>Open TCP connections</td><td>{}</td></tr>
+        <tr><td>TCP connections since start</td><td>{}</td></tr>
+    </table>"#,
+        metrics.active_upgrades.load(Ordering::Relaxed),
+        metrics.total_upgrades.load(Ordering::Relaxed),
+        metrics.held_requests.load(Ordering::Relaxed),
+        metrics.rate_limited.load(Ordering::Relaxed),
+        metrics.concurrency_limited.load(Ordering::Relaxed),
+        metrics.body_too_large.load(Ordering::Relaxed),
+        metrics.active_tcp_connections.load(Ordering::Relaxed),
+        metrics.total_tcp_connections.load(Ordering::Relaxed),
+    ));
+
+    html.push_str(
+        r#"
+    <h2>Recent Activity</h2>
+    <table>
+        <tr>
+            <th>Time</th>
+            <th>Actor</th>
+            <th>Action</th>
+            <th>App</th>
+            <th>Parameters</th>
+            <th>Source IP</th>
+            <th>Result</th>
+        </tr>
+"#,
+    );
+
+    // Add rows for each audit log entry
+    for entry in audit_entries {
+        let result = match &entry.message {
+            Some(message) => format!("{}: {}", entry.result, message),
+            None => entry.result.to_string(),
+        };
+
+        html.push_str(&format!(
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td class="{}">{}</td>
+        </tr>"#,
+            entry.created_at.format("%Y-%m-%d %H:%M:%S"),
+            escape_html(&entry.actor),
+            escape_html(&entry.action),
+            escape_html(entry.app_name.as_deref().unwrap_or("-")),
+            escape_html(&entry.parameters.map_or("-".to_string(), |p| p.to_string())),
+            escape_html(entry.source_ip.as_deref().unwrap_or("-")),
+            entry.result,
+            escape_html(&result),
+        ));
+    }
+
     // Add API information
     html.push_str(
         r#"
     </table>
     <h2>API Endpoints</h2>
     <ul>
-        <li>GET /api/apps - List all apps</li>
-        <li>POST /api/apps - Create a new app</li>
-        <li>GET /api/apps/:id - Get app details</li>
-        <li>DELETE /api/apps/:id - Delete an app</li>
-        <li>POST /api/apps/:id/start - Start an app</li>
-        <li>POST /api/apps/:id/stop - Stop an app</li>
+        <li>GET /v1/apps - List all apps</li>
+        <li>POST /v1/apps - Create a new app</li>
+        <li>GET /v1/apps/:name - Get app details</li>
+        <li>DELETE /v1/apps/:name - Delete an app</li>
+        <li>POST /v1/apps/:name/start - Start an app</li>
+        <li>POST /v1/apps/:name/stop - Stop an app</li>
+        <li>GET /v1/openapi.json - OpenAPI description of the full API</li>
     </ul>
 </body>
 </html>
 "#,
     );
 
-    Response::builder()
+    Ok(Response::builder()
         .header("Content-Type", "text/html")
         .body(Body::from(html))
-        .unwrap()
+        .unwrap())
 }
 
-/// Proxy request to app
-async fn proxy_to_app(
-    state: Arc<RwLock<ProxyState>>,
-    app_name: &str,
-    req: Request<Body>,
-) -> anyhow::Result<Response<Body>> {
-    let pool = state.read().await.db_pool.clone();
-    let app = db::apps::get_by_name(&pool, app_name)
-        .await?
-        .ok_or_else(|| anyhow::anyhow!("App '{}' not found", app_name))?;
-
-    // Check if app is running
-    if app.state != AppState::Running {
-        return Ok(Response::builder()
-            .status(503)
-            .body(Body::from(format!("App '{}' is not running", app_name)))
-            .unwrap());
-    }
+/// Escape text for inclusion in the admin HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
 
-    // Create URI for proxying
-    let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("");
-    let uri = format!(
-        "http://{}:{}{}",
-        app.host,
-        app.port.map_or("".to_string(), |p| p.to_string()),
-        path_and_query
-    );
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    // Create new request
-    let (parts, body) = req.into_parts();
-    let mut new_req = Request::builder().method(parts.method).uri(uri);
+    #[test]
+    fn test_acme_challenge() {
+        let challenges = AcmeChallenges::default();
+        challenges.insert("token", "token.thumbprint".to_string());
 
-    // Copy headers
-    for (name, value) in parts.headers.iter() {
-        if name != "host" {
-            new_req = new_req.header(name, value);
-        }
-    }
+        let path = format!("{}token", acme::CHALLENGE_PATH);
+        let response = acme_challenge(&challenges, &path).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
 
-    // Add custom headers
-    if let Some(host) = parts.headers.get("host") {
-        new_req = new_req.header("X-Forwarded-Host", host);
+        // Unknown tokens are left to the apps
+        let path = format!("{}other", acme::CHALLENGE_PATH);
+        assert!(acme_challenge(&challenges, &path).is_none());
+        assert!(acme_challenge(&challenges, "/token").is_none());
     }
-    new_req = new_req.header("X-Forwarded-Proto", "http");
-
-    // Build request
-    let new_req = new_req.body(body).context("Failed to build request")?;
 
-    // Send request
-    let client = Client::new();
-    let resp = client
-        .request(new_req)
-        .await
-        .context("Proxy request failed")?;
+    #[test]
+    fn test_https_redirect() {
+        let uri: hyper::Uri = "/login?next=%2F".parse().unwrap();
+        let response = https_redirect("web.example.com:80", &uri, 443);
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[hyper::header::LOCATION],
+            "https://web.example.com/login?next=%2F"
+        );
+
+        let response = https_redirect("web.example.com", &uri, 8443);
+        assert_eq!(
+            response.headers()[hyper::header::LOCATION],
+            "https://web.example.com:8443/login?next=%2F"
+        );
+
+        let response = https_redirect("[::1]:8080", &uri, 8443);
+        assert_eq!(
+            response.headers()[hyper::header::LOCATION],
+            "https://[::1]:8443/login?next=%2F"
+        );
+        let response = https_redirect("[::1]", &uri, 443);
+        assert_eq!(
+            response.headers()[hyper::header::LOCATION],
+            "https://[::1]/login?next=%2F"
+        );
+    }
 
-    Ok(resp)
+    #[test]
+    fn test_is_host() {
+        assert!(is_host("admin.example.com", "admin.example.com"));
+        assert!(is_host("Admin.Example.com:8080", "admin.example.com"));
+        assert!(!is_host("admin.example.com.evil.io", "admin.example.com"));
+        assert!(!is_host("web.example.com", "admin.example.com"));
+        assert!(is_host("[::1]:8080", "[::1]"));
+        assert!(is_host("[::1]", "[::1]"));
+    }
 }
@@ -0,0 +1,397 @@
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use super::access_control;
+use super::proxy::{self, KeepAwake, ProxyError, Upstream};
+use crate::config::ServerConfig;
+use crate::db;
+use crate::models::Service;
+use crate::supervisor::SUPERVISOR;
+
+/// Listens on the public port of every TCP service and passes each
+/// connection through to one of the app's instances. Apps' limits,
+/// allowlists and maintenance mode apply per connection.
+pub struct TcpProxy {
+    pool: Pool<Sqlite>,
+    upstream: Upstream,
+    host: String,
+    ports: RangeInclusive<u16>,
+    connect_timeout: Duration,
+    idle_timeout: Duration,
+    listeners: Mutex<HashMap<String, Listener>>,
+}
+
+/// Accept loop of one TCP service
+struct Listener {
+    port: u16,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl TcpProxy {
+    /// A proxy sharing `upstream`'s balancer, limiter and metrics with the
+    /// HTTP proxy. Nothing is listened on until the first `sync`.
+    pub fn new(pool: Pool<Sqlite>, upstream: Upstream, config: &ServerConfig) -> Self {
+        Self {
+            pool,
+            upstream,
+            host: config.host.clone(),
+            ports: config.tcp.port_range_start..=config.tcp.port_range_end,
+            connect_timeout: Duration::from_secs(config.upstream.connect_timeout_secs),
+            idle_timeout: Duration::from_secs(config.tcp.idle_timeout_secs),
+            listeners: Mutex::default(),
+        }
+    }
+
+    /// Address TCP services listen on
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Ports handed out to TCP services
+    pub fn ports(&self) -> RangeInclusive<u16> {
+        self.ports.clone()
+    }
+
+    /// Port an app's listener is bound to, if it has one
+    pub async fn listening_port(&self, app_name: &str) -> Option<u16> {
+        self.listeners
+            .lock()
+            .await
+            .get(app_name)
+            .map(|listener| listener.port)
+    }
+
+    /// Apps listened for and their ports, by app name
+    pub async fn listeners(&self) -> Vec<(String, u16)> {
+        let mut listeners: Vec<(String, u16)> = self
+            .listeners
+            .lock()
+            .await
+            .iter()
+            .map(|(app_name, listener)| (app_name.clone(), listener.port))
+            .collect();
+        listeners.sort();
+        listeners
+    }
+
+    /// Listen on the port of every TCP service, and stop listening for apps
+    /// that were deleted or serve HTTP again. Connections already open are
+    /// left to finish. Returns the apps whose port could not be bound.
+    pub async fn sync(self: &Arc<Self>) -> Result<Vec<(String, io::Error)>, db::DatabaseError> {
+        let services: HashMap<String, u16> = db::apps::get_all(&self.pool)
+            .await?
+            .into_iter()
+            .filter_map(|app| app.service.listen_port().map(|port| (app.name, port)))
+            .collect();
+
+        let mut listeners = self.listeners.lock().await;
+        listeners.retain(|app_name, listener| {
+            let keep = services.get(app_name) == Some(&listener.port);
+            if !keep {
+                info!(
+                    "Stopped listening on port {} for app '{}'",
+                    listener.port, app_name
+                );
+            }
+            keep
+        });
+
+        let mut failed = Vec::new();
+        for (app_name, port) in services {
+            if listeners.contains_key(&app_name) {
+                continue;
+            }
+            match TcpListener::bind((self.host.as_str(), port)).await {
+                Ok(listener) => {
+                    info!("Listening on port {} for app '{}'", port, app_name);
+                    let task = tokio::spawn(Arc::clone(self).accept(app_name.clone(), listener));
+                    listeners.insert(app_name, Listener { port, task });
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to listen on port {} for app '{}': {}",
+                        port, app_name, e
+                    );
+                    failed.push((app_name, e));
+                }
+            }
+        }
+
+        Ok(failed)
+    }
+
+    async fn accept(self: Arc<Self>, app_name: String, listener: TcpListener) {
+        loop {
+            let (stream, remote_addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors, which may pass
+                    warn!("Failed to accept connection for app '{}': {}", app_name, e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let proxy = Arc::clone(&self);
+            let app_name = app_name.clone();
+            tokio::spawn(async move {
+                match proxy.handle(&app_name, stream, remote_addr).await {
+                    Ok(()) => {}
+                    Err(e) if e.status().is_server_error() => warn!(
+                        "TCP connection from {} to app '{}' failed: {}",
+                        remote_addr, app_name, e
+                    ),
+                    Err(e) => debug!(
+                        "TCP connection from {} to app '{}' refused: {}",
+                        remote_addr, app_name, e
+                    ),
+                }
+            });
+        }
+    }
+
+    /// Pass a client's connection through to the app, or close it if the
+    /// app does not let it in
+    async fn handle(
+        &self,
+        app_name: &str,
+        client: TcpStream,
+        remote_addr: SocketAddr,
+    ) -> Result<(), ProxyError> {
+        let Upstream {
+            hold,
+            balancer,
+            limiter,
+            metrics,
+            ..
+        } = &self.upstream;
+        let client_ip = remote_addr.ip();
+
+        let app = db::apps::get_by_name(&self.pool, app_name)
+            .await?
+            .ok_or_else(|| ProxyError::AppNotFound(app_name.to_string()))?;
+        if app.service == Service::Http {
+            return Err(ProxyError::AppNotFound(app_name.to_string()));
+        }
+
+        if let Some(maintenance) = &app.maintenance {
+            if !access_control::allows(&maintenance.allow, client_ip) {
+                return Err(ProxyError::Maintenance {
+                    app: app_name.to_string(),
+                    page: None,
+                    retry_after: maintenance.retry_after,
+                });
+            }
+        }
+
+        // Basic auth users have nothing to sign in with over raw TCP, so
+        // only the allowlist applies
+        let allow = &app.access.allow;
+        if !allow.is_empty() && !access_control::allows(allow, client_ip) {
+            return Err(ProxyError::Forbidden(app_name.to_string()));
+        }
+
+        // The request rate limits new connections
+        if let Some(rate) = app.limits.requests_per_second {
+            let burst = app.limits.burst.unwrap_or(rate);
+            if let Err(wait) = limiter.check_rate(app_name, client_ip, rate, burst) {
+                metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+                return Err(ProxyError::RateLimited(app_name.to_string(), wait));
+            }
+        }
+
+        record_activity(app_name);
+        let app = proxy::wait_until_ready(&self.pool, app, hold, metrics).await?;
+
+        let _permit = match app.limits.max_concurrent {
+            Some(max) => match limiter.acquire(app_name, max) {
+                Some(permit) => Some(permit),
+                None => {
+                    metrics.concurrency_limited.fetch_add(1, Ordering::Relaxed);
+                    return Err(ProxyError::TooManyConcurrentRequests(app_name.to_string()));
+                }
+            },
+            None => None,
+        };
+
+        let ports = SUPERVISOR
+            .get()
+            .and_then(|supervisor| supervisor.instance_ports(app_name))
+            .unwrap_or_else(|| app.port.into_iter().collect());
+        let lease = balancer
+            .pick(app_name, &ports)
+            .ok_or_else(|| ProxyError::AppNotRunning(app_name.to_string()))?;
+
+        let connect = TcpStream::connect((app.host.as_str(), lease.port));
+        let upstream = match tokio::time::timeout(self.connect_timeout, connect).await {
+            Ok(Ok(upstream)) => upstream,
+            Ok(Err(e)) => return Err(ProxyError::TcpConnectFailed(app_name.to_string(), e)),
+            Err(_) => return Err(ProxyError::Timeout(app_name.to_string())),
+        };
+        let _ = client.set_nodelay(true);
+        let _ = upstream.set_nodelay(true);
+
+        metrics
+            .active_tcp_connections
+            .fetch_add(1, Ordering::Relaxed);
+        metrics
+            .total_tcp_connections
+            .fetch_add(1, Ordering::Relaxed);
+        let awake = KeepAwake::start(&app);
+        let result = proxy::splice(client, upstream, self.idle_timeout).await;
+        drop(awake);
+        metrics
+            .active_tcp_connections
+            .fetch_sub(1, Ordering::Relaxed);
+
+        match result {
+            Ok((sent, received)) => debug!(
+                "TCP connection from {} to app '{}' closed ({} bytes sent, {} received)",
+                remote_addr, app_name, sent, received
+            ),
+            Err(e) => debug!(
+                "TCP connection from {} to app '{}' failed: {}",
+                remote_addr, app_name, e
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Note a new connection to an app, keeping it awake
+fn record_activity(app_name: &str) {
+    if let Some(supervisor) = SUPERVISOR.get() {
+        supervisor.record_activity(app_name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::UpstreamConfig;
+    use crate::models::{App, AppState, Maintenance};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// An app that echoes whatever it receives
+    async fn echo_app() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    let _ = tokio::io::copy(&mut read, &mut write).await;
+                });
+            }
+        });
+        port
+    }
+
+    /// A port nothing listens on right now
+    fn unused_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    async fn tcp_proxy(pool: &Pool<Sqlite>) -> Arc<TcpProxy> {
+        let config = ServerConfig {
+            host: "127.0.0.1".to_string(),
+            ..Default::default()
+        };
+        let upstream = Upstream::new(&UpstreamConfig::default());
+        Arc::new(TcpProxy::new(pool.clone(), upstream, &config))
+    }
+
+    async fn save_service(pool: &Pool<Sqlite>, name: &str, port: u16, listen_port: u16) -> App {
+        let mut app = App::new(name).unwrap();
+        app.host = "127.0.0.1".to_string();
+        app.port = Some(port);
+        app.state = AppState::Running;
+        app.service = Service::Tcp { listen_port };
+        db::apps::save(pool, &app).await.unwrap();
+        app
+    }
+
+    #[tokio::test]
+    async fn test_proxies_tcp_services() {
+        let pool = db::test::get_test_pool().await;
+        let listen_port = unused_port();
+        let mut app = save_service(&pool, "cache", echo_app().await, listen_port).await;
+        let proxy = tcp_proxy(&pool).await;
+        assert!(proxy.sync().await.unwrap().is_empty());
+        assert_eq!(proxy.listening_port("cache").await, Some(listen_port));
+
+        let mut client = TcpStream::connect(("127.0.0.1", listen_port))
+            .await
+            .unwrap();
+        client.write_all(b"PING\r\n").await.unwrap();
+        let mut buf = [0u8; 6];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"PING\r\n");
+        drop(client);
+
+        // Apps that serve HTTP again stop listening
+        app.service = Service::Http;
+        db::apps::save(&pool, &app).await.unwrap();
+        proxy.sync().await.unwrap();
+        assert_eq!(proxy.listening_port("cache").await, None);
+        tokio::task::yield_now().await;
+        assert!(TcpStream::connect(("127.0.0.1", listen_port))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_closes_refused_connections() {
+        let pool = db::test::get_test_pool().await;
+        let listen_port = unused_port();
+        let mut app = save_service(&pool, "db", echo_app().await, listen_port).await;
+        app.maintenance = Some(Maintenance {
+            since: chrono::Utc::now(),
+            page: None,
+            allow: vec![],
+            retry_after: 60,
+        });
+        db::apps::save(&pool, &app).await.unwrap();
+        let proxy = tcp_proxy(&pool).await;
+        proxy.sync().await.unwrap();
+
+        let mut client = TcpStream::connect(("127.0.0.1", listen_port))
+            .await
+            .unwrap();
+        let _ = client.write_all(b"PING\r\n").await;
+        let mut buf = Vec::new();
+        let read = client.read_to_end(&mut buf).await;
+        assert!(read.map_or(true, |n| n == 0));
+    }
+
+    #[tokio::test]
+    async fn test_reports_ports_in_use() {
+        let pool = db::test::get_test_pool().await;
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let listen_port = taken.local_addr().unwrap().port();
+        save_service(&pool, "db", echo_app().await, listen_port).await;
+
+        let proxy = tcp_proxy(&pool).await;
+        let failed = proxy.sync().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, "db");
+        assert_eq!(proxy.listening_port("db").await, None);
+    }
+}
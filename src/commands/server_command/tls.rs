@@ -0,0 +1,380 @@
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, instrument, warn};
+
+use crate::config::TlsConfig;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("Failed to read {0}: {1}")]
+    IoError(PathBuf, std::io::Error),
+    #[error("No certificates found in {0}")]
+    NoCertificates(PathBuf),
+    #[error("No private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error("Unsupported private key in {0}")]
+    UnsupportedKey(PathBuf),
+    #[error("Private key in {0} does not belong to the certificate")]
+    KeyMismatch(PathBuf),
+    #[error("Config error: {0}")]
+    ConfigError(#[from] crate::config::ConfigError),
+}
+
+type Result<T> = std::result::Result<T, TlsError>;
+
+/// Certificate file inside a domain's directory in the certs dir
+pub const CERT_FILE: &str = "cert.pem";
+/// Private key file inside a domain's directory in the certs dir
+pub const KEY_FILE: &str = "key.pem";
+
+/// Certificates by domain, selected by the SNI name of each TLS handshake.
+///
+/// Each domain has a directory in the certs dir holding `cert.pem` (the full
+/// chain) and `key.pem`. Wildcard certificates live in a directory named
+/// with `_` in place of `*`, e.g. `_.example.com`.
+#[derive(Default)]
+pub struct CertStore {
+    certs: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    /// Modification times of the files the current certificates came from
+    loaded: RwLock<HashMap<PathBuf, SystemTime>>,
+}
+
+impl CertStore {
+    /// Find the certificate for `server_name`: an exact match first, then a
+    /// wildcard for its parent domain
+    pub fn get(&self, server_name: &str) -> Option<Arc<CertifiedKey>> {
+        let server_name = server_name.to_ascii_lowercase();
+        let certs = self.certs.read().unwrap();
+        if let Some(cert) = certs.get(&server_name) {
+            return Some(Arc::clone(cert));
+        }
+
+        let (_, parent) = server_name.split_once('.')?;
+        certs.get(&format!("*.{}", parent)).cloned()
+    }
+
+    /// Domains with a loaded certificate
+    pub fn domains(&self) -> Vec<String> {
+        let mut domains: Vec<String> = self.certs.read().unwrap().keys().cloned().collect();
+        domains.sort();
+        domains
+    }
+
+    /// Load all certificates from the certs dir and the configured
+    /// per-domain certificates, replacing the current ones. A domain whose
+    /// files fail to load keeps its previous certificate.
+    #[instrument(skip(self, config))]
+    pub fn reload(&self, config: &TlsConfig) -> Result<usize> {
+        let sources = cert_sources(config)?;
+        let mut certs = HashMap::new();
+        let mut loaded = HashMap::new();
+
+        for (domain, cert_path, key_path) in sources {
+            match load_certified_key(&cert_path, &key_path) {
+                Ok(cert) => {
+                    certs.insert(domain, Arc::new(cert));
+                }
+                Err(e) => {
+                    error!("Failed to load certificate for {}: {}", domain, e);
+                    if let Some(previous) = self.certs.read().unwrap().get(&domain) {
+                        certs.insert(domain, Arc::clone(previous));
+                    }
+                }
+            }
+            for path in [cert_path, key_path] {
+                if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                    loaded.insert(path, modified);
+                }
+            }
+        }
+
+        let count = certs.len();
+        *self.certs.write().unwrap() = certs;
+        *self.loaded.write().unwrap() = loaded;
+        info!("Loaded {} TLS certificate(s)", count);
+
+        Ok(count)
+    }
+
+    /// Whether any certificate file was added, removed or modified since the
+    /// last reload
+    pub fn has_changed(&self, config: &TlsConfig) -> Result<bool> {
+        let loaded = self.loaded.read().unwrap();
+        let mut seen = 0;
+        for (_, cert_path, key_path) in cert_sources(config)? {
+            for path in [cert_path, key_path] {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                if modified.is_none() || loaded.get(&path) != modified.as_ref() {
+                    return Ok(true);
+                }
+                seen += 1;
+            }
+        }
+
+        Ok(seen != loaded.len())
+    }
+}
+
+impl std::fmt::Debug for CertStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertStore")
+            .field("domains", &self.domains())
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name()?;
+        let cert = self.get(server_name);
+        if cert.is_none() {
+            warn!("No TLS certificate for {}", server_name);
+        }
+        cert
+    }
+}
+
+/// Every (domain, cert path, key path) to load, with per-domain config
+/// taking precedence over the certs dir
+fn cert_sources(config: &TlsConfig) -> Result<Vec<(String, PathBuf, PathBuf)>> {
+    let mut sources: HashMap<String, (PathBuf, PathBuf)> = HashMap::new();
+
+    let certs_dir = config.certs_dir()?;
+    if certs_dir.exists() {
+        let entries =
+            fs::read_dir(&certs_dir).map_err(|e| TlsError::IoError(certs_dir.clone(), e))?;
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            let cert_path = dir.join(CERT_FILE);
+            let key_path = dir.join(KEY_FILE);
+            if !cert_path.exists() || !key_path.exists() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+            let domain = match name.strip_prefix("_.") {
+                Some(parent) => format!("*.{}", parent),
+                None => name,
+            };
+            sources.insert(domain, (cert_path, key_path));
+        }
+    }
+
+    for cert in &config.certificates {
+        sources.insert(
+            cert.domain.to_ascii_lowercase(),
+            (cert.cert_path.clone(), cert.key_path.clone()),
+        );
+    }
+
+    Ok(sources
+        .into_iter()
+        .map(|(domain, (cert, key))| (domain, cert, key))
+        .collect())
+}
+
+/// Load a PEM certificate chain and the private key of its leaf certificate
+pub fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let cert_file =
+        fs::File::open(cert_path).map_err(|e| TlsError::IoError(cert_path.to_path_buf(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .map_err(|e| TlsError::IoError(cert_path.to_path_buf(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(cert_path.to_path_buf()));
+    }
+
+    let key_file =
+        fs::File::open(key_path).map_err(|e| TlsError::IoError(key_path.to_path_buf(), e))?;
+    let key = rustls_pemfile::read_all(&mut BufReader::new(key_file))
+        .map_err(|e| TlsError::IoError(key_path.to_path_buf(), e))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::NoPrivateKey(key_path.to_path_buf()))?;
+
+    let signing_key = rustls::sign::any_supported_type(&rustls::PrivateKey(key))
+        .map_err(|_| TlsError::UnsupportedKey(key_path.to_path_buf()))?;
+    // A key and certificate replaced one after the other may not match yet
+    if !key_matches(&certs[0], signing_key.as_ref()) {
+        return Err(TlsError::KeyMismatch(key_path.to_path_buf()));
+    }
+
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(rustls::Certificate).collect(),
+        signing_key,
+    ))
+}
+
+/// Whether `key` belongs to the certificate, checked by verifying a
+/// signature made with it against the certificate's public key
+fn key_matches(cert_der: &[u8], key: &dyn rustls::sign::SigningKey) -> bool {
+    use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+    use rustls::SignatureScheme;
+
+    const MESSAGE: &[u8] = b"bindrop certificate key check";
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(cert_der) else {
+        return false;
+    };
+    let Some(signer) = key.choose_scheme(&[
+        SignatureScheme::ECDSA_NISTP256_SHA256,
+        SignatureScheme::ECDSA_NISTP384_SHA384,
+        SignatureScheme::ED25519,
+        SignatureScheme::RSA_PKCS1_SHA256,
+    ]) else {
+        return false;
+    };
+    let algorithm: &'static dyn VerificationAlgorithm = match signer.scheme() {
+        SignatureScheme::ECDSA_NISTP256_SHA256 => &signature::ECDSA_P256_SHA256_ASN1,
+        SignatureScheme::ECDSA_NISTP384_SHA384 => &signature::ECDSA_P384_SHA384_ASN1,
+        SignatureScheme::ED25519 => &signature::ED25519,
+        _ => &signature::RSA_PKCS1_2048_8192_SHA256,
+    };
+
+    let public_key = UnparsedPublicKey::new(
+        algorithm,
+        cert.public_key().subject_public_key.data.as_ref(),
+    );
+    signer
+        .sign(MESSAGE)
+        .is_ok_and(|sig| public_key.verify(MESSAGE, &sig).is_ok())
+}
+
+/// rustls server config that picks certificates from `store`
+pub fn server_config(store: Arc<CertStore>) -> Arc<rustls::ServerConfig> {
+    let mut config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(store);
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Arc::new(config)
+}
+
+/// Reload certificates whenever their files change
+pub fn spawn_reloader(store: Arc<CertStore>, config: TlsConfig) {
+    let interval = Duration::from_secs(config.reload_interval_secs.max(1));
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            match store.has_changed(&config) {
+                Ok(true) => {
+                    info!("TLS certificates changed, reloading");
+                    if let Err(e) = store.reload(&config) {
+                        error!("Failed to reload TLS certificates: {}", e);
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to check TLS certificates: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_cert(dir: &Path, names: &[&str]) {
+        let cert = rcgen::generate_simple_self_signed(
+            names.iter().map(|n| n.to_string()).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(CERT_FILE), cert.serialize_pem().unwrap()).unwrap();
+        fs::write(dir.join(KEY_FILE), cert.serialize_private_key_pem()).unwrap();
+    }
+
+    fn tls_config(certs_dir: &Path) -> TlsConfig {
+        TlsConfig {
+            certs_dir: Some(certs_dir.to_path_buf()),
+            ..TlsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_select_by_sni() {
+        let dir = tempfile::tempdir().unwrap();
+        write_cert(&dir.path().join("example.com"), &["example.com"]);
+        write_cert(
+            &dir.path().join("_.apps.example.com"),
+            &["*.apps.example.com"],
+        );
+
+        let store = CertStore::default();
+        assert_eq!(store.reload(&tls_config(dir.path())).unwrap(), 2);
+        assert_eq!(store.domains(), vec!["*.apps.example.com", "example.com"]);
+
+        assert!(store.get("example.com").is_some());
+        assert!(store.get("Web.Apps.Example.com").is_some());
+        assert!(store.get("other.example.com").is_none());
+        assert!(store.get("a.b.apps.example.com").is_none());
+    }
+
+    #[test]
+    fn test_detects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = tls_config(dir.path());
+        write_cert(&dir.path().join("example.com"), &["example.com"]);
+
+        let store = CertStore::default();
+        store.reload(&config).unwrap();
+        assert!(!store.has_changed(&config).unwrap());
+
+        write_cert(&dir.path().join("example.org"), &["example.org"]);
+        assert!(store.has_changed(&config).unwrap());
+        store.reload(&config).unwrap();
+        assert!(store.get("example.org").is_some());
+
+        fs::remove_dir_all(dir.path().join("example.com")).unwrap();
+        assert!(store.has_changed(&config).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_cert_keeps_previous() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = tls_config(dir.path());
+        let domain_dir = dir.path().join("example.com");
+        write_cert(&domain_dir, &["example.com"]);
+
+        let store = CertStore::default();
+        store.reload(&config).unwrap();
+
+        fs::write(domain_dir.join(CERT_FILE), "not a certificate").unwrap();
+        store.reload(&config).unwrap();
+        assert!(store.get("example.com").is_some());
+    }
+
+    #[test]
+    fn test_mismatched_key_keeps_previous() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = tls_config(dir.path());
+        let domain_dir = dir.path().join("example.com");
+        write_cert(&domain_dir, &["example.com"]);
+
+        let store = CertStore::default();
+        store.reload(&config).unwrap();
+        let previous = store.get("example.com").unwrap();
+
+        // A new key next to the old certificate is not loaded
+        let other = rcgen::generate_simple_self_signed(vec!["example.com".to_string()]).unwrap();
+        fs::write(domain_dir.join(KEY_FILE), other.serialize_private_key_pem()).unwrap();
+        assert!(matches!(
+            load_certified_key(&domain_dir.join(CERT_FILE), &domain_dir.join(KEY_FILE)),
+            Err(TlsError::KeyMismatch(_))
+        ));
+        store.reload(&config).unwrap();
+        assert!(Arc::ptr_eq(&store.get("example.com").unwrap(), &previous));
+
+        fs::write(domain_dir.join(CERT_FILE), other.serialize_pem().unwrap()).unwrap();
+        store.reload(&config).unwrap();
+        assert!(!Arc::ptr_eq(&store.get("example.com").unwrap(), &previous));
+    }
+}
@@ -0,0 +1,185 @@
+use sqlx::{Pool, Sqlite};
+use tracing::{info, instrument};
+
+use crate::db;
+use crate::models::{ApiToken, TokenScope};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    #[error("Token already exists: {0}")]
+    TokenAlreadyExists(String),
+    #[error("Token not found: {0}")]
+    TokenNotFound(String),
+    #[error("Invalid token name: {0}")]
+    InvalidName(String),
+    #[error("Invalid app restriction: {0}")]
+    InvalidApp(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::db::DatabaseError),
+}
+
+type Result<T> = std::result::Result<T, TokenError>;
+
+/// Create a new API token and return it together with its plaintext secret.
+/// Without any scopes the token is granted `admin`.
+#[instrument(skip(pool))]
+pub async fn create(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    scopes: Vec<TokenScope>,
+    apps: Option<Vec<String>>,
+) -> Result<(ApiToken, String)> {
+    if name.trim().is_empty() {
+        return Err(TokenError::InvalidName(name.to_string()));
+    }
+
+    if let Some(apps) = &apps {
+        if let Some(app) = apps.iter().find(|a| a.trim().is_empty()) {
+            return Err(TokenError::InvalidApp(app.to_string()));
+        }
+    }
+
+    let scopes = if scopes.is_empty() {
+        vec![TokenScope::Admin]
+    } else {
+        scopes.into_iter().fold(Vec::new(), |mut acc, scope| {
+            if !acc.contains(&scope) {
+                acc.push(scope);
+            }
+            acc
+        })
+    };
+
+    if db::api_tokens::get_by_name(pool, name).await?.is_some() {
+        return Err(TokenError::TokenAlreadyExists(name.to_string()));
+    }
+
+    let (token, secret) = ApiToken::generate(name, scopes, apps);
+    db::api_tokens::save(pool, &token).await?;
+
+    info!("Created API token '{}' ({})", name, token.permissions());
+
+    Ok((token, secret))
+}
+
+/// List all API tokens
+#[instrument(skip(pool))]
+pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<ApiToken>> {
+    Ok(db::api_tokens::get_all(pool).await?)
+}
+
+/// Revoke an API token
+#[instrument(skip(pool))]
+pub async fn revoke(pool: &Pool<Sqlite>, name: &str) -> Result<()> {
+    if db::api_tokens::delete_by_name(pool, name).await? == 0 {
+        return Err(TokenError::TokenNotFound(name.to_string()));
+    }
+
+    info!("Revoked API token '{}'", name);
+
+    Ok(())
+}
+
+/// Look up the token matching a plaintext secret and record its use
+#[instrument(skip(pool, secret))]
+pub async fn authenticate(pool: &Pool<Sqlite>, secret: &str) -> Result<Option<ApiToken>> {
+    let token = match db::api_tokens::get_by_hash(pool, &ApiToken::hash(secret)).await? {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    db::api_tokens::touch(pool, &token.id).await?;
+
+    Ok(Some(token))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test::get_test_pool;
+
+    #[tokio::test]
+    async fn test_create_stores_only_hash() {
+        let pool = get_test_pool().await;
+        let (_, secret) = create(&pool, "laptop", vec![], None).await.unwrap();
+
+        let token = db::api_tokens::get_by_name(&pool, "laptop")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(token.token_hash, secret);
+        assert_eq!(token.token_hash, ApiToken::hash(&secret));
+        assert!(token.last_used_at.is_none());
+        assert_eq!(token.scopes, vec![TokenScope::Admin]);
+        assert_eq!(token.apps, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_scoped_token() {
+        let pool = get_test_pool().await;
+        let (token, _) = create(
+            &pool,
+            "ci",
+            vec![TokenScope::Deploy, TokenScope::Control],
+            Some(vec!["web".to_string()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(token.permissions(), "deploy, control on apps: web");
+
+        let token = db::api_tokens::get_by_name(&pool, "ci")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token.allows(TokenScope::Deploy, Some("web")));
+        assert!(token.allows(TokenScope::Control, Some("web")));
+        assert!(!token.allows(TokenScope::Deploy, Some("api")));
+        assert!(!token.allows(TokenScope::Admin, Some("web")));
+        assert!(!token.allows(TokenScope::Read, Some("web")));
+        assert!(!token.allows(TokenScope::Deploy, None));
+    }
+
+    #[tokio::test]
+    async fn test_create_duplicate_name() {
+        let pool = get_test_pool().await;
+        create(&pool, "laptop", vec![], None).await.unwrap();
+
+        let got = create(&pool, "laptop", vec![], None).await.unwrap_err();
+        match got {
+            TokenError::TokenAlreadyExists(ref n) if n == "laptop" => {}
+            _ => panic!("Expected TokenAlreadyExists, got: {:?}", got),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_records_last_used() {
+        let pool = get_test_pool().await;
+        let (_, secret) = create(&pool, "laptop", vec![], None).await.unwrap();
+
+        assert!(authenticate(&pool, "bd_wrong").await.unwrap().is_none());
+
+        let token = authenticate(&pool, &secret).await.unwrap().unwrap();
+        assert_eq!(token.name, "laptop");
+
+        let token = db::api_tokens::get_by_name(&pool, "laptop")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token.last_used_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_revoke() {
+        let pool = get_test_pool().await;
+        let (_, secret) = create(&pool, "laptop", vec![], None).await.unwrap();
+
+        revoke(&pool, "laptop").await.unwrap();
+        assert!(authenticate(&pool, &secret).await.unwrap().is_none());
+
+        let got = revoke(&pool, "laptop").await.unwrap_err();
+        match got {
+            TokenError::TokenNotFound(ref n) if n == "laptop" => {}
+            _ => panic!("Expected TokenNotFound, got: {:?}", got),
+        }
+    }
+}
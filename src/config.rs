@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use std::sync::OnceLock;
@@ -17,6 +17,8 @@ pub enum ConfigError {
     PortError(String),
     #[error("TOML parsing error: {0}")]
     TomlError(String),
+    #[error("Context not found: {0}")]
+    ContextNotFound(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,11 +26,273 @@ pub struct ServerConfig {
     pub host: String,
     pub proxy_host: String,
     pub proxy_port: u16,
+    /// Address the admin API and dashboard listen on, kept off the public
+    /// proxy port
+    #[serde(default = "default_admin_host")]
+    pub admin_host: String,
+    #[serde(default = "default_admin_port")]
+    pub admin_port: u16,
+    /// Also serve the admin API and dashboard through the proxy for requests
+    /// to this hostname, e.g. `admin.example.com`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_hostname: Option<String>,
+    /// Apex domain apps are served under as `<app>.<base_domain>`. Without
+    /// it, the first label of any hostname without a custom domain is taken
+    /// as the app name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_domain: Option<String>,
+    /// HTTPS listener, disabled unless configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Connection pool and timeouts used when proxying to apps
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+    /// Per-app logs of the requests the proxy forwards
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    /// Public ports and timeouts of apps serving raw TCP
+    #[serde(default)]
+    pub tcp: TcpConfig,
+    /// Addresses or CIDR networks of proxies in front of the server, e.g. a
+    /// cloud load balancer. Forwarding headers such as `X-Forwarded-For`
+    /// are only taken from these.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
+    /// HTML pages served for the proxy's error responses, by status code
+    /// such as `503`, or `default` for any error without its own page
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub error_pages: BTreeMap<String, PathBuf>,
+    /// Unix socket for local admin access, defaults to `control.sock` in the
+    /// data directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_socket: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientConfig {
-    pub base_url: String,
+    /// Context used when neither `--context` nor `BINDROP_CONTEXT` is given
+    pub current_context: Option<String>,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, ClientContext>,
+    /// Pre-context `base_url`, migrated into the `default` context on load
+    #[serde(default, rename = "base_url", skip_serializing)]
+    legacy_base_url: Option<String>,
+    /// Pre-context `token`, migrated into the `default` context on load
+    #[serde(default, rename = "token", skip_serializing)]
+    legacy_token: Option<String>,
+}
+
+/// A BinaryDrop server the client can talk to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientContext {
+    pub url: String,
+    /// Bearer token for the admin API, created with `bindrop server token create`
+    pub token: Option<String>,
+    /// App used by commands when no app name is given
+    pub default_app: Option<String>,
+}
+
+/// Name of the context created for new and pre-context configs
+pub const DEFAULT_CONTEXT: &str = "default";
+
+/// HTTPS termination in the proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub port: u16,
+    /// Directory with a `<domain>/cert.pem` and `<domain>/key.pem` pair per
+    /// domain, defaults to `certs` in the data directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certs_dir: Option<PathBuf>,
+    /// Certificates for specific domains, taking precedence over the certs
+    /// directory
+    pub certificates: Vec<CertificateConfig>,
+    /// Redirect plain HTTP requests to HTTPS
+    pub redirect_http: bool,
+    /// Seconds between checks for changed certificate files
+    pub reload_interval_secs: u64,
+    /// Obtain and renew certificates automatically
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acme: Option<AcmeConfig>,
+}
+
+/// A certificate for one domain, which may be a wildcard like `*.example.com`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateConfig {
+    pub domain: String,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            port: 443,
+            certs_dir: None,
+            certificates: vec![],
+            redirect_http: false,
+            reload_interval_secs: 30,
+            acme: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Directory certificates are loaded from
+    pub fn certs_dir(&self) -> Result<PathBuf, ConfigError> {
+        match &self.certs_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(get_data_dir()?.join("certs")),
+        }
+    }
+}
+
+/// Directory of Let's Encrypt's production ACME server
+pub const LETS_ENCRYPT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Certificate issuance over ACME with HTTP-01 challenges answered by the proxy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AcmeConfig {
+    /// ACME directory URL, e.g. Let's Encrypt staging or a local test server
+    pub directory_url: String,
+    /// Contact email registered with the account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Domains to obtain certificates for
+    pub domains: Vec<String>,
+    /// Renew certificates this many days before they expire
+    pub renew_before_days: u32,
+    /// Seconds between checks for certificates to issue or renew
+    pub check_interval_secs: u64,
+    /// Skip verifying the ACME server's TLS certificate, for test servers
+    /// like Pebble only
+    pub insecure: bool,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        Self {
+            directory_url: LETS_ENCRYPT_DIRECTORY.to_string(),
+            email: None,
+            domains: vec![],
+            renew_before_days: 30,
+            check_interval_secs: 12 * 60 * 60,
+            insecure: false,
+        }
+    }
+}
+
+/// Directory ACME account keys are stored in
+pub fn get_acme_dir() -> Result<PathBuf, ConfigError> {
+    Ok(get_data_dir()?.join("acme"))
+}
+
+/// How the proxy connects to apps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    /// Seconds to wait for a connection to an app before responding 504
+    pub connect_timeout_secs: u64,
+    /// Seconds from sending a request to an app until its response headers
+    /// arrive before responding 504. This includes uploading the request
+    /// body, so it also bounds how long a slow upload may take.
+    pub read_timeout_secs: u64,
+    /// Seconds an unused keep-alive connection to an app stays open
+    pub idle_timeout_secs: u64,
+    /// Maximum number of idle connections kept open to each app
+    pub max_idle_per_app: usize,
+    /// Seconds an upgraded connection, such as a WebSocket, may go without
+    /// traffic in either direction before it is closed
+    pub upgrade_idle_timeout_secs: u64,
+    /// Seconds a request waits for an app that is starting or restarting
+    /// before responding 503. Zero rejects such requests immediately.
+    pub restart_wait_secs: u64,
+    /// Maximum number of requests held for each app while it starts or
+    /// restarts. Requests beyond this get 503 straight away.
+    pub max_held_requests: usize,
+    /// Smallest response in bytes compressed for apps with compression on.
+    /// Responses of unknown length are compressed as they stream.
+    pub compression_min_bytes: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 5,
+            read_timeout_secs: 60,
+            idle_timeout_secs: 90,
+            max_idle_per_app: 32,
+            upgrade_idle_timeout_secs: 600,
+            restart_wait_secs: 30,
+            max_held_requests: 100,
+            compression_min_bytes: 1024,
+        }
+    }
+}
+
+/// Raw TCP proxying for apps that do not speak HTTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TcpConfig {
+    /// First of the ports handed out to TCP services, which listen on the
+    /// server's `host`
+    pub port_range_start: u16,
+    /// Last of the ports handed out to TCP services
+    pub port_range_end: u16,
+    /// Seconds a connection may go without traffic in either direction
+    /// before it is closed
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            port_range_start: 10000,
+            port_range_end: 10999,
+            idle_timeout_secs: 3600,
+        }
+    }
+}
+
+/// How the proxy writes each app's access log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessLogConfig {
+    /// Record requests to apps in `access.log` in each app's directory
+    pub enabled: bool,
+    /// `text` for one readable line per request, `json` for JSON lines
+    pub format: AccessLogFormat,
+    /// Size in megabytes at which the log is rotated
+    pub max_size_mb: u64,
+    /// Number of rotated logs kept, as `access.log.1` (newest) and up
+    pub max_files: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            format: AccessLogFormat::Text,
+            max_size_mb: 10,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    Text,
+    Json,
+}
+
+fn default_admin_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_admin_port() -> u16 {
+    7070
 }
 
 impl Default for ServerConfig {
@@ -37,14 +301,41 @@ impl Default for ServerConfig {
             host: "0.0.0.0".to_string(),
             proxy_host: "0.0.0.0".to_string(),
             proxy_port: 80,
+            admin_host: default_admin_host(),
+            admin_port: default_admin_port(),
+            admin_hostname: None,
+            base_domain: None,
+            tls: None,
+            upstream: UpstreamConfig::default(),
+            access_log: AccessLogConfig::default(),
+            tcp: TcpConfig::default(),
+            trusted_proxies: Vec::new(),
+            error_pages: BTreeMap::new(),
+            control_socket: None,
         }
     }
 }
 
 impl Default for ClientConfig {
+    fn default() -> Self {
+        let mut contexts = BTreeMap::new();
+        contexts.insert(DEFAULT_CONTEXT.to_string(), ClientContext::default());
+
+        Self {
+            current_context: Some(DEFAULT_CONTEXT.to_string()),
+            contexts,
+            legacy_base_url: None,
+            legacy_token: None,
+        }
+    }
+}
+
+impl Default for ClientContext {
     fn default() -> Self {
         Self {
-            base_url: "http://admin-api.localhost".to_string(),
+            url: "http://localhost:7070".to_string(),
+            token: None,
+            default_app: None,
         }
     }
 }
@@ -103,18 +394,12 @@ pub async fn get_next_available_port(
 ) -> Result<u16, ConfigError> {
     let start_port = 8000;
 
-    // Get all currently used ports
-    let rows = sqlx::query("SELECT port FROM apps")
-        .fetch_all(db_pool)
+    // Get all currently used ports, including those of extra instances and
+    // TCP services
+    let used_ports = crate::db::apps::get_used_ports(db_pool)
         .await
-        .context("Failed to query app ports from database")
         .map_err(|e| ConfigError::PortError(e.to_string()))?;
 
-    let mut used_ports = Vec::with_capacity(rows.len());
-    for row in &rows {
-        used_ports.push(row.get::<u16, _>("port"));
-    }
-
     // Find first available port
     let mut port = start_port;
     while used_ports.contains(&port) {
@@ -172,7 +457,42 @@ pub fn get_app_log_path(app_name: &str) -> Result<PathBuf, ConfigError> {
     Ok(get_app_dir(app_name)?.join(format!("{}.log", app_name)))
 }
 
+/// Get the path of the access log the proxy writes for an app
+pub fn get_app_access_log_path(app_name: &str) -> Result<PathBuf, ConfigError> {
+    Ok(get_app_dir(app_name)?.join("access.log"))
+}
+
+/// Get the default path of the local control socket
+pub fn get_control_socket_path() -> Result<PathBuf, ConfigError> {
+    Ok(get_data_dir()?.join("control.sock"))
+}
+
+/// Find the control socket of a server running on this machine, if it is
+/// accepting connections from the current user
+pub fn find_control_socket() -> Option<PathBuf> {
+    let server_config_path = ServerConfig::get_config_path().ok()?;
+    // Avoid ServerConfig::load, which writes a default config when none exists
+    let path = match fs::read_to_string(server_config_path) {
+        Ok(contents) => toml::from_str::<ServerConfig>(&contents)
+            .ok()?
+            .control_socket_path()
+            .ok()?,
+        Err(_) => get_control_socket_path().ok()?,
+    };
+
+    std::os::unix::net::UnixStream::connect(&path).ok()?;
+    Some(path)
+}
+
 impl ServerConfig {
+    /// Path of the local control socket `bindrop serve` listens on
+    pub fn control_socket_path(&self) -> Result<PathBuf, ConfigError> {
+        match &self.control_socket {
+            Some(path) => Ok(path.clone()),
+            None => get_control_socket_path(),
+        }
+    }
+
     #[tracing::instrument]
     pub fn load() -> Result<Self, ConfigError> {
         let config_path = Self::get_config_path()?;
@@ -214,6 +534,27 @@ impl ServerConfig {
     }
 }
 
+impl ClientContext {
+    /// Whether the context points at a server on this machine
+    pub fn is_local(&self) -> bool {
+        let url = match reqwest::Url::parse(&self.url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return false,
+        };
+
+        host == "localhost"
+            || host.ends_with(".localhost")
+            || host
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
 impl ClientConfig {
     #[tracing::instrument]
     pub fn load() -> Result<Self, ConfigError> {
@@ -228,11 +569,54 @@ impl ClientConfig {
 
         let contents = fs::read_to_string(config_path)
             .map_err(|_| ConfigError::IoError("Failed to read config file".to_string()))?;
-        let config: Self =
-            toml::from_str(&contents).map_err(|e| ConfigError::TomlError(e.to_string()))?;
+        let mut config = Self::parse(&contents)?;
+        if config.migrate_legacy() {
+            config.save()?;
+        }
         Ok(config)
     }
 
+    /// Parse a client config from TOML
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        toml::from_str(contents).map_err(|e| ConfigError::TomlError(e.to_string()))
+    }
+
+    /// Move a pre-context `base_url`/`token` into the `default` context.
+    /// Returns whether anything changed.
+    fn migrate_legacy(&mut self) -> bool {
+        let url = match self.legacy_base_url.take() {
+            Some(url) => url,
+            None => return false,
+        };
+
+        let context = ClientContext {
+            url,
+            token: self.legacy_token.take(),
+            default_app: None,
+        };
+        self.contexts
+            .entry(DEFAULT_CONTEXT.to_string())
+            .or_insert(context);
+        if self.current_context.is_none() {
+            self.current_context = Some(DEFAULT_CONTEXT.to_string());
+        }
+
+        true
+    }
+
+    /// Pick the context to use: the explicitly requested one (from
+    /// `--context` or `BINDROP_CONTEXT`), otherwise the current context.
+    pub fn resolve_context(&self, name: Option<&str>) -> Result<ClientContext, ConfigError> {
+        let name = name
+            .or(self.current_context.as_deref())
+            .unwrap_or(DEFAULT_CONTEXT);
+
+        self.contexts
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ConfigError::ContextNotFound(name.to_string()))
+    }
+
     #[tracing::instrument]
     pub fn save(&self) -> Result<(), ConfigError> {
         let config_path = Self::get_config_path()?;
@@ -256,3 +640,70 @@ impl ClientConfig {
         Ok(path)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_legacy_client_config_is_migrated() {
+        let mut config = ClientConfig::parse(
+            r#"
+            base_url = "https://admin-api.example.com"
+            token = "bd_secret"
+            "#,
+        )
+        .unwrap();
+        assert!(config.migrate_legacy());
+
+        let context = config.resolve_context(None).unwrap();
+        assert_eq!(context.url, "https://admin-api.example.com");
+        assert_eq!(context.token.as_deref(), Some("bd_secret"));
+
+        let saved = toml::to_string_pretty(&config).unwrap();
+        assert!(!saved.contains("base_url"));
+    }
+
+    #[test]
+    fn test_resolve_context() {
+        let mut config = ClientConfig::parse(
+            r#"
+            current_context = "staging"
+
+            [contexts.staging]
+            url = "https://admin-api.staging.example.com"
+
+            [contexts.production]
+            url = "https://admin-api.example.com"
+            default_app = "web"
+            "#,
+        )
+        .unwrap();
+        assert!(!config.migrate_legacy());
+
+        let context = config.resolve_context(None).unwrap();
+        assert_eq!(context.url, "https://admin-api.staging.example.com");
+
+        let context = config.resolve_context(Some("production")).unwrap();
+        assert_eq!(context.default_app.as_deref(), Some("web"));
+
+        assert_eq!(
+            config.resolve_context(Some("missing")).unwrap_err(),
+            ConfigError::ContextNotFound("missing".to_string())
+        );
+    }
+
+    #[test]
+    fn test_context_is_local() {
+        let context = |url: &str| ClientContext {
+            url: url.to_string(),
+            ..ClientContext::default()
+        };
+
+        assert!(ClientContext::default().is_local());
+        assert!(context("http://127.0.0.1:8080").is_local());
+        assert!(context("http://[::1]").is_local());
+        assert!(!context("https://admin-api.example.com").is_local());
+        assert!(!context("http://10.0.0.5").is_local());
+    }
+}
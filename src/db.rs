@@ -2,10 +2,10 @@ use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
 use std::path::PathBuf;
 use tracing::{debug, info, instrument};
 
-use crate::models::ProcessHistory;
+use crate::models::{ApiToken, AuditEntry, AuditResult, Domain, Instance, ProcessHistory, Route};
 
 use crate::config;
-use crate::models::{App, AppState};
+use crate::models::{App, AppAccess, AppLimits, AppState, Service};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DatabaseError {
@@ -79,6 +79,30 @@ pub mod apps {
             None => None,
         };
 
+        // Serialize limits to JSON unless there are none
+        let limits_json = match app.limits.is_unlimited() {
+            true => None,
+            false => Some(serde_json::to_string(&app.limits)?),
+        };
+
+        // Serialize access rules to JSON unless the app is open to everyone
+        let access_json = match app.access.is_open() {
+            true => None,
+            false => Some(serde_json::to_string(&app.access)?),
+        };
+
+        // Serialize maintenance mode to JSON if the app is in it
+        let maintenance_json = match &app.maintenance {
+            Some(maintenance) => Some(serde_json::to_string(maintenance)?),
+            None => None,
+        };
+
+        // Serialize the service to JSON unless it is plain HTTP
+        let service_json = match app.service {
+            Service::Http => None,
+            service => Some(serde_json::to_string(&service)?),
+        };
+
         // Serialize environment variables to JSON
         let env_json = serde_json::to_string(&app.environment)?;
 
@@ -91,8 +115,9 @@ pub mod apps {
                 id, name, created_at, updated_at, state, binary_path, binary_hash, 
                 port, environment, process_id, host, restart_policy, max_restarts,
                 restart_count, last_exit_code, last_exit_time, startup_timeout,
-                shutdown_timeout, health_check
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                shutdown_timeout, health_check, idle_timeout, instances, limits, access,
+                maintenance, compression, service
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ON CONFLICT(id) DO UPDATE SET
                 name = excluded.name,
                 updated_at = excluded.updated_at,
@@ -110,7 +135,14 @@ pub mod apps {
                 last_exit_time = excluded.last_exit_time,
                 startup_timeout = excluded.startup_timeout,
                 shutdown_timeout = excluded.shutdown_timeout,
-                health_check = excluded.health_check
+                health_check = excluded.health_check,
+                idle_timeout = excluded.idle_timeout,
+                instances = excluded.instances,
+                limits = excluded.limits,
+                access = excluded.access,
+                maintenance = excluded.maintenance,
+                compression = excluded.compression,
+                service = excluded.service
             "#,
             app.id,
             app.name,
@@ -131,6 +163,13 @@ pub mod apps {
             app.startup_timeout,
             app.shutdown_timeout,
             health_check_json,
+            app.idle_timeout,
+            app.instances,
+            limits_json,
+            access_json,
+            maintenance_json,
+            app.compression,
+            service_json,
         )
         .execute(pool)
         .await?;
@@ -152,7 +191,8 @@ pub mod apps {
             SELECT id, name, created_at, updated_at, state, binary_path, binary_hash,
                    port, environment, process_id, host, restart_policy, max_restarts,
                    restart_count, last_exit_code, last_exit_time, startup_timeout,
-                   shutdown_timeout, health_check
+                   shutdown_timeout, health_check, idle_timeout, instances, limits,
+                   access, maintenance, compression, service
             FROM apps 
             WHERE name = ?
             "#,
@@ -183,6 +223,7 @@ pub mod apps {
                     "failed" => AppState::Failed,
                     "restarting" => AppState::Restarting,
                     "crashed" => AppState::Crashed,
+                    "sleeping" => AppState::Sleeping,
                     _ => AppState::Created,
                 };
 
@@ -214,6 +255,25 @@ pub mod apps {
                     startup_timeout: record.startup_timeout as u32,
                     shutdown_timeout: record.shutdown_timeout as u32,
                     health_check,
+                    idle_timeout: record.idle_timeout.map(|t| t as u32),
+                    instances: record.instances as u32,
+                    limits: match record.limits {
+                        Some(json) => serde_json::from_str(&json)?,
+                        None => AppLimits::default(),
+                    },
+                    access: match record.access {
+                        Some(json) => serde_json::from_str(&json)?,
+                        None => AppAccess::default(),
+                    },
+                    maintenance: match record.maintenance {
+                        Some(json) => Some(serde_json::from_str(&json)?),
+                        None => None,
+                    },
+                    compression: record.compression,
+                    service: match record.service {
+                        Some(json) => serde_json::from_str(&json)?,
+                        None => Service::Http,
+                    },
                 }))
             }
             None => Ok(None),
@@ -230,7 +290,8 @@ pub mod apps {
             SELECT id, name, created_at, updated_at, state, binary_path, binary_hash,
                    port, environment, process_id, host, restart_policy, max_restarts,
                    restart_count, last_exit_code, last_exit_time, startup_timeout,
-                   shutdown_timeout, health_check
+                   shutdown_timeout, health_check, idle_timeout, instances, limits,
+                   access, maintenance, compression, service
             FROM apps 
             WHERE state = ?
             "#,
@@ -279,6 +340,25 @@ pub mod apps {
                 startup_timeout: record.startup_timeout as u32,
                 shutdown_timeout: record.shutdown_timeout as u32,
                 health_check,
+                idle_timeout: record.idle_timeout.map(|t| t as u32),
+                instances: record.instances as u32,
+                limits: match record.limits {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => AppLimits::default(),
+                },
+                access: match record.access {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => AppAccess::default(),
+                },
+                maintenance: match record.maintenance {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+                compression: record.compression,
+                service: match record.service {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => Service::Http,
+                },
             });
         }
 
@@ -293,10 +373,22 @@ pub mod apps {
             DELETE FROM process_history
             WHERE app_id = ?;
 
+            DELETE FROM domains
+            WHERE app_id = ?;
+
+            DELETE FROM routes
+            WHERE app_id = ?;
+
+            DELETE FROM instances
+            WHERE app_id = ?;
+
             DELETE FROM apps
             WHERE id = ?;
             "#,
             id,
+            id,
+            id,
+            id,
             id
         )
         .execute(pool)
@@ -313,7 +405,8 @@ pub mod apps {
             SELECT id, name, created_at, updated_at, state, binary_path, binary_hash,
                    port, environment, process_id, host, restart_policy, max_restarts,
                    restart_count, last_exit_code, last_exit_time, startup_timeout,
-                   shutdown_timeout, health_check
+                   shutdown_timeout, health_check, idle_timeout, instances, limits,
+                   access, maintenance, compression, service
             FROM apps 
             ORDER BY name
             "#
@@ -344,6 +437,7 @@ pub mod apps {
                 "failed" => AppState::Failed,
                 "restarting" => AppState::Restarting,
                 "crashed" => AppState::Crashed,
+                "sleeping" => AppState::Sleeping,
                 _ => AppState::Created,
             };
 
@@ -375,11 +469,50 @@ pub mod apps {
                 startup_timeout: record.startup_timeout as u32,
                 shutdown_timeout: record.shutdown_timeout as u32,
                 health_check,
+                idle_timeout: record.idle_timeout.map(|t| t as u32),
+                instances: record.instances as u32,
+                limits: match record.limits {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => AppLimits::default(),
+                },
+                access: match record.access {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => AppAccess::default(),
+                },
+                maintenance: match record.maintenance {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+                compression: record.compression,
+                service: match record.service {
+                    Some(json) => serde_json::from_str(&json)?,
+                    None => Service::Http,
+                },
             });
         }
 
         Ok(apps)
     }
+
+    /// Get every port taken by an app: its own, those of its extra instances
+    /// and the public port of a TCP service
+    #[instrument(skip(pool))]
+    pub async fn get_used_ports(pool: &Pool<Sqlite>) -> Result<Vec<u16>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT port AS "port!: i64" FROM apps WHERE port IS NOT NULL
+            UNION
+            SELECT port FROM instances
+            UNION
+            SELECT json_extract(service, '$.listen_port') FROM apps
+            WHERE json_extract(service, '$.listen_port') IS NOT NULL
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records.into_iter().map(|r| r.port as u16).collect())
+    }
 }
 
 /// Process history repository
@@ -532,6 +665,502 @@ pub mod process_history {
     }
 }
 
+/// API token repository
+pub mod api_tokens {
+    use super::*;
+
+    /// Save an API token
+    #[instrument(skip(pool, token))]
+    pub async fn save(pool: &Pool<Sqlite>, token: &ApiToken) -> Result<()> {
+        let scopes_json = serde_json::to_string(&token.scopes)?;
+        let apps_json = match &token.apps {
+            Some(apps) => Some(serde_json::to_string(apps)?),
+            None => None,
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO api_tokens (
+                id, name, token_hash, scopes, apps, created_at, last_used_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                token_hash = excluded.token_hash,
+                scopes = excluded.scopes,
+                apps = excluded.apps,
+                last_used_at = excluded.last_used_at
+            "#,
+            token.id,
+            token.name,
+            token.token_hash,
+            scopes_json,
+            apps_json,
+            token.created_at,
+            token.last_used_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get a token by the hash of its secret
+    #[instrument(skip(pool, token_hash))]
+    pub async fn get_by_hash(pool: &Pool<Sqlite>, token_hash: &str) -> Result<Option<ApiToken>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT id, name, token_hash, scopes, apps, created_at, last_used_at
+            FROM api_tokens
+            WHERE token_hash = ?
+            "#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        match record {
+            Some(record) => Ok(Some(ApiToken {
+                id: record.id,
+                name: record.name,
+                token_hash: record.token_hash,
+                scopes: serde_json::from_str(&record.scopes)?,
+                apps: match record.apps {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+                created_at: record.created_at.and_utc(),
+                last_used_at: record.last_used_at.map(|dt| dt.and_utc()),
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a token by name
+    #[instrument(skip(pool))]
+    pub async fn get_by_name(pool: &Pool<Sqlite>, name: &str) -> Result<Option<ApiToken>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT id, name, token_hash, scopes, apps, created_at, last_used_at
+            FROM api_tokens
+            WHERE name = ?
+            "#,
+            name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        match record {
+            Some(record) => Ok(Some(ApiToken {
+                id: record.id,
+                name: record.name,
+                token_hash: record.token_hash,
+                scopes: serde_json::from_str(&record.scopes)?,
+                apps: match record.apps {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+                created_at: record.created_at.and_utc(),
+                last_used_at: record.last_used_at.map(|dt| dt.and_utc()),
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Get all tokens
+    #[instrument(skip(pool))]
+    pub async fn get_all(pool: &Pool<Sqlite>) -> Result<Vec<ApiToken>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT id, name, token_hash, scopes, apps, created_at, last_used_at
+            FROM api_tokens
+            ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut tokens = Vec::new();
+
+        for record in records {
+            tokens.push(ApiToken {
+                id: record.id,
+                name: record.name,
+                token_hash: record.token_hash,
+                scopes: serde_json::from_str(&record.scopes)?,
+                apps: match record.apps {
+                    Some(json) => Some(serde_json::from_str(&json)?),
+                    None => None,
+                },
+                created_at: record.created_at.and_utc(),
+                last_used_at: record.last_used_at.map(|dt| dt.and_utc()),
+            });
+        }
+
+        Ok(tokens)
+    }
+
+    /// Record that a token was just used
+    #[instrument(skip(pool))]
+    pub async fn touch(pool: &Pool<Sqlite>, id: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        sqlx::query!(
+            r#"
+            UPDATE api_tokens
+            SET last_used_at = ?
+            WHERE id = ?
+            "#,
+            now,
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a token by name
+    #[instrument(skip(pool))]
+    pub async fn delete_by_name(pool: &Pool<Sqlite>, name: &str) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM api_tokens
+            WHERE name = ?
+            "#,
+            name
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Domain repository
+pub mod domains {
+    use super::*;
+
+    /// Save a domain
+    #[instrument(skip(pool, domain))]
+    pub async fn save(pool: &Pool<Sqlite>, domain: &Domain) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO domains (id, hostname, app_id, created_at)
+            VALUES (?, ?, ?, ?)
+            "#,
+            domain.id,
+            domain.hostname,
+            domain.app_id,
+            domain.created_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get a domain by its hostname, which may be a wildcard
+    #[instrument(skip(pool))]
+    pub async fn get_by_hostname(pool: &Pool<Sqlite>, hostname: &str) -> Result<Option<Domain>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT d.id, d.hostname, d.app_id, a.name AS app_name, d.created_at
+            FROM domains d
+            JOIN apps a ON a.id = d.app_id
+            WHERE d.hostname = ?
+            "#,
+            hostname
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record.map(|record| Domain {
+            id: record.id,
+            hostname: record.hostname,
+            app_id: record.app_id,
+            app_name: record.app_name,
+            created_at: record.created_at.and_utc(),
+        }))
+    }
+
+    /// Get all domains of an app
+    #[instrument(skip(pool))]
+    pub async fn get_by_app_id(pool: &Pool<Sqlite>, app_id: &str) -> Result<Vec<Domain>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT d.id, d.hostname, d.app_id, a.name AS app_name, d.created_at
+            FROM domains d
+            JOIN apps a ON a.id = d.app_id
+            WHERE d.app_id = ?
+            ORDER BY d.hostname
+            "#,
+            app_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Domain {
+                id: record.id,
+                hostname: record.hostname,
+                app_id: record.app_id,
+                app_name: record.app_name,
+                created_at: record.created_at.and_utc(),
+            })
+            .collect())
+    }
+
+    /// Delete a domain of an app by hostname
+    #[instrument(skip(pool))]
+    pub async fn delete(pool: &Pool<Sqlite>, app_id: &str, hostname: &str) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM domains
+            WHERE app_id = ? AND hostname = ?
+            "#,
+            app_id,
+            hostname
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Route repository
+pub mod routes {
+    use super::*;
+
+    /// Save a route
+    #[instrument(skip(pool, route))]
+    pub async fn save(pool: &Pool<Sqlite>, route: &Route) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO routes (id, hostname, path_prefix, app_id, strip_prefix, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            route.id,
+            route.hostname,
+            route.path_prefix,
+            route.app_id,
+            route.strip_prefix,
+            route.created_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the routes of a hostname, or of all hostnames, ordered by
+    /// hostname and then longest prefix first
+    #[instrument(skip(pool))]
+    pub async fn get(pool: &Pool<Sqlite>, hostname: Option<&str>) -> Result<Vec<Route>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT r.id, r.hostname, r.path_prefix, r.app_id, a.name AS app_name,
+                   r.strip_prefix, r.created_at
+            FROM routes r
+            JOIN apps a ON a.id = r.app_id
+            WHERE (?1 IS NULL OR r.hostname = ?1)
+            ORDER BY r.hostname, length(r.path_prefix) DESC
+            "#,
+            hostname
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Route {
+                id: record.id,
+                hostname: record.hostname,
+                path_prefix: record.path_prefix,
+                app_id: record.app_id,
+                app_name: record.app_name,
+                strip_prefix: record.strip_prefix,
+                created_at: record.created_at.and_utc(),
+            })
+            .collect())
+    }
+
+    /// Delete the route for a hostname and path prefix
+    #[instrument(skip(pool))]
+    pub async fn delete(pool: &Pool<Sqlite>, hostname: &str, path_prefix: &str) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM routes
+            WHERE hostname = ? AND path_prefix = ?
+            "#,
+            hostname,
+            path_prefix
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Ports of app instances beyond the first
+pub mod instances {
+    use super::*;
+
+    /// Save an instance's port
+    #[instrument(skip(pool, instance))]
+    pub async fn save(pool: &Pool<Sqlite>, instance: &Instance) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO instances (app_id, idx, port)
+            VALUES (?, ?, ?)
+            "#,
+            instance.app_id,
+            instance.index,
+            instance.port
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the extra instances of an app in order
+    #[instrument(skip(pool))]
+    pub async fn get_by_app_id(pool: &Pool<Sqlite>, app_id: &str) -> Result<Vec<Instance>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT app_id, idx, port
+            FROM instances
+            WHERE app_id = ?
+            ORDER BY idx
+            "#,
+            app_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Instance {
+                app_id: record.app_id,
+                index: record.idx as u32,
+                port: record.port as u16,
+            })
+            .collect())
+    }
+
+    /// Delete an app's instances from `index` up
+    #[instrument(skip(pool))]
+    pub async fn delete_from(pool: &Pool<Sqlite>, app_id: &str, index: u32) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM instances
+            WHERE app_id = ? AND idx >= ?
+            "#,
+            app_id,
+            index
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Audit log repository
+pub mod audit_log {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    /// Save an audit log entry
+    #[instrument(skip(pool, entry))]
+    pub async fn save(pool: &Pool<Sqlite>, entry: &AuditEntry) -> Result<()> {
+        let parameters_json = match &entry.parameters {
+            Some(parameters) => Some(serde_json::to_string(parameters)?),
+            None => None,
+        };
+        let result = entry.result.to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (
+                id, created_at, actor, action, app_name, parameters, source_ip,
+                result, message
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            entry.id,
+            entry.created_at,
+            entry.actor,
+            entry.action,
+            entry.app_name,
+            parameters_json,
+            entry.source_ip,
+            result,
+            entry.message
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get audit log entries, newest first, optionally filtered by app and
+    /// start time
+    #[instrument(skip(pool))]
+    pub async fn get(
+        pool: &Pool<Sqlite>,
+        app_name: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<AuditEntry>> {
+        let records = sqlx::query!(
+            r#"
+            SELECT id, created_at, actor, action, app_name, parameters, source_ip,
+                   result, message
+            FROM audit_log
+            WHERE (?1 IS NULL OR app_name = ?1)
+              AND (?2 IS NULL OR created_at >= ?2)
+            ORDER BY created_at DESC
+            LIMIT ?3
+            "#,
+            app_name,
+            since,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut entries = Vec::new();
+
+        for record in records {
+            let parameters = match record.parameters {
+                Some(json) => Some(serde_json::from_str(&json)?),
+                None => None,
+            };
+
+            let result = match record.result.as_str() {
+                "success" => AuditResult::Success,
+                _ => AuditResult::Failure,
+            };
+
+            entries.push(AuditEntry {
+                id: record.id,
+                created_at: record.created_at.and_utc(),
+                actor: record.actor,
+                action: record.action,
+                app_name: record.app_name,
+                parameters,
+                source_ip: record.source_ip,
+                result,
+                message: record.message,
+            });
+        }
+
+        Ok(entries)
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -1,11 +1,13 @@
 pub mod api;
 pub mod api_client;
+pub mod api_types;
 pub mod cli;
 pub mod commands;
 pub mod config;
 pub mod db;
 pub mod errors;
 pub mod models;
+pub mod output;
 #[allow(async_fn_in_trait)]
 pub mod providers;
 pub mod supervisor;
@@ -23,6 +23,15 @@ pub struct App {
     pub startup_timeout: u32,  // Seconds
     pub shutdown_timeout: u32, // Seconds
     pub health_check: Option<HealthCheck>,
+    pub idle_timeout: Option<u32>, // Seconds without requests before sleeping
+    pub instances: u32,            // Processes to run, each on its own port
+    pub limits: AppLimits,
+    pub access: AppAccess,
+    /// Set while the proxy answers for the app with a maintenance page
+    pub maintenance: Option<Maintenance>,
+    /// Whether the proxy compresses responses for clients that accept it
+    pub compression: bool,
+    pub service: Service,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -61,6 +70,13 @@ impl App {
             binary_hash: None,
             startup_timeout: 30,
             shutdown_timeout: 10,
+            idle_timeout: None,
+            instances: 1,
+            limits: AppLimits::default(),
+            access: AppAccess::default(),
+            maintenance: None,
+            compression: false,
+            service: Service::Http,
             // runtime state
             process_id: None,
             last_exit_code: None,
@@ -170,6 +186,8 @@ pub enum AppState {
     Failed,
     Restarting,
     Crashed,
+    /// Stopped after going idle, started again by the next request
+    Sleeping,
 }
 
 impl std::fmt::Display for AppState {
@@ -184,6 +202,7 @@ impl std::fmt::Display for AppState {
             AppState::Failed => write!(f, "failed"),
             AppState::Restarting => write!(f, "restarting"),
             AppState::Crashed => write!(f, "crashed"),
+            AppState::Sleeping => write!(f, "sleeping"),
         }
     }
 }
@@ -216,7 +235,187 @@ pub struct HealthCheck {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HealthCheckType {
-    HttpGet { path: String, expected_status: u16 },
+    HttpGet {
+        path: String,
+        expected_status: u16,
+    },
+    /// Passes when the app accepts TCP connections, for apps that do not
+    /// speak HTTP
+    TcpConnect,
+}
+
+/// How clients reach an app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Service {
+    /// Requests through the HTTP proxy, routed by host and path
+    #[default]
+    Http,
+    /// Raw TCP connections to a public port of the server, each passed
+    /// through to the app as is
+    Tcp { listen_port: u16 },
+}
+
+impl Service {
+    pub fn listen_port(&self) -> Option<u16> {
+        match self {
+            Service::Http => None,
+            Service::Tcp { listen_port } => Some(*listen_port),
+        }
+    }
+}
+
+/// Kind of service an app declares, without the port the server assigns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceType {
+    Http,
+    Tcp,
+}
+
+impl std::fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceType::Http => write!(f, "http"),
+            ServiceType::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// Limits the proxy enforces on requests to an app. Each is off when `None`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AppLimits {
+    /// Steady rate of requests allowed from each client IP
+    pub requests_per_second: Option<u32>,
+    /// Requests a client may make in a burst above the steady rate,
+    /// defaults to `requests_per_second`
+    pub burst: Option<u32>,
+    /// Requests forwarded to the app at the same time, or open connections
+    /// for TCP services
+    pub max_concurrent: Option<u32>,
+    /// Largest request body accepted, in bytes
+    pub max_body_bytes: Option<u64>,
+}
+
+impl AppLimits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Who the proxy lets through to an app. Each check is off when empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppAccess {
+    /// Users who must sign in with HTTP basic auth
+    #[serde(default)]
+    pub users: Vec<BasicAuthUser>,
+    /// Addresses and CIDR networks requests must come from
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl AppAccess {
+    pub fn is_open(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Maintenance mode of an app. The proxy serves a 503 page in its place
+/// while the process is left running, e.g. for migrations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Maintenance {
+    pub since: DateTime<Utc>,
+    /// HTML page served instead of the server's 503 error page
+    pub page: Option<String>,
+    /// Addresses and CIDR networks still let through to the app
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Seconds clients are told to wait before trying again
+    pub retry_after: u32,
+}
+
+/// PBKDF2 rounds for basic auth passwords
+const PASSWORD_ITERATIONS: u32 = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BasicAuthUser {
+    pub username: String,
+    /// Salted hash of the password as `pbkdf2-sha256$<iterations>$<salt>$<hash>`
+    pub password_hash: String,
+}
+
+impl BasicAuthUser {
+    /// Usernames are kept to characters that need no escaping in URLs, which
+    /// also keeps out the colon that ends the username in the credentials
+    pub fn is_valid_username(username: &str) -> bool {
+        !username.is_empty()
+            && username.len() <= 64
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._@-".contains(c))
+    }
+
+    pub fn new(username: &str, password: &str) -> Self {
+        use base64::engine::general_purpose::STANDARD_NO_PAD;
+        use base64::Engine as _;
+        use ring::rand::{SecureRandom, SystemRandom};
+
+        let mut salt = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .expect("system random number generator failed");
+        let mut hash = [0u8; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            std::num::NonZeroU32::new(PASSWORD_ITERATIONS).unwrap(),
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+
+        Self {
+            username: username.to_string(),
+            password_hash: format!(
+                "pbkdf2-sha256${}${}${}",
+                PASSWORD_ITERATIONS,
+                STANDARD_NO_PAD.encode(salt),
+                STANDARD_NO_PAD.encode(hash)
+            ),
+        }
+    }
+
+    /// Check a password against the stored hash in constant time
+    pub fn verify(&self, password: &str) -> bool {
+        use base64::engine::general_purpose::STANDARD_NO_PAD;
+        use base64::Engine as _;
+
+        let mut parts = self.password_hash.split('$');
+        let (Some("pbkdf2-sha256"), Some(iterations), Some(salt), Some(hash), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return false;
+        };
+        let (Some(iterations), Ok(salt), Ok(hash)) = (
+            iterations.parse().ok().and_then(std::num::NonZeroU32::new),
+            STANDARD_NO_PAD.decode(salt),
+            STANDARD_NO_PAD.decode(hash),
+        ) else {
+            return false;
+        };
+
+        ring::pbkdf2::verify(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &hash,
+        )
+        .is_ok()
+    }
 }
 
 impl Default for HealthCheck {
@@ -259,3 +458,324 @@ impl ProcessHistory {
         }
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    /// Apps this token is limited to, or `None` for all apps
+    pub apps: Option<Vec<String>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// Generate a new token. Returns the token record (holding only the hash)
+    /// and the plaintext secret, which must be shown to the user exactly once.
+    pub fn generate(
+        name: &str,
+        scopes: Vec<TokenScope>,
+        apps: Option<Vec<String>>,
+    ) -> (Self, String) {
+        let secret = format!(
+            "bd_{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+
+        let token = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            token_hash: Self::hash(&secret),
+            scopes,
+            apps,
+            created_at: Utc::now(),
+            last_used_at: None,
+        };
+
+        (token, secret)
+    }
+
+    /// Identity given to requests over the local control socket. Anyone who
+    /// can open the socket already has the server user's file access, so
+    /// they get full access without a token.
+    pub fn local() -> Self {
+        Self {
+            id: LOCAL_ACTOR.to_string(),
+            name: LOCAL_ACTOR.to_string(),
+            token_hash: String::new(),
+            scopes: vec![TokenScope::Admin],
+            apps: None,
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    /// Hash a plaintext token for storage and lookup
+    pub fn hash(secret: &str) -> String {
+        use sha2::{Digest, Sha256};
+
+        let mut hasher = Sha256::new();
+        hasher.update(secret.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Check whether this token grants `scope`, optionally for a specific app.
+    /// `admin` grants every scope.
+    pub fn allows(&self, scope: TokenScope, app_name: Option<&str>) -> bool {
+        let has_scope = self
+            .scopes
+            .iter()
+            .any(|s| *s == scope || *s == TokenScope::Admin);
+
+        let has_app = match (&self.apps, app_name) {
+            (None, _) => true,
+            (Some(apps), Some(name)) => apps.iter().any(|a| a == name),
+            (Some(_), None) => false,
+        };
+
+        has_scope && has_app
+    }
+
+    /// Check whether this token is limited to a set of apps
+    pub fn is_app_scoped(&self) -> bool {
+        self.apps.is_some()
+    }
+
+    /// Human readable description of what this token may do
+    pub fn permissions(&self) -> String {
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match &self.apps {
+            Some(apps) => format!("{} on apps: {}", scopes, apps.join(", ")),
+            None => format!("{} on all apps", scopes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// View apps, status and logs
+    Read,
+    /// Upload binaries and set environment variables
+    Deploy,
+    /// Start, stop and restart apps
+    Control,
+    /// Everything, including creating and deleting apps
+    Admin,
+}
+
+impl std::fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenScope::Read => write!(f, "read"),
+            TokenScope::Deploy => write!(f, "deploy"),
+            TokenScope::Control => write!(f, "control"),
+            TokenScope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::str::FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "deploy" => Ok(TokenScope::Deploy),
+            "control" => Ok(TokenScope::Control),
+            "admin" => Ok(TokenScope::Admin),
+            _ => Err(format!(
+                "Invalid scope '{}'. Expected one of: read, deploy, control, admin",
+                s
+            )),
+        }
+    }
+}
+
+/// A hostname routed to an app: a full domain like `www.example.com` or a
+/// wildcard like `*.example.com`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Domain {
+    pub id: String,
+    pub hostname: String,
+    pub app_id: String,
+    pub app_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Domain {
+    pub fn new(hostname: &str, app: &App) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            hostname: hostname.to_string(),
+            app_id: app.id.clone(),
+            app_name: app.name.clone(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A path-based route sending requests for `hostname` under `path_prefix` to
+/// an app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Route {
+    pub id: String,
+    pub hostname: String,
+    pub path_prefix: String,
+    pub app_id: String,
+    pub app_name: String,
+    /// Remove the prefix from the path before forwarding to the app
+    pub strip_prefix: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Route {
+    pub fn new(hostname: &str, path_prefix: &str, app: &App, strip_prefix: bool) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            hostname: hostname.to_string(),
+            path_prefix: path_prefix.to_string(),
+            app_id: app.id.clone(),
+            app_name: app.name.clone(),
+            strip_prefix,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Whether the route's prefix covers `path`, matching whole segments so
+    /// `/api` covers `/api` and `/api/users` but not `/apis`
+    pub fn matches(&self, path: &str) -> bool {
+        match path.strip_prefix(self.path_prefix.trim_end_matches('/')) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+/// Actor recorded for actions the supervisor takes on its own
+pub const SUPERVISOR_ACTOR: &str = "supervisor";
+
+/// Actor recorded for actions run directly on the server from the CLI
+pub const LOCAL_ACTOR: &str = "local";
+
+/// Parameter names whose values are never written to the audit log
+const REDACTED_PARAMETERS: &[&str] = &["value", "token", "secret", "password"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub actor: String,
+    pub action: String,
+    pub app_name: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    pub parameters: Option<serde_json::Value>,
+    pub source_ip: Option<String>,
+    pub result: AuditResult,
+    pub message: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, action: &str) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            app_name: None,
+            parameters: None,
+            source_ip: None,
+            result: AuditResult::Success,
+            message: None,
+        }
+    }
+
+    pub fn with_app(self, app_name: &str) -> Self {
+        Self {
+            app_name: Some(app_name.to_string()),
+            ..self
+        }
+    }
+
+    /// Attach parameters, redacting the values of sensitive keys
+    pub fn with_parameters(self, parameters: serde_json::Value) -> Self {
+        Self {
+            parameters: Some(redact(parameters)),
+            ..self
+        }
+    }
+
+    pub fn with_source_ip(self, source_ip: Option<String>) -> Self {
+        Self { source_ip, ..self }
+    }
+
+    /// Record the outcome of the action
+    pub fn with_result<T, E: std::fmt::Display>(self, result: &Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self {
+                result: AuditResult::Success,
+                ..self
+            },
+            Err(e) => Self {
+                result: AuditResult::Failure,
+                message: Some(e.to_string()),
+                ..self
+            },
+        }
+    }
+}
+
+fn redact(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    if REDACTED_PARAMETERS.contains(&k.to_lowercase().as_str()) {
+                        (k, serde_json::Value::String("[REDACTED]".to_string()))
+                    } else {
+                        (k, redact(v))
+                    }
+                })
+                .collect(),
+        ),
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(redact).collect())
+        }
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditResult {
+    Success,
+    Failure,
+}
+
+impl std::fmt::Display for AuditResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditResult::Success => write!(f, "success"),
+            AuditResult::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+/// An app process beyond the first, listening on a port of its own. The
+/// first instance uses the app's port.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instance {
+    pub app_id: String,
+    pub index: u32,
+    pub port: u16,
+}
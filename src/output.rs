@@ -0,0 +1,228 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use prettytable::{format, Cell, Row, Table};
+use serde::Serialize;
+
+use crate::api_types::{AccessInfo, AppInfo};
+use crate::models::{ApiToken, AppLimits, AuditEntry, Domain, Route};
+
+/// How the CLI prints results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for humans
+    #[default]
+    Table,
+    /// Pretty-printed JSON
+    Json,
+    /// Tab-separated values without headers
+    Plain,
+}
+
+/// A value the CLI can print as a table, plain text or JSON
+pub trait Render: Serialize {
+    /// Column headers used for table output
+    fn headers() -> Vec<&'static str>;
+
+    /// Values for each column, in the same order as the headers
+    fn row(&self) -> Vec<String>;
+}
+
+/// Print a list of values, or `empty` when there are none in table mode
+pub fn print_list<T: Render>(items: &[T], format: OutputFormat, empty: &str) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Table if items.is_empty() => println!("{}", empty),
+        OutputFormat::Table => {
+            let mut table = new_table(T::headers());
+            for item in items {
+                table.add_row(Row::new(item.row().iter().map(|c| Cell::new(c)).collect()));
+            }
+            table.printstd();
+        }
+        OutputFormat::Plain => {
+            for item in items {
+                println!("{}", item.row().join("\t"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a single value. Tables are printed as one `field value` row per column.
+pub fn print_one<T: Render>(item: &T, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        OutputFormat::Table => {
+            let mut table = Table::new();
+            table.set_format(*format::consts::FORMAT_CLEAN);
+            for (header, value) in T::headers().into_iter().zip(item.row()) {
+                table.add_row(Row::new(vec![Cell::new(header), Cell::new(&value)]));
+            }
+            table.printstd();
+        }
+        OutputFormat::Plain => println!("{}", item.row().join("\t")),
+    }
+
+    Ok(())
+}
+
+/// Print the result of an action: `message` for humans, `value` as JSON
+pub fn print_message<T: Serialize>(message: &str, value: &T, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Table | OutputFormat::Plain => println!("{}", message),
+    }
+
+    Ok(())
+}
+
+fn new_table(headers: Vec<&str>) -> Table {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.set_titles(Row::new(headers.into_iter().map(Cell::new).collect()));
+    table
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+impl Render for AppInfo {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "NAME",
+            "STATE",
+            "PORT",
+            "PID",
+            "INSTANCES",
+            "RESTARTS",
+            "BINARY",
+            "UPDATED",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            match self.maintenance {
+                Some(_) => format!("{} (maintenance)", self.state),
+                None => self.state.clone(),
+            },
+            or_dash(self.port),
+            or_dash(self.process_id),
+            self.instances.to_string(),
+            self.restart_count.to_string(),
+            or_dash(
+                self.binary_hash
+                    .as_ref()
+                    .map(|h| h.chars().take(12).collect::<String>()),
+            ),
+            self.updated_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ]
+    }
+}
+
+impl Render for AuditEntry {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "TIME",
+            "ACTOR",
+            "ACTION",
+            "APP",
+            "SOURCE IP",
+            "RESULT",
+            "PARAMETERS",
+            "MESSAGE",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            self.actor.clone(),
+            self.action.clone(),
+            or_dash(self.app_name.as_ref()),
+            or_dash(self.source_ip.as_ref()),
+            self.result.to_string(),
+            or_dash(self.parameters.as_ref()),
+            or_dash(self.message.as_ref()),
+        ]
+    }
+}
+
+impl Render for AccessInfo {
+    fn headers() -> Vec<&'static str> {
+        vec!["USERS", "ALLOW"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let list = |items: &[String]| match items.is_empty() {
+            true => "-".to_string(),
+            false => items.join(", "),
+        };
+        vec![list(&self.users), list(&self.allow)]
+    }
+}
+
+impl Render for AppLimits {
+    fn headers() -> Vec<&'static str> {
+        vec!["REQUESTS/S", "BURST", "CONCURRENT", "MAX BODY"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            or_dash(self.requests_per_second),
+            or_dash(self.burst.or(self.requests_per_second)),
+            or_dash(self.max_concurrent),
+            or_dash(self.max_body_bytes.map(|bytes| format!("{} bytes", bytes))),
+        ]
+    }
+}
+
+impl Render for Domain {
+    fn headers() -> Vec<&'static str> {
+        vec!["HOSTNAME", "APP", "ADDED"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.hostname.clone(),
+            self.app_name.clone(),
+            self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ]
+    }
+}
+
+impl Render for Route {
+    fn headers() -> Vec<&'static str> {
+        vec!["HOSTNAME", "PATH", "APP", "STRIP PREFIX", "ADDED"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.hostname.clone(),
+            self.path_prefix.clone(),
+            self.app_name.clone(),
+            if self.strip_prefix { "yes" } else { "no" }.to_string(),
+            self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ]
+    }
+}
+
+impl Render for ApiToken {
+    fn headers() -> Vec<&'static str> {
+        vec!["NAME", "CREATED", "LAST USED", "PERMISSIONS"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            self.last_used_at.map_or("never".to_string(), |d| {
+                d.format("%Y-%m-%d %H:%M:%S").to_string()
+            }),
+            self.permissions(),
+        ]
+    }
+}
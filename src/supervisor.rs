@@ -1,7 +1,7 @@
 use anyhow::{anyhow, Result};
 use chrono::Utc;
 use sqlx::{Pool, Sqlite};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::process::Child;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -10,8 +10,9 @@ use tokio::time;
 use tracing::{error, info, instrument, warn};
 
 use crate::commands::app_command;
+use crate::commands::server_command::audit;
 use crate::db;
-use crate::models::{App, AppState, HealthCheckType};
+use crate::models::{App, AppState, AuditEntry, HealthCheck, HealthCheckType, SUPERVISOR_ACTOR};
 
 use once_cell::sync::OnceCell;
 
@@ -38,22 +39,68 @@ pub async fn init(pool: Pool<Sqlite>) -> Result<()> {
 // Message types for the supervisor channel
 #[derive(Debug)]
 pub enum SupervisorMessage {
-    Start(String),            // App name
-    Stop(String),             // App name
-    Restart(String),          // App name
-    CheckHealth(String),      // App name
-    ProcessExit(String, i64), // App name, exit code
+    Start(String),                 // App name
+    Stop(String),                  // App name
+    Restart(String),               // App name
+    CheckHealth(String),           // App name
+    ProcessExit(String, i64),      // App name, exit code
+    Wake(String),                  // App name
+    Scale(String),                 // App name
+    Restarted(String, Result<()>), // App name, outcome of a rolling restart
 }
 
 pub struct Supervisor {
     tx: mpsc::Sender<SupervisorMessage>,
     db_pool: Pool<Sqlite>,
-    running_processes: Arc<Mutex<HashMap<String, RunningProcess>>>,
+    running_processes: ProcessMap,
+    last_activity: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+/// How long a killed process may take to exit before it is given up on
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Running processes of each app by instance index
+type ProcessMap = Arc<Mutex<HashMap<String, BTreeMap<u32, RunningProcess>>>>;
+
+/// Rolling restarts in progress. Each runs in a task of its own, as waiting
+/// for instances to come up would hold up the supervisor loop, and reports
+/// back with `SupervisorMessage::Restarted`.
+struct RollingRestarts {
+    tx: mpsc::Sender<SupervisorMessage>,
+    apps: HashSet<String>,
+}
+
+impl RollingRestarts {
+    fn start(&mut self, db_pool: &Pool<Sqlite>, processes: &ProcessMap, app: App) {
+        self.apps.insert(app.name.clone());
+        let (db_pool, processes, tx) = (db_pool.clone(), Arc::clone(processes), self.tx.clone());
+        tokio::spawn(async move {
+            let result = Supervisor::rolling_restart(&db_pool, &processes, &app).await;
+            let _ = tx
+                .send(SupervisorMessage::Restarted(app.name, result))
+                .await;
+        });
+    }
 }
 
 struct RunningProcess {
     child: Child,
     started_at: Instant,
+    port: u16,
+    /// Whether the instance passed its last health check. The proxy only
+    /// sends requests to healthy instances while there are any.
+    healthy: bool,
+}
+
+impl RunningProcess {
+    fn new(child: Child, port: u16) -> Self {
+        Self {
+            child,
+            started_at: Instant::now(),
+            port,
+            healthy: true,
+        }
+    }
 }
 
 impl Supervisor {
@@ -64,7 +111,13 @@ impl Supervisor {
         // Create shared state
         let running_processes = Arc::new(Mutex::new(HashMap::new()));
         let processes_clone = running_processes.clone();
+        let last_activity = Arc::new(Mutex::new(HashMap::new()));
+        let activity_clone = last_activity.clone();
         let pool_clone = db_pool.clone();
+        let mut rolling_restarts = RollingRestarts {
+            tx: tx.clone(),
+            apps: HashSet::new(),
+        };
 
         // Spawn the supervisor task
         tokio::spawn(async move {
@@ -96,10 +149,17 @@ impl Supervisor {
                                 }
                             },
                             SupervisorMessage::Restart(app_name) => {
-                                if let Err(e) = Self::handle_restart(&pool_clone, &processes_clone, &app_name).await {
+                                if let Err(e) = Self::handle_restart(&pool_clone, &processes_clone, &mut rolling_restarts, &app_name).await {
                                     error!("Failed to restart app '{}': {}", app_name, e);
                                 }
                             },
+                            SupervisorMessage::Restarted(app_name, result) => {
+                                rolling_restarts.apps.remove(&app_name);
+                                match result {
+                                    Ok(()) => info!("Restarted all instances of app '{}'", app_name),
+                                    Err(e) => error!("Failed to restart app '{}': {}", app_name, e),
+                                }
+                            }
                             SupervisorMessage::CheckHealth(app_name) => {
                                 if let Err(e) = Self::handle_health_check(&pool_clone, &processes_clone, &app_name).await {
                                     warn!("Health check failed for app '{}': {}", app_name, e);
@@ -110,10 +170,21 @@ impl Supervisor {
                                     error!("Failed to handle process exit for app '{}': {}", app_name, e);
                                 }
                             }
+                            SupervisorMessage::Scale(app_name) => {
+                                if let Err(e) = Self::handle_scale(&pool_clone, &processes_clone, &app_name).await {
+                                    error!("Failed to scale app '{}': {}", app_name, e);
+                                }
+                            }
+                            SupervisorMessage::Wake(app_name) => {
+                                if let Err(e) = Self::handle_wake(&pool_clone, &processes_clone, &app_name).await {
+                                    error!("Failed to wake app '{}': {}", app_name, e);
+                                }
+                            }
                         }
                     },
                     _ = health_check_interval.tick() => {
-                        Self::run_health_checks(&pool_clone, &processes_clone).await;
+                        Self::run_health_checks(&pool_clone, &processes_clone, &mut rolling_restarts).await;
+                        Self::put_idle_apps_to_sleep(&pool_clone, &processes_clone, &activity_clone).await;
                     }
                 }
             }
@@ -125,19 +196,24 @@ impl Supervisor {
             tx,
             db_pool,
             running_processes,
+            last_activity,
         })
     }
 
     #[instrument(skip(db_pool, processes))]
-    async fn restore_running_apps(
-        db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
-    ) -> Result<usize> {
+    async fn restore_running_apps(db_pool: &Pool<Sqlite>, processes: &ProcessMap) -> Result<usize> {
         let running_apps = db::apps::get_by_state(db_pool, AppState::Running).await?;
 
         let mut count = 0;
         for app in running_apps {
-            match Self::start_process(db_pool, processes, &app).await {
+            let result = Self::start_process(db_pool, processes, &app).await;
+            let entry = AuditEntry::new(SUPERVISOR_ACTOR, "start")
+                .with_app(&app.name)
+                .with_parameters(serde_json::json!({ "reason": "restore on startup" }))
+                .with_result(&result);
+            audit::record(db_pool, entry).await;
+
+            match result {
                 Ok(_) => {
                     info!("Restored app '{}' (PID: {:?})", app.name, app.process_id);
                     count += 1;
@@ -161,7 +237,7 @@ impl Supervisor {
     #[instrument(skip(db_pool, processes))]
     async fn handle_start(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
         app_name: &str,
     ) -> Result<()> {
         // Get app from database
@@ -184,21 +260,32 @@ impl Supervisor {
     #[instrument(skip(db_pool, processes))]
     async fn start_process(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
         app: &App,
     ) -> Result<()> {
         use crate::providers::cmd::CmdProvider;
 
         match app_command::start::execute(db_pool, &app.name, CmdProvider {}).await {
             Ok(handle) => {
-                let mut process_map = processes.lock().unwrap();
-                process_map.insert(
-                    app.name.clone(),
-                    RunningProcess {
-                        child: handle,
-                        started_at: Instant::now(),
-                    },
-                );
+                let ports = app_command::scale::ports(db_pool, app).await?;
+                let port = ports.first().copied().unwrap_or_default();
+                {
+                    let mut process_map = processes.lock().unwrap();
+                    process_map.insert(
+                        app.name.clone(),
+                        BTreeMap::from([(0, RunningProcess::new(handle, port))]),
+                    );
+                }
+
+                // Further instances are best effort, the app is up already
+                for (index, port) in ports.into_iter().enumerate().skip(1) {
+                    if let Err(e) = Self::start_instance(processes, app, index as u32, port).await {
+                        error!(
+                            "Failed to start instance {} of '{}': {}",
+                            index, app.name, e
+                        );
+                    }
+                }
                 Ok(())
             }
             Err(e) => {
@@ -208,76 +295,155 @@ impl Supervisor {
         }
     }
 
+    /// Start one more instance of a running app. The instance joins the
+    /// rotation once it accepts connections.
+    async fn start_instance(
+        processes: &ProcessMap,
+        app: &App,
+        index: u32,
+        port: u16,
+    ) -> Result<u32> {
+        use crate::providers::cmd::CmdProvider;
+
+        let child = app_command::start::start_instance(app, port, CmdProvider {}).await?;
+        let pid = child.id();
+        {
+            let mut running = RunningProcess::new(child, port);
+            running.healthy = false;
+            let mut process_map = processes.lock().unwrap();
+            process_map
+                .entry(app.name.clone())
+                .or_default()
+                .insert(index, running);
+        }
+
+        let processes = Arc::clone(processes);
+        let (app_name, host) = (app.name.clone(), app.host.clone());
+        let startup_timeout = Duration::from_secs(app.startup_timeout as u64);
+        tokio::spawn(async move {
+            if Self::wait_until_listening(&host, port, startup_timeout).await {
+                let mut process_map = processes.lock().unwrap();
+                if let Some(running) = process_map
+                    .get_mut(&app_name)
+                    .and_then(|instances| instances.get_mut(&index))
+                    .filter(|running| running.child.id() == pid)
+                {
+                    running.healthy = true;
+                }
+            }
+        });
+
+        Ok(pid)
+    }
+
+    /// Kill a process and wait for it to exit, returning its exit code. The
+    /// wait runs on the blocking pool, so a process slow to go away holds up
+    /// neither the runtime nor the supervisor for longer than `KILL_TIMEOUT`.
+    async fn kill_process(app_name: &str, mut child: Child) -> Result<Option<i64>> {
+        if let Err(e) = child.kill() {
+            error!("Failed to kill app '{}': {}", app_name, e);
+            return Err(anyhow!("Failed to kill app: {}", e));
+        }
+        info!(
+            "Sent kill signal to app '{}' (PID: {})",
+            app_name,
+            child.id()
+        );
+
+        let wait = tokio::task::spawn_blocking(move || child.wait());
+        match time::timeout(KILL_TIMEOUT, wait).await {
+            Ok(Ok(Ok(status))) => {
+                info!("App '{}' exited with status: {:?}", app_name, status);
+                Ok(status.code().map(|c| c as i64))
+            }
+            Ok(Ok(Err(e))) => {
+                error!("Failed to wait for app '{}' to exit: {}", app_name, e);
+                Err(anyhow!("Failed to wait for app to exit: {}", e))
+            }
+            Ok(Err(e)) => Err(anyhow!("Failed to wait for app to exit: {}", e)),
+            Err(_) => {
+                error!(
+                    "App '{}' did not exit within {}s of being killed",
+                    app_name,
+                    KILL_TIMEOUT.as_secs()
+                );
+                Err(anyhow!(
+                    "App did not exit within {}s of being killed",
+                    KILL_TIMEOUT.as_secs()
+                ))
+            }
+        }
+    }
+
     #[instrument(skip(db_pool, processes))]
     async fn handle_stop(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
+        app_name: &str,
+    ) -> Result<()> {
+        Self::stop_process(db_pool, processes, app_name, AppState::Stopped).await
+    }
+
+    /// Stop an app's process, leaving it in the `stopped` state. While
+    /// restarting, the app stays `Restarting` throughout so the proxy holds
+    /// requests instead of rejecting them.
+    #[instrument(skip(db_pool, processes))]
+    async fn stop_process(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
         app_name: &str,
+        stopped: AppState,
     ) -> Result<()> {
+        let stopping = match stopped {
+            AppState::Restarting => AppState::Restarting,
+            _ => AppState::Stopping,
+        };
+
         // Get app
         let app = db::apps::get_by_name(db_pool, app_name)
             .await?
             .ok_or_else(|| anyhow!("App '{}' not found", app_name))?;
 
-        // Get child process
-        let child_opt = {
+        // Get child processes
+        let instances_opt = {
             let mut process_map = processes.lock().unwrap();
             process_map.remove(app_name)
         };
 
-        // If we found a child process, try to stop it gracefully
-        if let Some(mut running) = child_opt {
-            info!("Stopping app '{}' (PID: {})", app_name, running.child.id());
+        // If we found child processes, stop every instance
+        if let Some(instances) = instances_opt {
+            info!(
+                "Stopping app '{}' ({} instance(s), PID: {:?})",
+                app_name,
+                instances.len(),
+                app.process_id
+            );
 
             // Update app state
             let mut app = app.clone();
-            app.state = AppState::Stopping;
+            app.state = stopping;
             app.updated_at = Utc::now();
             db::apps::save(db_pool, &app).await?;
 
-            // Try to terminate gracefully first
-            match running.child.kill() {
-                Ok(_) => {
-                    info!(
-                        "Sent kill signal to app '{}' (PID: {})",
-                        app_name,
-                        running.child.id()
-                    );
-
-                    // Try to wait for process to exit
-                    match running.child.wait() {
-                        Ok(status) => {
-                            info!("App '{}' exited with status: {:?}", app_name, status);
-
-                            // Update process history
-                            let exit_code = status.code().map(|c| c as i64);
-                            Self::update_process_history(
-                                db_pool,
-                                &app,
-                                exit_code,
-                                "Stopped by user",
-                            )
-                            .await?;
-
-                            // Update app state
-                            app.state = AppState::Stopped;
-                            app.process_id = None;
-                            app.last_exit_code = exit_code;
-                            app.last_exit_time = Some(Utc::now());
-                            app.updated_at = Utc::now();
-                            db::apps::save(db_pool, &app).await?;
-                        }
-                        Err(e) => {
-                            error!("Failed to wait for app '{}' to exit: {}", app_name, e);
-                            return Err(anyhow!("Failed to wait for app to exit: {}", e));
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to kill app '{}': {}", app_name, e);
-                    return Err(anyhow!("Failed to kill app: {}", e));
+            // The first instance's exit is the app's
+            let mut exit_code = None;
+            for (index, running) in instances {
+                let code = Self::kill_process(app_name, running.child).await?;
+                if index == 0 {
+                    exit_code = code;
                 }
             }
+
+            // Update process history
+            Self::update_process_history(db_pool, &app, exit_code, "Stopped by user").await?;
+
+            // Update app state
+            app.state = stopped;
+            app.process_id = None;
+            app.last_exit_code = exit_code;
+            app.last_exit_time = Some(Utc::now());
+            app.updated_at = Utc::now();
+            db::apps::save(db_pool, &app).await?;
         } else {
             // App was not in the running processes map, but marked as running in DB
             warn!(
@@ -287,7 +453,7 @@ impl Supervisor {
 
             // Update app state
             let mut app = app.clone();
-            app.state = AppState::Stopped;
+            app.state = stopped;
             app.process_id = None;
             app.updated_at = Utc::now();
             db::apps::save(db_pool, &app).await?;
@@ -298,32 +464,287 @@ impl Supervisor {
         Ok(())
     }
 
-    #[instrument(skip(db_pool, processes))]
+    #[instrument(skip(db_pool, processes, rolling_restarts))]
     async fn handle_restart(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
+        rolling_restarts: &mut RollingRestarts,
         app_name: &str,
     ) -> Result<()> {
+        if rolling_restarts.apps.contains(app_name) {
+            return Err(anyhow!("App '{}' is already restarting", app_name));
+        }
+
         // Stop app if running
         let app = db::apps::get_by_name(db_pool, app_name)
             .await?
             .ok_or_else(|| anyhow!("App '{}' not found", app_name))?;
 
+        // Apps with several instances restart them one at a time, so the
+        // others keep serving requests
+        let running_instances = processes
+            .lock()
+            .unwrap()
+            .get(app_name)
+            .map_or(0, |instances| instances.len());
+        if app.state == AppState::Running && running_instances > 1 {
+            rolling_restarts.start(db_pool, processes, app);
+            return Ok(());
+        }
+
         if app.state == AppState::Running {
-            Self::handle_stop(db_pool, processes, app_name).await?;
+            Self::stop_process(db_pool, processes, app_name, AppState::Restarting).await?;
         }
 
         // Wait a moment before starting
         time::sleep(Duration::from_secs(1)).await;
 
-        // Start app
-        Self::handle_start(db_pool, processes, app_name).await
+        // Start app, leaving it failed rather than restarting forever if it
+        // does not come back
+        let result = Self::handle_start(db_pool, processes, app_name).await;
+        if result.is_err() {
+            if let Some(mut app) = db::apps::get_by_name(db_pool, app_name).await? {
+                if app.state == AppState::Restarting {
+                    app.state = AppState::Failed;
+                    app.updated_at = Utc::now();
+                    db::apps::save(db_pool, &app).await?;
+                }
+            }
+        }
+        result
+    }
+
+    /// Restart each instance of an app in turn, waiting for one to accept
+    /// connections before moving on to the next
+    #[instrument(skip(db_pool, processes, app), fields(app = %app.name))]
+    async fn rolling_restart(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        app: &App,
+    ) -> Result<()> {
+        let indexes: Vec<u32> = processes
+            .lock()
+            .unwrap()
+            .get(&app.name)
+            .map(|instances| instances.keys().copied().collect())
+            .unwrap_or_default();
+
+        let startup_timeout = Duration::from_secs(app.startup_timeout as u64);
+        for index in indexes {
+            let port = Self::restart_instance(db_pool, processes, app, index).await?;
+            if !Self::wait_until_listening(&app.host, port, startup_timeout).await {
+                return Err(anyhow!(
+                    "Instance {} of app '{}' did not accept connections within {}s",
+                    index,
+                    app.name,
+                    app.startup_timeout
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace one instance of an app with a new process on the same port,
+    /// returning the port. The instance is out of rotation until the new
+    /// process accepts connections.
+    #[instrument(skip(db_pool, processes, app), fields(app = %app.name))]
+    async fn restart_instance(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        app: &App,
+        index: u32,
+    ) -> Result<u16> {
+        let old = processes
+            .lock()
+            .unwrap()
+            .get_mut(&app.name)
+            .and_then(|instances| instances.remove(&index));
+        let Some(old) = old else {
+            return Err(anyhow!(
+                "Instance {} of app '{}' is not running",
+                index,
+                app.name
+            ));
+        };
+
+        info!("Restarting instance {} of app '{}'", index, app.name);
+        Self::kill_process(&app.name, old.child).await?;
+        let pid = Self::start_instance(processes, app, index, old.port).await?;
+
+        if index == 0 {
+            if let Some(mut app) = db::apps::get_by_name(db_pool, &app.name).await? {
+                app.process_id = Some(pid);
+                app.updated_at = Utc::now();
+                db::apps::save(db_pool, &app).await?;
+            }
+        }
+
+        Ok(old.port)
+    }
+
+    /// Wait for a port to accept TCP connections
+    async fn wait_until_listening(host: &str, port: u16, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if tokio::net::TcpStream::connect((host, port)).await.is_ok() {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    fn set_instance_health(processes: &ProcessMap, app_name: &str, index: u32, healthy: bool) {
+        let mut process_map = processes.lock().unwrap();
+        if let Some(running) = process_map
+            .get_mut(app_name)
+            .and_then(|instances| instances.get_mut(&index))
+        {
+            running.healthy = healthy;
+        }
+    }
+
+    /// Start or stop instances of a running app to match its instance count
+    #[instrument(skip(db_pool, processes))]
+    async fn handle_scale(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        app_name: &str,
+    ) -> Result<()> {
+        let app = db::apps::get_by_name(db_pool, app_name)
+            .await?
+            .ok_or_else(|| anyhow!("App '{}' not found", app_name))?;
+        let ports = app_command::scale::ports(db_pool, &app).await?;
+
+        let (running, extra) = {
+            let mut process_map = processes.lock().unwrap();
+            let Some(instances) = process_map.get_mut(app_name) else {
+                return Ok(());
+            };
+            let extra = instances.split_off(&(ports.len() as u32));
+            (instances.keys().copied().collect::<Vec<u32>>(), extra)
+        };
+
+        for (index, process) in extra {
+            info!("Stopping instance {} of app '{}'", index, app_name);
+            Self::kill_process(app_name, process.child).await?;
+        }
+
+        for (index, port) in ports.into_iter().enumerate() {
+            let index = index as u32;
+            if !running.contains(&index) {
+                Self::start_instance(processes, &app, index, port).await?;
+            }
+        }
+
+        info!("App '{}' runs {} instance(s)", app_name, app.instances);
+
+        Ok(())
+    }
+
+    /// Start a sleeping app for the request that is waiting on it. Apps that
+    /// are no longer sleeping, e.g. woken by an earlier request, are left alone.
+    #[instrument(skip(db_pool, processes))]
+    async fn handle_wake(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        app_name: &str,
+    ) -> Result<()> {
+        let app = db::apps::get_by_name(db_pool, app_name)
+            .await?
+            .ok_or_else(|| anyhow!("App '{}' not found", app_name))?;
+        if app.state != AppState::Sleeping {
+            return Ok(());
+        }
+
+        info!("Waking app '{}' for an incoming request", app_name);
+        let result = Self::start_process(db_pool, processes, &app).await;
+        let entry = AuditEntry::new(SUPERVISOR_ACTOR, "wake")
+            .with_app(app_name)
+            .with_parameters(serde_json::json!({ "reason": "request" }))
+            .with_result(&result);
+        audit::record(db_pool, entry).await;
+
+        // Don't leave waiting requests holding on to an app that won't start
+        if result.is_err() {
+            if let Some(mut app) = db::apps::get_by_name(db_pool, app_name).await? {
+                app.state = AppState::Failed;
+                app.updated_at = Utc::now();
+                db::apps::save(db_pool, &app).await?;
+            }
+        }
+        result
+    }
+
+    /// Stop running apps that have an idle timeout and have had neither a
+    /// proxied request nor a (re)start for that long
+    async fn put_idle_apps_to_sleep(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        last_activity: &Arc<Mutex<HashMap<String, Instant>>>,
+    ) {
+        let apps = match db::apps::get_by_state(db_pool, AppState::Running).await {
+            Ok(apps) => apps,
+            Err(e) => {
+                error!("Failed to get running apps: {}", e);
+                return;
+            }
+        };
+
+        for app in apps {
+            let Some(idle_timeout) = app.idle_timeout else {
+                continue;
+            };
+            // Requests are kept from apps in maintenance, not missing
+            if app.maintenance.is_some() {
+                continue;
+            }
+            let since_start = {
+                let process_map = processes.lock().unwrap();
+                match process_map.get(&app.name).and_then(|i| i.values().next()) {
+                    Some(running) => running.started_at.elapsed(),
+                    None => continue,
+                }
+            };
+            let since_request = last_activity
+                .lock()
+                .unwrap()
+                .get(&app.name)
+                .map(|last| last.elapsed());
+            let idle_for = since_request.map_or(since_start, |d| d.min(since_start));
+            if idle_for < Duration::from_secs(idle_timeout as u64) {
+                continue;
+            }
+
+            info!(
+                "Putting app '{}' to sleep after {}s without requests",
+                app.name,
+                idle_for.as_secs()
+            );
+            let result =
+                Self::stop_process(db_pool, processes, &app.name, AppState::Sleeping).await;
+            let entry = AuditEntry::new(SUPERVISOR_ACTOR, "sleep")
+                .with_app(&app.name)
+                .with_parameters(serde_json::json!({
+                    "reason": "idle",
+                    "idle_timeout": idle_timeout,
+                }))
+                .with_result(&result);
+            audit::record(db_pool, entry).await;
+
+            if let Err(e) = result {
+                error!("Failed to put app '{}' to sleep: {}", app.name, e);
+            }
+        }
     }
 
     #[instrument(skip(db_pool, processes))]
     async fn handle_process_exit(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
         app_name: &str,
         exit_code: i64,
     ) -> Result<()> {
@@ -363,7 +784,17 @@ impl Supervisor {
                 );
                 app.state = AppState::Crashed;
                 db::apps::save(db_pool, &app).await?;
-                return Err(anyhow!("App reached maximum restart count"));
+
+                let result = Err::<(), _>(anyhow!("App reached maximum restart count"));
+                let entry = AuditEntry::new(SUPERVISOR_ACTOR, "restart")
+                    .with_app(app_name)
+                    .with_parameters(serde_json::json!({
+                        "reason": "process exited",
+                        "exit_code": exit_code,
+                    }))
+                    .with_result(&result);
+                audit::record(db_pool, entry).await;
+                return result;
             }
 
             info!(
@@ -379,7 +810,17 @@ impl Supervisor {
             time::sleep(Duration::from_secs(backoff)).await;
 
             // Start the process again
-            return Self::start_process(db_pool, processes, &app).await;
+            let result = Self::start_process(db_pool, processes, &app).await;
+            let entry = AuditEntry::new(SUPERVISOR_ACTOR, "restart")
+                .with_app(app_name)
+                .with_parameters(serde_json::json!({
+                    "reason": "process exited",
+                    "exit_code": exit_code,
+                    "restart_count": app.restart_count,
+                }))
+                .with_result(&result);
+            audit::record(db_pool, entry).await;
+            return result;
         } else {
             // App should not be restarted
             if exit_code == 0 {
@@ -416,7 +857,7 @@ impl Supervisor {
     #[instrument(skip(db_pool, processes))]
     async fn handle_health_check(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
         app_name: &str,
     ) -> Result<()> {
         // Get app
@@ -436,14 +877,38 @@ impl Supervisor {
         };
 
         // Check if process exists
-        {
+        let instances: Vec<(u32, u16)> = {
             let process_map = processes.lock().unwrap();
-            if !process_map.contains_key(app_name) {
-                return Err(anyhow!("App '{}' not found in process map", app_name));
+            match process_map.get(app_name) {
+                Some(instances) => instances
+                    .iter()
+                    .map(|(index, running)| (*index, running.port))
+                    .collect(),
+                None => return Err(anyhow!("App '{}' not found in process map", app_name)),
+            }
+        };
+
+        // Check every instance, taking failing ones out of rotation
+        let mut failure = None;
+        for (index, port) in instances {
+            let result = Self::check_instance_health(&app.with_port(port), health_check).await;
+            Self::set_instance_health(processes, app_name, index, result.is_ok());
+            if let Err(e) = result {
+                failure.get_or_insert(e);
             }
         }
 
-        // Perform health check
+        match failure {
+            None => {
+                info!("Health check passed for app '{}'", app_name);
+                Ok(())
+            }
+            Some(e) => Err(e),
+        }
+    }
+
+    /// Run an app's health check against one of its instances
+    async fn check_instance_health(app: &App, health_check: &HealthCheck) -> Result<()> {
         match &health_check.check_type {
             HealthCheckType::HttpGet {
                 path,
@@ -460,20 +925,32 @@ impl Supervisor {
                 match client.get(&url).send().await {
                     Ok(response) => {
                         if response.status().as_u16() == *expected_status {
-                            info!("Health check passed for app '{}'", app_name);
-                            return Ok(());
+                            Ok(())
                         } else {
-                            return Err(anyhow!(
+                            Err(anyhow!(
                                 "Health check failed for app '{}': expected status {}, got {}",
-                                app_name,
+                                app.name,
                                 expected_status,
                                 response.status()
-                            ));
+                            ))
                         }
                     }
-                    Err(e) => {
-                        return Err(anyhow!("Health check failed for app '{}': {}", app_name, e));
-                    }
+                    Err(e) => Err(anyhow!("Health check failed for app '{}': {}", app.name, e)),
+                }
+            }
+            HealthCheckType::TcpConnect => {
+                let port = app
+                    .port
+                    .ok_or_else(|| anyhow!("App '{}' has no port", app.name))?;
+                let connect = tokio::net::TcpStream::connect((app.host.as_str(), port));
+                match time::timeout(Duration::from_secs(health_check.timeout as u64), connect).await
+                {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err(e)) => Err(anyhow!("Health check failed for app '{}': {}", app.name, e)),
+                    Err(_) => Err(anyhow!(
+                        "Health check failed for app '{}': connection timed out",
+                        app.name
+                    )),
                 }
             }
         }
@@ -481,14 +958,16 @@ impl Supervisor {
 
     async fn run_health_checks(
         db_pool: &Pool<Sqlite>,
-        processes: &Arc<Mutex<HashMap<String, RunningProcess>>>,
+        processes: &ProcessMap,
+        rolling_restarts: &mut RollingRestarts,
     ) {
         // Get all running apps
         match db::apps::get_by_state(db_pool, AppState::Running).await {
             Ok(apps) => {
                 for app in apps {
-                    // Skip apps without health checks
-                    if app.health_check.is_none() {
+                    // Skip apps without health checks, and those with
+                    // instances restarting already
+                    if app.health_check.is_none() || rolling_restarts.apps.contains(&app.name) {
                         continue;
                     }
 
@@ -500,8 +979,38 @@ impl Supervisor {
                         Err(e) => {
                             error!("Health check failed for app '{}': {}", app.name, e);
 
+                            // Restart just the failing instances while others
+                            // are healthy, otherwise the whole app
+                            let (unhealthy, total) =
+                                Self::unhealthy_instances(processes, &app.name);
+                            if !unhealthy.is_empty() && unhealthy.len() < total {
+                                for index in unhealthy {
+                                    Self::restart_unhealthy_instance(
+                                        db_pool, processes, &app, index, &e,
+                                    )
+                                    .await;
+                                }
+                                continue;
+                            }
+
                             // Try to restart the app
-                            match Self::handle_restart(db_pool, processes, &app.name).await {
+                            let result = Self::handle_restart(
+                                db_pool,
+                                processes,
+                                rolling_restarts,
+                                &app.name,
+                            )
+                            .await;
+                            let entry = AuditEntry::new(SUPERVISOR_ACTOR, "restart")
+                                .with_app(&app.name)
+                                .with_parameters(serde_json::json!({
+                                    "reason": "failed health check",
+                                    "error": e.to_string(),
+                                }))
+                                .with_result(&result);
+                            audit::record(db_pool, entry).await;
+
+                            match result {
                                 Ok(_) => {
                                     info!("Restarted app '{}' after failed health check", app.name)
                                 }
@@ -517,6 +1026,53 @@ impl Supervisor {
         }
     }
 
+    /// Indexes of an app's instances that failed their last health check,
+    /// and the number of instances
+    fn unhealthy_instances(processes: &ProcessMap, app_name: &str) -> (Vec<u32>, usize) {
+        let process_map = processes.lock().unwrap();
+        match process_map.get(app_name) {
+            Some(instances) => (
+                instances
+                    .iter()
+                    .filter(|(_, running)| !running.healthy)
+                    .map(|(index, _)| *index)
+                    .collect(),
+                instances.len(),
+            ),
+            None => (vec![], 0),
+        }
+    }
+
+    async fn restart_unhealthy_instance(
+        db_pool: &Pool<Sqlite>,
+        processes: &ProcessMap,
+        app: &App,
+        index: u32,
+        error: &anyhow::Error,
+    ) {
+        let result = Self::restart_instance(db_pool, processes, app, index).await;
+        let entry = AuditEntry::new(SUPERVISOR_ACTOR, "restart")
+            .with_app(&app.name)
+            .with_parameters(serde_json::json!({
+                "reason": "failed health check",
+                "instance": index,
+                "error": error.to_string(),
+            }))
+            .with_result(&result);
+        audit::record(db_pool, entry).await;
+
+        match result {
+            Ok(_) => info!(
+                "Restarted instance {} of app '{}' after failed health check",
+                index, app.name
+            ),
+            Err(e) => error!(
+                "Failed to restart instance {} of app '{}': {}",
+                index, app.name, e
+            ),
+        }
+    }
+
     // External API
 
     pub async fn start_app(&self, app_name: &str) -> Result<()> {
@@ -540,6 +1096,61 @@ impl Supervisor {
             .map_err(|e| anyhow!("Failed to send restart message: {}", e))
     }
 
+    /// Start or stop instances of a running app to match its instance count
+    pub async fn scale_app(&self, app_name: &str) -> Result<()> {
+        self.tx
+            .send(SupervisorMessage::Scale(app_name.to_string()))
+            .await
+            .map_err(|e| anyhow!("Failed to send scale message: {}", e))
+    }
+
+    /// Watch over the first instance of an app started outside the
+    /// supervisor and start the app's other instances alongside it
+    pub async fn adopt_process(&self, app: &App, child: Child) -> Result<()> {
+        let port = app.port.unwrap_or_default();
+        self.running_processes.lock().unwrap().insert(
+            app.name.clone(),
+            BTreeMap::from([(0, RunningProcess::new(child, port))]),
+        );
+        self.scale_app(&app.name).await
+    }
+
+    /// Ports of an app's instances the proxy should use: the healthy ones,
+    /// or all of them if none are healthy. `None` if the app has no running
+    /// processes.
+    pub fn instance_ports(&self, app_name: &str) -> Option<Vec<u16>> {
+        let processes = self.running_processes.lock().unwrap();
+        let instances = processes.get(app_name)?;
+        let healthy: Vec<u16> = instances
+            .values()
+            .filter(|running| running.healthy)
+            .map(|running| running.port)
+            .collect();
+        if healthy.is_empty() {
+            return Some(instances.values().map(|running| running.port).collect());
+        }
+        Some(healthy)
+    }
+
+    /// Start an app that is sleeping, if it still is
+    pub async fn wake_app(&self, app_name: &str) -> Result<()> {
+        self.tx
+            .send(SupervisorMessage::Wake(app_name.to_string()))
+            .await
+            .map_err(|e| anyhow!("Failed to send wake message: {}", e))
+    }
+
+    /// Note that a request was proxied to an app, keeping it awake
+    pub fn record_activity(&self, app_name: &str) {
+        let mut activity = self.last_activity.lock().unwrap();
+        match activity.get_mut(app_name) {
+            Some(last) => *last = Instant::now(),
+            None => {
+                activity.insert(app_name.to_string(), Instant::now());
+            }
+        }
+    }
+
     pub async fn check_app_health(&self, app_name: &str) -> Result<()> {
         self.tx
             .send(SupervisorMessage::CheckHealth(app_name.to_string()))
@@ -572,7 +1183,10 @@ impl Supervisor {
         // Check if app is running
         let uptime = {
             let processes = self.running_processes.lock().unwrap();
-            processes.get(app_name).map(|p| p.started_at.elapsed())
+            processes
+                .get(app_name)
+                .and_then(|instances| instances.get(&0))
+                .map(|p| p.started_at.elapsed())
         };
 
         // Get process history